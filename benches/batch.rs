@@ -0,0 +1,167 @@
+// `cargo bench --bench batch`, as root: single frame versus batched send/recv
+// over a veth pair in a throwaway network namespace.
+extern crate znet;
+
+#[cfg(target_os = "linux")]
+use znet::interface::link::{self, LinkBuilder};
+#[cfg(target_os = "linux")]
+use znet::netns::NetNs;
+#[cfg(target_os = "linux")]
+use znet::raw_socket::{BufferReader, PacketBuf, RawSocket};
+
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::time::{Duration, Instant};
+
+
+#[cfg(target_os = "linux")]
+const FRAMES: usize = 100_000;
+#[cfg(target_os = "linux")]
+const BATCH_SIZE: usize = 32;
+
+// Broadcast frame with the IEEE 802 local experimental EtherType (0x88B5).
+#[cfg(target_os = "linux")]
+fn make_frame() -> Vec<u8> {
+    let mut frame = vec![0u8; 64];
+    frame[..6].copy_from_slice(&[0xff; 6]);
+    frame[6..12].copy_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    frame[12] = 0x88;
+    frame[13] = 0xb5;
+    frame
+}
+
+// The kernel sends a few frames of its own when the link comes up.
+#[cfg(target_os = "linux")]
+fn is_bench_frame(frame: &[u8]) -> bool {
+    frame.len() >= 14 && frame[12..14] == [0x88, 0xb5]
+}
+
+#[cfg(target_os = "linux")]
+fn report(name: &str, frames: usize, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    println!("{:<12} {:>8} frames in {:>8.3}s  {:>12.0} frames/s", name, frames, secs, frames as f64 / secs);
+}
+
+// Frames are sent BATCH_SIZE at a time and the receiver drained after each round,
+// so that the socket buffer does not overflow and both sides count frames.
+#[cfg(target_os = "linux")]
+struct Bench {
+    sender: RawSocket,
+    receiver: RawSocket,
+    sent: usize,
+    received: usize,
+    send_time: Duration,
+    recv_time: Duration,
+}
+
+#[cfg(target_os = "linux")]
+impl Bench {
+    fn new(sender: RawSocket, receiver: RawSocket) -> Bench {
+        Bench { sender, receiver, sent: 0, received: 0, send_time: Duration::default(), recv_time: Duration::default() }
+    }
+
+    fn reset(&mut self) {
+        self.sent = 0;
+        self.received = 0;
+        self.send_time = Duration::default();
+        self.recv_time = Duration::default();
+    }
+
+    fn single(&mut self, frame: &[u8]) -> io::Result<()> {
+        let mut buffer = vec![0u8; self.receiver.blen()];
+        while self.sent < FRAMES {
+            let now = Instant::now();
+            for _ in 0..BATCH_SIZE {
+                match self.sender.send(frame) {
+                    Ok(_) => self.sent += 1,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.send_time += now.elapsed();
+
+            let now = Instant::now();
+            loop {
+                match self.receiver.recv(&mut buffer) {
+                    Ok(len) => {
+                        self.received += BufferReader::new(&buffer, len)
+                            .filter(|&(start, end)| is_bench_frame(&buffer[start..end]))
+                            .count();
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.recv_time += now.elapsed();
+        }
+        Ok(())
+    }
+
+    fn batch(&mut self, frame: &[u8]) -> io::Result<()> {
+        let frames = vec![frame; BATCH_SIZE];
+        let mut bufs = vec![PacketBuf::new(self.receiver.blen()); BATCH_SIZE];
+        while self.sent < FRAMES {
+            let now = Instant::now();
+            match self.sender.send_batch(&frames) {
+                Ok(n) => self.sent += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                Err(e) => return Err(e),
+            }
+            self.send_time += now.elapsed();
+
+            let now = Instant::now();
+            loop {
+                match self.receiver.recv_batch(&mut bufs) {
+                    Ok(0) => break,
+                    Ok(n) => self.received += bufs[..n].iter().filter(|buf| is_bench_frame(buf.as_slice())).count(),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.recv_time += now.elapsed();
+        }
+        Ok(())
+    }
+
+    fn report(&self, prefix: &str) {
+        report(&format!("{}send", prefix), self.sent, self.send_time);
+        report(&format!("{}recv", prefix), self.received, self.recv_time);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    if unsafe { libc::geteuid() } != 0 {
+        println!("batch: skipped, needs root to create a network namespace");
+        return;
+    }
+
+    let name = format!("znet-bench-{}", std::process::id());
+    let netns = NetNs::create(&name).unwrap();
+    let (sender, receiver) = netns.run(|| -> io::Result<(RawSocket, RawSocket)> {
+        let veth = LinkBuilder::veth("bench0", "bench1").up().create()?;
+        let peer = znet::interface::Interface::with_name("bench1")?;
+        link::set_up(peer.index(), true)?;
+        let sender = RawSocket::with_ifname(&veth.name())?;
+        let receiver = RawSocket::with_ifname(&peer.name())?;
+        Ok((sender, receiver))
+    }).unwrap().unwrap();
+
+    let frame = make_frame();
+    let mut bench = Bench::new(sender, receiver);
+    bench.single(&frame).unwrap();
+    bench.report("");
+    bench.reset();
+    bench.batch(&frame).unwrap();
+    bench.report("batch_");
+
+    drop(bench);
+    drop(netns);
+    NetNs::delete(&name).unwrap();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    println!("batch: skipped, the veth pair it runs over is Linux only");
+}
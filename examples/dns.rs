@@ -1,10 +1,12 @@
 extern crate znet;
 
+#[cfg(target_os = "macos")]
 use std::net::IpAddr;
 
 
+#[cfg(target_os = "macos")]
 fn main (){
-    let network_global = znet::dns::get_network_global();
+    let network_global = znet::dns::get_network_global().unwrap();
     println!("NetworkGlobal: {:?}", network_global);
     
     // 设置全局 DNS, 需要 Root 权限
@@ -22,4 +24,10 @@ fn main (){
     
     println!("Set DNS: {:?}", network_global.service.set_dns(&[dns_ip]));
     println!("{:?}", network_global.service.dns());
-}
\ No newline at end of file
+}
+#[cfg(target_os = "linux")]
+fn main() {
+    let resolv_conf = znet::dns::ResolvConf::new();
+    println!("{}: {:?}", resolv_conf.path().display(), resolv_conf.manager());
+    println!("{:?}", resolv_conf.read());
+}
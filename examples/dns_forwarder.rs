@@ -0,0 +1,29 @@
+extern crate znet;
+
+use znet::dns::Forwarder;
+
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+
+// $ target/debug/examples/dns_forwarder 127.0.0.1:5353 corp.example=10.8.0.1:53
+// $ dig @127.0.0.1 -p 5353 intranet.corp.example
+fn main() {
+    let mut args = env::args().skip(1);
+    let listen = args.next().unwrap_or("127.0.0.1:5353".to_string());
+
+    let mut forwarder = Forwarder::system().unwrap().listen(listen.parse().unwrap());
+    for route in args {
+        let mut parts = route.splitn(2, '=');
+        let domain = parts.next().unwrap().parse().unwrap();
+        let server = parts.next().expect("domain=server:port").parse().unwrap();
+        forwarder = forwarder.route(domain, vec![server]);
+    }
+
+    let handle = forwarder.spawn().unwrap();
+    println!("Listening on {:?}", handle.local_addrs());
+    loop {
+        thread::sleep(Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,32 @@
+extern crate znet;
+extern crate smoltcp;
+
+use znet::dns::LeakDetector;
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+
+// $ sudo target/debug/examples/dns_leaks wg0
+// $ sudo target/debug/examples/dns_leaks wg0 10.8.0.1:53
+fn main() {
+    let mut args = env::args().skip(1);
+    let tunnel = args.next().expect("tunnel interface");
+
+    let mut detector = LeakDetector::new(&tunnel);
+    if let Some(resolver) = args.next() {
+        let resolver: SocketAddr = resolver.parse().unwrap();
+        detector = detector.redirect(IpEndpoint::new(IpAddress::from(resolver.ip()), resolver.port()));
+    }
+
+    let mut monitor = detector.start().unwrap();
+    println!("Watching for DNS leaving outside of {} (redirecting: {})", tunnel, monitor.is_redirecting());
+    loop {
+        match monitor.next_leak(Some(Duration::from_secs(10))).unwrap() {
+            Some(leak) => println!("[LEAK] {}", leak),
+            None => println!("{}", monitor.stats()),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+extern crate znet;
+
+use znet::dns::hosts::HostsFile;
+
+use std::env;
+
+
+// $ target/debug/examples/hosts localhost intranet.corp.example
+// $ HOSTS_ROOT=/tmp/root target/debug/examples/hosts localhost
+fn main() {
+    let file = match env::var("HOSTS_ROOT") {
+        Ok(root) => HostsFile::with_root(root),
+        Err(_) => HostsFile::new(),
+    };
+
+    let hosts = file.read().unwrap();
+    println!("{}: {} entries, znet blocks {:?}", file.path().display(), hosts.entries().len(), file.tags().unwrap());
+    for name in env::args().skip(1) {
+        println!("{}: {:?}", name, hosts.lookup(&name));
+    }
+}
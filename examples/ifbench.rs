@@ -0,0 +1,42 @@
+extern crate znet;
+
+use std::env;
+use std::time::Instant;
+
+
+// ip link add vethA type veth peer name vethB, a few thousand times, makes the difference show.
+fn main(){
+    let rounds = env::args().nth(1).map(|n| n.parse::<u32>().unwrap()).unwrap_or(100);
+
+    let start = Instant::now();
+    let mut count = 0;
+    for _ in 0..rounds {
+        count = znet::interface::interfaces().len();
+    }
+    let elapsed = start.elapsed();
+    println!("interfaces()                 {} interfaces, {:?} per call", count, elapsed / rounds);
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        count = znet::interface::interfaces_from_getifaddrs().len();
+    }
+    let elapsed = start.elapsed();
+    println!("interfaces_from_getifaddrs() {} interfaces, {:?} per call", count, elapsed / rounds);
+
+    // getifaddrs knows nothing about address flags and lifetimes, compare the rest.
+    let names_and_addrs = |ifaces: Vec<znet::interface::Interface>| {
+        ifaces.into_iter()
+              .map(|iface| (iface.name(), iface.index(), iface.mtu(), iface.hwaddr(), iface.addrs().clone()))
+              .collect::<Vec<_>>()
+    };
+    if names_and_addrs(znet::interface::interfaces()) != names_and_addrs(znet::interface::interfaces_from_getifaddrs()) {
+        println!("warning: the two enumerations disagree");
+    }
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        count = znet::interface::interfaces_filtered(|iface| !iface.is_loopback()).len();
+    }
+    let elapsed = start.elapsed();
+    println!("interfaces_filtered()        {} interfaces, {:?} per call", count, elapsed / rounds);
+}
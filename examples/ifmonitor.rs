@@ -0,0 +1,16 @@
+extern crate znet;
+
+use znet::interface::Monitor;
+
+
+// ip link add dummy0 type dummy && ip addr add 10.9.0.1/24 dev dummy0 && ip link set dummy0 up
+fn main(){
+    let monitor = Monitor::new().unwrap();
+    for iface in monitor.interfaces() {
+        println!("{}", iface.name());
+    }
+
+    for event in monitor {
+        println!("{}", event.unwrap());
+    }
+}
@@ -0,0 +1,24 @@
+extern crate znet;
+
+use znet::interface::{Interface, StatsSampler};
+
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+fn main(){
+    let mut args = env::args();
+    let _ = args.next().unwrap();
+
+    let ifname = args.next().unwrap_or("lo".to_string());
+    let iface = Interface::with_name(&ifname).unwrap();
+    println!("{}\n{}\n", iface.name(), iface.stats().unwrap());
+
+    let mut sampler = StatsSampler::new(&ifname);
+    loop {
+        if let Some(rates) = sampler.sample().unwrap() {
+            println!("{}", rates);
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
@@ -9,6 +9,9 @@ fn main(){
     if let Some(ifname) = args.next() {
         let iface = znet::interface::Interface::with_name(&ifname).unwrap();
         println!("{}", iface);
+        println!("    kind {}", iface.kind());
+        #[cfg(target_os = "linux")]
+        println!("    {:?}", iface.link_info().unwrap());
     } else {
         let ifaces = znet::interface::interfaces();
         for x in ifaces{
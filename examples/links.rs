@@ -0,0 +1,63 @@
+extern crate znet;
+extern crate smoltcp;
+
+use znet::interface::Interface;
+use znet::interface::TunnelParams;
+use znet::interface::link::{self, LinkBuilder, MacvlanMode};
+
+use smoltcp::wire::{IpAddress, Ipv4Address, Ipv6Address};
+
+
+// A bridge with a veth pair and a macvlan, torn down again. Needs CAP_NET_ADMIN.
+fn main(){
+    let br = LinkBuilder::bridge("znbr0").up().create().unwrap();
+    println!("{}\n    kind {}\n", br, br.kind());
+
+    match LinkBuilder::veth("znveth0", "znveth1").master(br.index()).mtu(1400).up().create() {
+        Ok(veth) => {
+            println!("{}\n    kind {}\n", veth, veth.kind());
+            println!("ports of {}: {:?}", br.name(), br.slaves().unwrap().iter().map(|port| port.name()).collect::<Vec<_>>());
+            link::release(veth.index()).unwrap();
+            link::delete(veth.index()).unwrap();
+        },
+        Err(e) => println!("veth: {}", e),
+    }
+
+    let uplink = znet::interface::interfaces_filtered(|iface| !iface.kind().is_virtual());
+    if let Some(parent) = uplink.first() {
+        match LinkBuilder::macvlan("znmv0", parent.index(), MacvlanMode::Bridge).create() {
+            Ok(macvlan) => {
+                println!("{}\n    kind {}\n", macvlan, macvlan.kind());
+                link::delete(macvlan.index()).unwrap();
+            },
+            Err(e) => println!("macvlan: {}", e),
+        }
+    }
+
+    let local4 = Ipv4Address::new(192, 0, 2, 2);
+    let remote4 = Ipv4Address::new(198, 51, 100, 1);
+    let local6 = Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+    let remote6 = Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 0x99);
+    let tunnels = vec![
+        ("zngre0", TunnelParams::gre(IpAddress::Ipv4(local4), IpAddress::Ipv4(remote4)).key(42).ttl(64)),
+        ("zngtap0", TunnelParams::gretap(IpAddress::Ipv4(local4), IpAddress::Ipv4(remote4))),
+        ("znipip0", TunnelParams::ipip(local4, remote4)),
+        ("znsit0", TunnelParams::sit(local4, remote4)),
+        ("znip6t0", TunnelParams::ip6tnl(local6, remote6)),
+        ("znvx0", TunnelParams::vxlan(100).remote(IpAddress::Ipv4(remote4)).local(IpAddress::Ipv4(local4))),
+    ];
+    for (name, params) in tunnels {
+        match LinkBuilder::tunnel(name, params).create() {
+            Ok(tunnel) => {
+                println!("{}: {} ({})", name, tunnel.tunnel_params().unwrap().unwrap(), tunnel.kind());
+                link::delete(tunnel.index()).unwrap();
+            },
+            Err(e) => println!("{}: {}", name, e),
+        }
+    }
+
+    link::set_mtu(br.index(), 1300).unwrap();
+    link::set_up(br.index(), false).unwrap();
+    println!("{}", Interface::with_index(br.index()).unwrap());
+    link::delete(br.index()).unwrap();
+}
@@ -0,0 +1,37 @@
+extern crate znet;
+
+use znet::netns::NetNs;
+use znet::raw_socket::RawSocket;
+use znet::interface::{self, Interface};
+use znet::interface::link::{self, LinkBuilder};
+
+
+// A namespace with one end of a veth in it, like `ip netns add` + `ip link add ... netns`.
+fn main(){
+    let ns = NetNs::create("znet-example").unwrap();
+    println!("namespaces: {:?}", NetNs::list().unwrap());
+
+    let veth = LinkBuilder::veth("znveth0", "znveth1").peer_netns((&ns).into()).create().unwrap();
+    let moved = LinkBuilder::veth("znveth2", "znveth3").create().unwrap();
+    ns.move_interface(moved.index()).unwrap();
+
+    for iface in interface::interfaces_in(Some(&ns)).unwrap() {
+        println!("{}", iface);
+    }
+
+    ns.run(|| link::set_up(Interface::with_name("lo").unwrap().index(), true)).unwrap().unwrap();
+    let raw_socket = RawSocket::with_ifname_in("lo", Some(&ns)).unwrap();
+    println!("\ncapturing on lo ({}) inside {}", raw_socket.link_layer(), ns.name().unwrap());
+
+    {
+        let _guard = ns.enter().unwrap();
+        println!("entered: {:?}", interface::interfaces().iter().map(|iface| iface.name()).collect::<Vec<_>>());
+    }
+    println!("left: {:?}", interface::interfaces().iter().map(|iface| iface.name()).collect::<Vec<_>>());
+
+    link::delete(veth.index()).unwrap();
+    ns.run(|| link::delete(Interface::with_name("znveth2").unwrap().index())).unwrap().unwrap();
+    drop(ns);
+    NetNs::delete("znet-example").unwrap();
+    println!("namespaces: {:?}", NetNs::list().unwrap());
+}
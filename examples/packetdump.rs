@@ -1,3 +1,4 @@
+extern crate libc;
 extern crate smoltcp;
 extern crate znet;
 
@@ -7,20 +8,73 @@ use smoltcp::wire;
 use znet::raw_socket::{LinkLayer, RawSocket, BufferReader};
 
 use std::env;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 
+static RUNNING: AtomicBool = AtomicBool::new(true);
+
+extern "C" fn handle_signal(_signum: libc::c_int) {
+    RUNNING.store(false, Ordering::SeqCst);
+}
+
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn handle_udp_payload(protocol: wire::IpProtocol, payload: &[u8]) {
+    use znet::dns;
+
+    if protocol != wire::IpProtocol::Udp {
+        return;
+    }
+    if let Ok(udp) = wire::UdpPacket::new_checked(payload) {
+        if udp.src_port() == dns::wire::PORT || udp.dst_port() == dns::wire::PORT {
+            println!("{}", &wire::PrettyPrinter::<dns::wire::Packet<&[u8]>>::new("    ", &udp.payload()));
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn handle_udp_payload(_protocol: wire::IpProtocol, _payload: &[u8]) { }
+
 fn handle_ip_packet(packet: &[u8]) {
-    match wire::IpVersion::of_packet(&packet) {
-        Ok(version) => match version {
-            wire::IpVersion::Ipv4 => {
-                println!("{}", &wire::PrettyPrinter::<wire::Ipv4Packet<&[u8]>>::new("", &packet));
-            },
-            wire::IpVersion::Ipv6 => {
-                println!("{}", &wire::PrettyPrinter::<wire::Ipv6Packet<&[u8]>>::new("", &packet));
-            },
-            _ => { }
+    match wire::IpVersion::of_packet(packet) {
+        Ok(wire::IpVersion::Ipv4) => {
+            println!("{}", &wire::PrettyPrinter::<wire::Ipv4Packet<&[u8]>>::new("", &packet));
+            if let Ok(ipv4) = wire::Ipv4Packet::new_checked(packet) {
+                handle_udp_payload(ipv4.protocol(), ipv4.payload());
+            }
         },
-        Err(_) => { }
+        Ok(wire::IpVersion::Ipv6) => {
+            println!("{}", &wire::PrettyPrinter::<wire::Ipv6Packet<&[u8]>>::new("", &packet));
+            if let Ok(ipv6) = wire::Ipv6Packet::new_checked(packet) {
+                handle_udp_payload(ipv6.next_header(), ipv6.payload());
+            }
+        },
+        _ => { }
+    }
+}
+
+// https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL2.html
+fn handle_sll2_frame(packet: &[u8]) {
+    if packet.len() < 20 {
+        return;
+    }
+
+    let protocol = u16::from_be_bytes([packet[0], packet[1]]);
+    let ifindex = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+    let pkttype = match packet[10] {
+        0 => "host",
+        1 => "broadcast",
+        2 => "multicast",
+        3 => "otherhost",
+        4 => "outgoing",
+        _ => "unknown",
+    };
+    println!("LinuxSll2 ifindex={} pkttype={} protocol=0x{:04x}", ifindex, pkttype, protocol);
+
+    match protocol {
+        0x0800 | 0x86dd => handle_ip_packet(&packet[20..]),
+        _ => { }
     }
 }
 
@@ -33,42 +87,69 @@ fn main() {
     let mut args = env::args();
     if args.len() < 2 {
         println!("Usage:\n    $ sudo target/debug/examples/packetdump <interface name>");
-        return ();
+        return;
     }
 
     let ifname = args.nth(1).unwrap().clone();
 
-    let mut raw_socket = RawSocket::with_ifname(&ifname).unwrap();
+    let mut raw_socket = if ifname == "any" {
+        RawSocket::any().unwrap()
+    } else {
+        RawSocket::with_ifname(&ifname).unwrap()
+    };
     let mut buffer = vec![0u8; raw_socket.blen()];
 
     let link_layer = raw_socket.link_layer();
     println!("Interface:\n\tname: {}\n\tdatalink: {}\n", ifname, link_layer);
 
-    loop {
-        raw_socket.wait(None).unwrap();
+    unsafe {
+        libc::signal(libc::SIGINT, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+
+    while RUNNING.load(Ordering::SeqCst) {
+        if let Err(e) = raw_socket.wait(None) {
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            panic!("{:?}", e);
+        }
+
         match raw_socket.recv(&mut buffer) {
             Ok(len) => {
                 for (start, end) in BufferReader::new(&buffer, len) {
                     match link_layer {
                         LinkLayer::IpWithPI(prefix_len) => {
                             let packet = &buffer[start+prefix_len..end];
-                            handle_ip_packet(&packet);
+                            handle_ip_packet(packet);
                         },
                         LinkLayer::Eth => {
                             let packet = &buffer[start..end];
-                            handle_ethernet_frame(&packet);
+                            handle_ethernet_frame(packet);
+                        },
+                        LinkLayer::LinuxSll2 => {
+                            let packet = &buffer[start..end];
+                            handle_sll2_frame(packet);
                         },
-                        LinkLayer::Ip => {
+                        link_layer if link_layer.is_ip() => {
                             let packet = &buffer[start..end];
-                            handle_ip_packet(&packet);
+                            handle_ip_packet(packet);
+                        },
+                        _ => {
+                            println!("{} frame, {} bytes", link_layer, end - start);
                         }
                     }
                 }
             }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => { },
             Err(e) => {
                 println!("[ERROR] {:?}", e);
             }
         }
     }
-}
 
+    match raw_socket.stats() {
+        Ok(stats) => println!("\n{}", stats),
+        Err(e) => println!("[ERROR] {:?}", e),
+    }
+}
@@ -1,9 +1,21 @@
 extern crate znet;
 
 
+#[cfg(target_os = "macos")]
 fn main() {
     for msg in znet::route::iter().unwrap() {
         println!("{:?}  -->  {:?}", msg.dest, msg.gateway);
         println!("{:?}", msg.hdr);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    for rule in znet::route::rule::list().unwrap() {
+        println!("{}", rule);
+    }
+    println!();
+    for route in znet::route::list(None).unwrap() {
+        println!("{}", route);
+    }
+}
@@ -0,0 +1,192 @@
+use super::Dns;
+
+use std::io;
+use std::net::IpAddr;
+
+
+/// What a VPN pushes: servers, domains to search and, for split DNS, the only
+/// domains to resolve through `servers`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DnsConfig {
+    pub servers: Vec<IpAddr>,
+    pub search_domains: Vec<String>,
+    // Empty for every query to go to `servers`
+    pub match_domains: Vec<String>,
+}
+
+impl DnsConfig {
+    pub fn new(servers: Vec<IpAddr>) -> DnsConfig {
+        DnsConfig { servers, search_domains: Vec::new(), match_domains: Vec::new() }
+    }
+
+    pub fn search_domain(mut self, domain: &str) -> DnsConfig {
+        self.search_domains.push(domain.to_string());
+        self
+    }
+
+    pub fn match_domain(mut self, domain: &str) -> DnsConfig {
+        self.match_domains.push(domain.to_string());
+        self
+    }
+}
+
+impl<'a> From<&'a Dns> for DnsConfig {
+    fn from(dns: &'a Dns) -> DnsConfig {
+        // resolv.conf(5) searches the `domain` when there is no `search`.
+        let mut search_domains = Vec::new();
+        for name in dns.domain_name().into_iter().chain(dns.search.iter().map(|name| name.as_str())) {
+            if !search_domains.iter().any(|domain: &String| domain == name) {
+                search_domains.push(name.to_string());
+            }
+        }
+        DnsConfig { servers: dns.addrs().to_vec(), search_domains, match_domains: Vec::new() }
+    }
+}
+
+
+/// Sets the DNS configuration of the system, whatever manages it.
+///
+/// `restore` puts back what was there before the first `apply`, the backends also
+/// do so when dropped.
+pub trait DnsConfigurator {
+    fn current(&self) -> Result<DnsConfig, io::Error>;
+    fn apply(&mut self, config: &DnsConfig) -> Result<(), io::Error>;
+    fn restore(&mut self) -> Result<(), io::Error>;
+}
+
+impl<T: DnsConfigurator + ?Sized> DnsConfigurator for Box<T> {
+    fn current(&self) -> Result<DnsConfig, io::Error> {
+        (**self).current()
+    }
+
+    fn apply(&mut self, config: &DnsConfig) -> Result<(), io::Error> {
+        (**self).apply(config)
+    }
+
+    fn restore(&mut self) -> Result<(), io::Error> {
+        (**self).restore()
+    }
+}
+
+
+/// An in-memory configurator for tests.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FakeConfigurator {
+    config: DnsConfig,
+    original: Option<DnsConfig>,
+    applied: Vec<DnsConfig>,
+}
+
+impl FakeConfigurator {
+    pub fn new(config: DnsConfig) -> FakeConfigurator {
+        FakeConfigurator { config, original: None, applied: Vec::new() }
+    }
+
+    /// Every configuration applied so far, first first.
+    pub fn applied(&self) -> &[DnsConfig] {
+        &self.applied
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.original.is_some()
+    }
+}
+
+impl DnsConfigurator for FakeConfigurator {
+    fn current(&self) -> Result<DnsConfig, io::Error> {
+        Ok(self.config.clone())
+    }
+
+    fn apply(&mut self, config: &DnsConfig) -> Result<(), io::Error> {
+        if self.original.is_none() {
+            self.original = Some(self.config.clone());
+        }
+        self.config = config.clone();
+        self.applied.push(config.clone());
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<(), io::Error> {
+        if let Some(original) = self.original.take() {
+            self.config = original;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn apply_restore() {
+        let original = DnsConfig::new(vec![addr("192.168.1.1")]).search_domain("lan");
+        let mut fake = FakeConfigurator::new(original.clone());
+        assert!(!fake.is_applied());
+
+        // Restoring what was never applied changes nothing.
+        fake.restore().unwrap();
+        assert_eq!(fake.current().unwrap(), original);
+
+        let first = DnsConfig::new(vec![addr("10.8.0.1")]).match_domain("corp.example");
+        let second = DnsConfig::new(vec![addr("10.8.0.2")]);
+        fake.apply(&first).unwrap();
+        fake.apply(&second).unwrap();
+        assert!(fake.is_applied());
+        assert_eq!(fake.current().unwrap(), second);
+        assert_eq!(fake.applied(), &[first.clone(), second.clone()]);
+
+        // The original is the one before the first apply, not the second.
+        fake.restore().unwrap();
+        assert!(!fake.is_applied());
+        assert_eq!(fake.current().unwrap(), original);
+
+        fake.restore().unwrap();
+        assert_eq!(fake.current().unwrap(), original);
+
+        // Through a box, as `configurator` returns.
+        let mut boxed: Box<dyn DnsConfigurator> = Box::new(fake);
+        boxed.apply(&first).unwrap();
+        assert_eq!(boxed.current().unwrap(), first);
+        boxed.restore().unwrap();
+        assert_eq!(boxed.current().unwrap(), original);
+    }
+
+    #[test]
+    fn from_dns() {
+        let dns = Dns {
+            default_domain_name: Some("lan".to_string()),
+            default_addrs: Some(vec![addr("192.168.1.1")]),
+            ..Dns::default()
+        };
+        let config = DnsConfig::from(&dns);
+        assert_eq!(config.servers, vec![addr("192.168.1.1")]);
+        assert_eq!(config.search_domains, vec!["lan".to_string()]);
+        assert!(config.match_domains.is_empty());
+
+        // The domain comes first and is not repeated by `search`.
+        let dns = Dns {
+            search: vec!["a.example".to_string(), "lan".to_string(), "a.example".to_string()],
+            ..dns
+        };
+        assert_eq!(DnsConfig::from(&dns).search_domains, vec!["lan".to_string(), "a.example".to_string()]);
+
+        // The manual domain and servers take precedence.
+        let dns = Dns {
+            manually_specifying_domain_name: Some("corp.example".to_string()),
+            manually_specifying_addrs: Some(vec![addr("10.8.0.1")]),
+            search: Vec::new(),
+            ..dns
+        };
+        let config = DnsConfig::from(&dns);
+        assert_eq!(config.servers, vec![addr("10.8.0.1")]);
+        assert_eq!(config.search_domains, vec!["corp.example".to_string()]);
+
+        assert_eq!(DnsConfig::from(&Dns::default()), DnsConfig::default());
+    }
+}
@@ -0,0 +1,892 @@
+use super::DnsConfig;
+use super::wire::{Edns, Flags, Name, Opcode, Packet, Question, Rcode, RecordData, Repr};
+
+use crate::poller::{Interest, Poller};
+
+use std::io;
+use std::thread;
+use std::io::{Read, Write};
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_ATTEMPTS: usize = 2;
+const DEFAULT_CACHE_SIZE: usize = 1024;
+// What we advertise, small enough to not fragment (DNS flag day 2020).
+const EDNS_UDP_SIZE: u16 = 1232;
+const MIN_UDP_SIZE: usize = 512;
+const MAX_TTL: u32 = 86400;
+const MAX_NEGATIVE_TTL: u32 = 300;
+// Queries being resolved at once, each has a thread.
+const MAX_UDP_IN_FLIGHT: usize = 256;
+// TCP connections served at once, apart so that idle ones cannot starve UDP.
+const MAX_TCP_IN_FLIGHT: usize = 64;
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn random_id() -> u16 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u32(elapsed.subsec_nanos());
+    }
+    hasher.finish() as u16
+}
+
+fn is_answer(query: &Repr, response: &Repr) -> bool {
+    response.id == query.id && response.flags.contains(Flags::RESPONSE) && response.questions == query.questions
+}
+
+fn read_message<R: Read>(stream: &mut R) -> Result<Vec<u8>, io::Error> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut message = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+fn write_message<W: Write>(stream: &mut W, message: &[u8]) -> Result<(), io::Error> {
+    let mut buffer = Vec::with_capacity(message.len() + 2);
+    buffer.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(message);
+    stream.write_all(&buffer)
+}
+
+fn exchange_udp(query: &Repr, server: SocketAddr, timeout: Duration) -> Result<Repr, io::Error> {
+    let any: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(any)?;
+    socket.connect(server)?;
+    socket.send(&query.to_bytes().map_err(invalid_data)?)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buffer = vec![0u8; u16::MAX as usize];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, format!("{} did not answer", server)));
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+        let len = match socket.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        };
+        // Anything else is late, for an earlier attempt, or spoofed.
+        let response = Packet::new_checked(&buffer[..len]).and_then(|packet| Repr::parse(&packet));
+        if let Ok(response) = response {
+            if is_answer(query, &response) {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+fn exchange_tcp(query: &Repr, server: SocketAddr, timeout: Duration) -> Result<Repr, io::Error> {
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    write_message(&mut stream, &query.to_bytes().map_err(invalid_data)?)?;
+
+    let message = read_message(&mut stream)?;
+    let response = Packet::new_checked(&message[..]).and_then(|packet| Repr::parse(&packet)).map_err(invalid_data)?;
+    if !is_answer(query, &response) {
+        return Err(invalid_data(format!("{} answered another query", server)));
+    }
+    Ok(response)
+}
+
+
+// A question and the flags the response depends on: DO asks for the DNSSEC
+// records, CD for the data a validating server would have refused.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CacheKey {
+    question: Question,
+    dnssec_ok: bool,
+    checking_disabled: bool,
+}
+
+impl CacheKey {
+    fn new(query: &Repr, question: &Question) -> CacheKey {
+        CacheKey {
+            question: question.clone(),
+            dnssec_ok: query.edns.as_ref().map(|edns| edns.dnssec_ok).unwrap_or(false),
+            checking_disabled: query.flags.contains(Flags::CHECKING_DISABLED),
+        }
+    }
+}
+
+// Responses by question, aged on the way out.
+#[derive(Debug)]
+struct Cache {
+    capacity: usize,
+    // (stored at, expires at, response)
+    entries: HashMap<CacheKey, (Instant, Instant, Repr)>,
+}
+
+impl Cache {
+    // The smallest TTL of the answers, or of the SOA for NXDOMAIN and empty answers (RFC 2308).
+    fn ttl(response: &Repr) -> Option<u32> {
+        if response.flags.contains(Flags::TRUNCATED) {
+            return None;
+        }
+        let ttl = match response.rcode {
+            Rcode::NoError if !response.answers.is_empty() => {
+                response.answers.iter().map(|record| record.ttl).min().map(|ttl| ttl.min(MAX_TTL))
+            },
+            Rcode::NoError | Rcode::NxDomain => {
+                response.authorities.iter().filter_map(|record| match record.data {
+                    RecordData::Soa { minimum, .. } => Some(record.ttl.min(minimum).min(MAX_NEGATIVE_TTL)),
+                    _ => None,
+                }).next()
+            },
+            _ => None,
+        };
+        ttl.and_then(|ttl| if ttl == 0 { None } else { Some(ttl) })
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Repr> {
+        let now = Instant::now();
+        let (stored, expires, mut response) = match self.entries.get(key) {
+            Some(&(stored, expires, ref response)) => (stored, expires, response.clone()),
+            None => return None,
+        };
+        if expires <= now {
+            self.entries.remove(key);
+            return None;
+        }
+
+        let age = (now - stored).as_secs() as u32;
+        for record in response.answers.iter_mut()
+                                      .chain(response.authorities.iter_mut())
+                                      .chain(response.additionals.iter_mut()) {
+            record.ttl = record.ttl.saturating_sub(age);
+        }
+        Some(response)
+    }
+
+    fn insert(&mut self, key: &CacheKey, response: &Repr) {
+        let ttl = match Cache::ttl(response) {
+            Some(ttl) if self.capacity > 0 => ttl,
+            _ => return,
+        };
+        let now = Instant::now();
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(key) {
+            self.entries.retain(|_, &mut (_, expires, _)| expires > now);
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(key) {
+            let soonest = self.entries.iter()
+                                      .min_by_key(|&(_, &(_, expires, _))| expires)
+                                      .map(|(key, _)| key.clone());
+            if let Some(soonest) = soonest {
+                self.entries.remove(&soonest);
+            }
+        }
+        self.entries.insert(key.clone(), (now, now + Duration::from_secs(ttl as u64), response.clone()));
+    }
+}
+
+
+/// A DNS forwarder for split DNS: queries for the routed domains go to their
+/// servers (the VPN resolvers), everything else to the upstream servers.
+///
+/// ```no_run
+/// use znet::dns::{DnsConfig, Forwarder};
+///
+/// let vpn = DnsConfig::new(vec!["10.8.0.1".parse().unwrap()]).match_domain("corp.example");
+/// let forwarder = Forwarder::system().unwrap()
+///                           .split(&vpn)
+///                           .listen("127.0.0.1:53".parse().unwrap())
+///                           .spawn()
+///                           .unwrap();
+/// // ... point the system DNS at 127.0.0.1 ...
+/// forwarder.stop();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Forwarder {
+    listen: Vec<SocketAddr>,
+    upstream: Vec<SocketAddr>,
+    // (domain, its servers), the longest match wins
+    routes: Vec<(Name, Vec<SocketAddr>)>,
+    timeout: Duration,
+    attempts: usize,
+    cache_size: usize,
+}
+
+impl Forwarder {
+    pub fn new(upstream: Vec<SocketAddr>) -> Forwarder {
+        Forwarder {
+            listen: Vec::new(),
+            upstream,
+            routes: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            cache_size: DEFAULT_CACHE_SIZE,
+        }
+    }
+
+    /// Forward to the current system resolvers, before the VPN changes them.
+    pub fn system() -> Result<Forwarder, io::Error> {
+        match super::get_default_dns() {
+            Some(ref addrs) if !addrs.is_empty() => {
+                Ok(Forwarder::new(addrs.iter().map(|addr| SocketAddr::new(*addr, 53)).collect()))
+            },
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "no system DNS servers")),
+        }
+    }
+
+    /// UDP and TCP, port 0 picks the same free port for both.
+    pub fn listen(mut self, addr: SocketAddr) -> Forwarder {
+        self.listen.push(addr);
+        self
+    }
+
+    /// Queries for `domain` and its subdomains go to `servers`.
+    pub fn route(mut self, domain: Name, servers: Vec<SocketAddr>) -> Forwarder {
+        self.routes.push((domain, servers));
+        self
+    }
+
+    /// Route the match domains of a VPN to its servers, every domain if it has none.
+    pub fn split(self, config: &DnsConfig) -> Forwarder {
+        let servers = config.servers.iter().map(|addr| SocketAddr::new(*addr, 53)).collect::<Vec<SocketAddr>>();
+        if config.match_domains.is_empty() {
+            return self.route(Name::root(), servers);
+        }
+        config.match_domains.iter().fold(self, |forwarder, domain| match domain.parse::<Name>() {
+            Ok(name) => forwarder.route(name, servers.clone()),
+            Err(_) => {
+                warn!("ignoring the invalid match domain {:?}", domain);
+                forwarder
+            },
+        })
+    }
+
+    /// How long to wait for each server.
+    pub fn timeout(mut self, timeout: Duration) -> Forwarder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times to go through the servers.
+    pub fn attempts(mut self, attempts: usize) -> Forwarder {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// In questions, 0 turns caching off.
+    pub fn cache_size(mut self, cache_size: usize) -> Forwarder {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// The resolver alone, to answer queries intercepted on a TUN interface.
+    pub fn resolver(self) -> Resolver {
+        let cache = Cache { capacity: self.cache_size, entries: HashMap::new() };
+        Resolver { config: Arc::new(self), cache: Arc::new(Mutex::new(cache)) }
+    }
+
+    /// Listen and serve on a thread of its own until the handle is stopped or dropped.
+    pub fn spawn(mut self) -> Result<ForwarderHandle, io::Error> {
+        if self.listen.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "nowhere to listen"));
+        }
+
+        let mut udp = Vec::new();
+        let mut tcp = Vec::new();
+        let mut local_addrs = Vec::new();
+        for addr in self.listen.iter() {
+            let socket = UdpSocket::bind(addr)?;
+            let local_addr = socket.local_addr()?;
+            let listener = TcpListener::bind(local_addr)?;
+            socket.set_nonblocking(true)?;
+            listener.set_nonblocking(true)?;
+            udp.push(socket);
+            tcp.push(listener);
+            local_addrs.push(local_addr);
+        }
+
+        // Upstream servers taken from a resolv.conf that already points here would loop.
+        self.upstream.retain(|addr| !local_addrs.contains(addr));
+        for &mut (_, ref mut servers) in self.routes.iter_mut() {
+            servers.retain(|addr| !local_addrs.contains(addr));
+        }
+
+        let mut poller = Poller::new()?;
+        for (i, (socket, listener)) in udp.iter().zip(tcp.iter()).enumerate() {
+            poller.register(socket, 2 * i, Interest::READABLE)?;
+            poller.register(listener, 2 * i + 1, Interest::READABLE)?;
+        }
+
+        let resolver = self.resolver();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = running.clone();
+            thread::Builder::new().name("dns-forwarder".to_string())
+                                  .spawn(move || serve(poller, udp, tcp, resolver, running))?
+        };
+
+        Ok(ForwarderHandle { local_addrs, running, thread: Some(thread) })
+    }
+}
+
+
+/// Answers queries through the forwarder's routes and cache, cheap to clone.
+#[derive(Clone, Debug)]
+pub struct Resolver {
+    config: Arc<Forwarder>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl Resolver {
+    /// The servers queries for `name` go to.
+    pub fn servers(&self, name: &Name) -> &[SocketAddr] {
+        self.config.routes.iter()
+                          .filter(|&(domain, _)| name.is_subdomain_of(domain))
+                          .max_by_key(|&(domain, _)| domain.labels().len())
+                          .map(|(_, servers)| &servers[..])
+                          .unwrap_or(&self.config.upstream)
+    }
+
+    /// The response to `query`, SERVFAIL when no server answered.
+    pub fn resolve(&self, query: &Repr) -> Repr {
+        let question = match (query.opcode, query.questions.len()) {
+            (Opcode::Query, 1) => &query.questions[0],
+            (Opcode::Query, _) => return Repr { rcode: Rcode::FormErr, ..query.reply() },
+            _ => return Repr { rcode: Rcode::NotImp, ..query.reply() },
+        };
+
+        let key = CacheKey::new(query, question);
+        if let Some(response) = self.cache.lock().unwrap().get(&key) {
+            return self.finish(query, response);
+        }
+
+        match self.exchange(query, self.servers(&question.name)) {
+            Ok(response) => {
+                self.cache.lock().unwrap().insert(&key, &response);
+                self.finish(query, response)
+            },
+            Err(e) => {
+                debug!("{}: {}", question, e);
+                Repr { rcode: Rcode::ServFail, ..query.reply() }
+            },
+        }
+    }
+
+    /// Answer a query message, truncated to fit the client's UDP size if `udp`.
+    /// Responses are ignored.
+    pub fn handle(&self, query: &[u8], udp: bool) -> Option<Vec<u8>> {
+        let packet = Packet::new_checked(query).ok()?;
+        if packet.is_response() {
+            return None;
+        }
+        let query = match Repr::parse(&packet) {
+            Ok(query) => query,
+            Err(_) => {
+                let header = Repr {
+                    id: packet.id(),
+                    flags: Flags::RESPONSE,
+                    opcode: packet.opcode(),
+                    rcode: Rcode::FormErr,
+                    questions: Vec::new(),
+                    answers: Vec::new(),
+                    authorities: Vec::new(),
+                    additionals: Vec::new(),
+                    edns: None,
+                };
+                return header.to_bytes().ok();
+            },
+        };
+
+        let response = self.resolve(&query);
+        let bytes = match response.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                debug!("{}: {}", response, e);
+                return Repr { rcode: Rcode::ServFail, ..query.reply() }.to_bytes().ok();
+            },
+        };
+        let max_len = query.edns.as_ref()
+                                .map(|edns| (edns.udp_payload_size as usize).max(MIN_UDP_SIZE))
+                                .unwrap_or(MIN_UDP_SIZE);
+        if udp && bytes.len() > max_len {
+            // The client retries over TCP.
+            let truncated = Repr {
+                flags: response.flags | Flags::TRUNCATED,
+                answers: Vec::new(),
+                authorities: Vec::new(),
+                additionals: Vec::new(),
+                ..response
+            };
+            return truncated.to_bytes().ok();
+        }
+        Some(bytes)
+    }
+
+    // Every server in turn, `attempts` times, over TCP when the UDP response is truncated.
+    fn exchange(&self, query: &Repr, servers: &[SocketAddr]) -> Result<Repr, io::Error> {
+        let dnssec_ok = query.edns.as_ref().map(|edns| edns.dnssec_ok).unwrap_or(false);
+        let upstream = Repr {
+            id: random_id(),
+            edns: Some(Edns { dnssec_ok, ..Edns::new(EDNS_UDP_SIZE) }),
+            ..query.clone()
+        };
+
+        let timeout = self.config.timeout;
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no servers");
+        for _ in 0..self.config.attempts {
+            for &server in servers.iter() {
+                let response = exchange_udp(&upstream, server, timeout).and_then(|response| {
+                    if response.flags.contains(Flags::TRUNCATED) {
+                        exchange_tcp(&upstream, server, timeout)
+                    } else {
+                        Ok(response)
+                    }
+                });
+                match response {
+                    Ok(ref response) if response.rcode == Rcode::ServFail || response.rcode == Rcode::Refused => {
+                        last_error = io::Error::new(io::ErrorKind::Other, format!("{} answered {}", server, response.rcode));
+                    },
+                    Ok(response) => return Ok(response),
+                    Err(e) => last_error = e,
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    // The response as an answer to this query, the question keeps its case.
+    fn finish(&self, query: &Repr, response: Repr) -> Repr {
+        let extended_rcode = response.edns.as_ref().map(|edns| edns.extended_rcode).unwrap_or(0);
+        Repr {
+            id: query.id,
+            questions: query.questions.clone(),
+            edns: query.edns.as_ref().map(|_| Edns { extended_rcode, ..Edns::new(EDNS_UDP_SIZE) }),
+            ..response
+        }
+    }
+}
+
+
+fn serve(mut poller: Poller, udp: Vec<UdpSocket>, tcp: Vec<TcpListener>, resolver: Resolver, running: Arc<AtomicBool>) {
+    let udp_in_flight = Arc::new(AtomicUsize::new(0));
+    let tcp_in_flight = Arc::new(AtomicUsize::new(0));
+    let mut events = Vec::new();
+    let mut buffer = vec![0u8; u16::MAX as usize];
+
+    while running.load(Ordering::SeqCst) {
+        if let Err(e) = poller.poll(&mut events, Some(POLL_INTERVAL)) {
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            error!("DNS forwarder stopped: {}", e);
+            return;
+        }
+
+        for event in events.iter() {
+            let index = event.token / 2;
+            if event.token % 2 == 0 {
+                serve_udp(&udp[index], &mut buffer, &resolver, &udp_in_flight);
+            } else {
+                serve_tcp(&tcp[index], &resolver, &tcp_in_flight);
+            }
+        }
+    }
+}
+
+fn serve_udp(socket: &UdpSocket, buffer: &mut [u8], resolver: &Resolver, in_flight: &Arc<AtomicUsize>) {
+    loop {
+        let (len, peer) = match socket.recv_from(buffer) {
+            Ok(received) => received,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+                debug!("DNS forwarder: {}", e);
+                return;
+            },
+        };
+        if in_flight.load(Ordering::SeqCst) >= MAX_UDP_IN_FLIGHT {
+            debug!("dropping a query from {}, too many in flight", peer);
+            continue;
+        }
+
+        let socket = match socket.try_clone() {
+            Ok(socket) => socket,
+            Err(e) => {
+                debug!("DNS forwarder: {}", e);
+                continue;
+            },
+        };
+        let query = buffer[..len].to_vec();
+        let resolver = resolver.clone();
+        let in_flight = in_flight.clone();
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            if let Some(response) = resolver.handle(&query, true) {
+                let _ = socket.send_to(&response, peer);
+            }
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn serve_tcp(listener: &TcpListener, resolver: &Resolver, in_flight: &Arc<AtomicUsize>) {
+    loop {
+        let (mut stream, peer) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+            Err(e) => {
+                debug!("DNS forwarder: {}", e);
+                return;
+            },
+        };
+        if in_flight.load(Ordering::SeqCst) >= MAX_TCP_IN_FLIGHT {
+            debug!("refusing a connection from {}, too many in flight", peer);
+            continue;
+        }
+
+        let resolver = resolver.clone();
+        let in_flight = in_flight.clone();
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            // Accepted sockets inherit O_NONBLOCK on BSD.
+            let ready = stream.set_nonblocking(false)
+                              .and_then(|_| stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT)))
+                              .and_then(|_| stream.set_write_timeout(Some(TCP_IDLE_TIMEOUT)));
+            if ready.is_ok() {
+                while let Ok(query) = read_message(&mut stream) {
+                    match resolver.handle(&query, false) {
+                        Some(response) => if write_message(&mut stream, &response).is_err() { break },
+                        None => break,
+                    }
+                }
+            }
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+
+/// A running forwarder, stopped when dropped.
+#[derive(Debug)]
+pub struct ForwarderHandle {
+    local_addrs: Vec<SocketAddr>,
+    running: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ForwarderHandle {
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Stop listening, queries being resolved are still answered.
+    pub fn stop(self) { }
+}
+
+impl Drop for ForwarderHandle {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::wire::Type;
+
+    use smoltcp::wire::Ipv4Address;
+
+    // The answer of a stub server to a query, over UDP if `udp`; `n` counts the
+    // queries it had over that transport before. `None` drops the query.
+    type Answer = dyn Fn(&Repr, bool, usize) -> Option<Repr> + Send + Sync;
+
+    // A DNS server on 127.0.0.1, UDP and TCP on the same port.
+    struct Stub {
+        addr: SocketAddr,
+        udp_queries: Arc<AtomicUsize>,
+        tcp_queries: Arc<AtomicUsize>,
+        running: Arc<AtomicBool>,
+        threads: Vec<thread::JoinHandle<()>>,
+    }
+
+    impl Stub {
+        fn new<F>(answer: F) -> Stub where F: Fn(&Repr, bool, usize) -> Option<Repr> + Send + Sync + 'static {
+            let answer: Arc<Answer> = Arc::new(answer);
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap();
+            let listener = TcpListener::bind(addr).unwrap();
+            socket.set_read_timeout(Some(POLL_INTERVAL)).unwrap();
+            listener.set_nonblocking(true).unwrap();
+
+            let udp_queries = Arc::new(AtomicUsize::new(0));
+            let tcp_queries = Arc::new(AtomicUsize::new(0));
+            let running = Arc::new(AtomicBool::new(true));
+
+            let udp = {
+                let (answer, queries, running) = (answer.clone(), udp_queries.clone(), running.clone());
+                thread::spawn(move || {
+                    let mut buffer = vec![0u8; u16::MAX as usize];
+                    while running.load(Ordering::SeqCst) {
+                        let (len, peer) = match socket.recv_from(&mut buffer) {
+                            Ok(received) => received,
+                            Err(_) => continue,
+                        };
+                        let query = Repr::parse(&Packet::new_checked(&buffer[..len]).unwrap()).unwrap();
+                        let n = queries.fetch_add(1, Ordering::SeqCst);
+                        if let Some(response) = answer(&query, true, n) {
+                            socket.send_to(&response.to_bytes().unwrap(), peer).unwrap();
+                        }
+                    }
+                })
+            };
+            let tcp = {
+                let (answer, queries, running) = (answer, tcp_queries.clone(), running.clone());
+                thread::spawn(move || {
+                    while running.load(Ordering::SeqCst) {
+                        let mut stream = match listener.accept() {
+                            Ok((stream, _)) => stream,
+                            Err(_) => {
+                                thread::sleep(Duration::from_millis(10));
+                                continue;
+                            },
+                        };
+                        stream.set_nonblocking(false).unwrap();
+                        let message = read_message(&mut stream).unwrap();
+                        let query = Repr::parse(&Packet::new_checked(&message[..]).unwrap()).unwrap();
+                        let n = queries.fetch_add(1, Ordering::SeqCst);
+                        if let Some(response) = answer(&query, false, n) {
+                            write_message(&mut stream, &response.to_bytes().unwrap()).unwrap();
+                        }
+                    }
+                })
+            };
+
+            Stub { addr, udp_queries, tcp_queries, running, threads: vec![udp, tcp] }
+        }
+
+        // Answers every query with `addr`.
+        fn answering(addr: Ipv4Address, ttl: u32) -> Stub {
+            Stub::new(move |query, _, _| {
+                let mut response = query.reply();
+                response.answer(ttl, RecordData::A(addr));
+                Some(response)
+            })
+        }
+
+        fn queries(&self) -> (usize, usize) {
+            (self.udp_queries.load(Ordering::SeqCst), self.tcp_queries.load(Ordering::SeqCst))
+        }
+    }
+
+    impl Drop for Stub {
+        fn drop(&mut self) {
+            self.running.store(false, Ordering::SeqCst);
+            for thread in self.threads.drain(..) {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn query(name: &str) -> Repr {
+        Repr::query(random_id(), name.parse().unwrap(), Type::A)
+    }
+
+    fn ask_udp(forwarder: SocketAddr, query: &Repr) -> Repr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        socket.send_to(&query.to_bytes().unwrap(), forwarder).unwrap();
+        let mut buffer = vec![0u8; u16::MAX as usize];
+        let len = socket.recv(&mut buffer).unwrap();
+        let response = Repr::parse(&Packet::new_checked(&buffer[..len]).unwrap()).unwrap();
+        assert!(is_answer(query, &response));
+        response
+    }
+
+    fn ask_tcp(forwarder: SocketAddr, query: &Repr) -> Repr {
+        let mut stream = TcpStream::connect(forwarder).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write_message(&mut stream, &query.to_bytes().unwrap()).unwrap();
+        let message = read_message(&mut stream).unwrap();
+        let response = Repr::parse(&Packet::new_checked(&message[..]).unwrap()).unwrap();
+        assert!(is_answer(query, &response));
+        response
+    }
+
+    fn answers(response: &Repr) -> Vec<(u32, RecordData)> {
+        response.answers.iter().map(|record| (record.ttl, record.data.clone())).collect()
+    }
+
+    fn spawn(forwarder: Forwarder) -> (ForwarderHandle, SocketAddr) {
+        let handle = forwarder.listen("127.0.0.1:0".parse().unwrap()).spawn().unwrap();
+        let addr = handle.local_addrs()[0];
+        (handle, addr)
+    }
+
+    #[test]
+    fn routes() {
+        let public = Stub::answering(Ipv4Address::new(192, 0, 2, 1), 300);
+        let vpn = Stub::answering(Ipv4Address::new(10, 8, 0, 1), 300);
+        let lab = Stub::answering(Ipv4Address::new(10, 9, 0, 1), 300);
+        let forwarder = Forwarder::new(vec![public.addr])
+                                  .route("corp.example".parse().unwrap(), vec![vpn.addr])
+                                  .route("lab.corp.example".parse().unwrap(), vec![lab.addr]);
+        let (_handle, addr) = spawn(forwarder);
+
+        let response = ask_udp(addr, &query("Intranet.CORP.example"));
+        assert_eq!(answers(&response), vec![(300, RecordData::A(Ipv4Address::new(10, 8, 0, 1)))]);
+        // The longest match wins.
+        let response = ask_udp(addr, &query("host.lab.corp.example"));
+        assert_eq!(answers(&response), vec![(300, RecordData::A(Ipv4Address::new(10, 9, 0, 1)))]);
+        let response = ask_tcp(addr, &query("example.com"));
+        assert_eq!(answers(&response), vec![(300, RecordData::A(Ipv4Address::new(192, 0, 2, 1)))]);
+        // Not a subdomain, only a suffix of the name.
+        let response = ask_udp(addr, &query("notcorp.example"));
+        assert_eq!(answers(&response), vec![(300, RecordData::A(Ipv4Address::new(192, 0, 2, 1)))]);
+
+        assert_eq!(public.queries(), (2, 0));
+        assert_eq!(vpn.queries(), (1, 0));
+        assert_eq!(lab.queries(), (1, 0));
+    }
+
+    #[test]
+    fn split() {
+        let vpn = SocketAddr::new(Ipv4Addr::new(10, 8, 0, 1).into(), 53);
+        let config = DnsConfig::new(vec![vpn.ip()]).match_domain("corp.example").match_domain("bad..example");
+        let forwarder = Forwarder::new(Vec::new()).split(&config);
+        assert_eq!(forwarder.routes, vec![("corp.example".parse().unwrap(), vec![vpn])]);
+        let resolver = forwarder.resolver();
+        assert_eq!(resolver.servers(&"a.corp.example".parse().unwrap()), &[vpn]);
+        assert!(resolver.servers(&"example.com".parse().unwrap()).is_empty());
+
+        // Without match domains, everything.
+        let forwarder = Forwarder::new(Vec::new()).split(&DnsConfig::new(vec![vpn.ip()]));
+        assert_eq!(forwarder.resolver().servers(&"example.com".parse().unwrap()), &[vpn]);
+    }
+
+    #[test]
+    fn cache() {
+        let stub = Stub::new(|query, _, _| {
+            let mut response = query.reply();
+            let ttl = if query.questions[0].name == "short.example".parse().unwrap() { 1 } else { 300 };
+            response.answer(ttl, RecordData::A(Ipv4Address::new(192, 0, 2, 1)));
+            Some(response)
+        });
+        let (_handle, addr) = spawn(Forwarder::new(vec![stub.addr]));
+
+        let first = ask_udp(addr, &query("example.com"));
+        assert_eq!(first.answers[0].ttl, 300);
+        ask_udp(addr, &query("short.example"));
+        assert_eq!(stub.queries(), (2, 0));
+
+        // The same question differently cased, with another id, from the cache.
+        let again = query("EXAMPLE.com");
+        let response = ask_udp(addr, &again);
+        assert_eq!(response.id, again.id);
+        assert_eq!(response.questions, again.questions);
+        assert_eq!(stub.queries(), (2, 0));
+
+        // DO and CD get responses of their own.
+        let mut dnssec = query("example.com");
+        dnssec.edns = Some(Edns { dnssec_ok: true, ..Edns::new(1232) });
+        ask_udp(addr, &dnssec);
+        let mut unchecked = query("example.com");
+        unchecked.flags |= Flags::CHECKING_DISABLED;
+        let response = ask_udp(addr, &unchecked);
+        assert!(response.flags.contains(Flags::CHECKING_DISABLED));
+        assert_eq!(stub.queries(), (4, 0));
+        ask_udp(addr, &dnssec);
+        ask_udp(addr, &unchecked);
+        assert_eq!(stub.queries(), (4, 0));
+
+        // Aged by the time in the cache, expired after the TTL.
+        thread::sleep(Duration::from_millis(1100));
+        let response = ask_udp(addr, &query("example.com"));
+        assert!(response.answers[0].ttl < 300 && response.answers[0].ttl >= 290, "{}", response.answers[0].ttl);
+        assert_eq!(stub.queries(), (4, 0));
+        ask_udp(addr, &query("short.example"));
+        assert_eq!(stub.queries(), (5, 0));
+    }
+
+    #[test]
+    fn truncated() {
+        let stub = Stub::new(|query, udp, _| {
+            let mut response = query.reply();
+            if udp {
+                response.flags |= Flags::TRUNCATED;
+            } else {
+                for i in 0..100 {
+                    response.answer(60, RecordData::A(Ipv4Address::new(192, 0, 2, i)));
+                }
+            }
+            Some(response)
+        });
+        let (_handle, addr) = spawn(Forwarder::new(vec![stub.addr]));
+
+        // Too big for the client without EDNS, it has to ask again over TCP.
+        let response = ask_udp(addr, &query("example.com"));
+        assert!(response.flags.contains(Flags::TRUNCATED));
+        assert!(response.answers.is_empty());
+        assert_eq!(stub.queries(), (1, 1));
+
+        let response = ask_tcp(addr, &query("example.com"));
+        assert!(!response.flags.contains(Flags::TRUNCATED));
+        assert_eq!(response.answers.len(), 100);
+        // Truncated responses are not cached, the full one is.
+        assert_eq!(stub.queries(), (1, 1));
+
+        let mut large = query("example.com");
+        large.edns = Some(Edns::new(4096));
+        assert_eq!(ask_udp(addr, &large).answers.len(), 100);
+        assert_eq!(stub.queries(), (1, 1));
+    }
+
+    #[test]
+    fn retry() {
+        // Drops the first query, answers the next.
+        let flaky = Stub::new(|query, _, n| {
+            if n == 0 {
+                return None;
+            }
+            let mut response = query.reply();
+            response.answer(60, RecordData::A(Ipv4Address::new(192, 0, 2, 1)));
+            Some(response)
+        });
+        let (_handle, addr) = spawn(Forwarder::new(vec![flaky.addr]).timeout(Duration::from_millis(300)));
+        let response = ask_udp(addr, &query("example.com"));
+        assert_eq!(response.rcode, Rcode::NoError);
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(flaky.queries(), (2, 0));
+
+        // The next server after one that failed.
+        let failing = Stub::new(|query, _, _| Some(Repr { rcode: Rcode::ServFail, ..query.reply() }));
+        let working = Stub::answering(Ipv4Address::new(192, 0, 2, 2), 60);
+        let (_handle, addr) = spawn(Forwarder::new(vec![failing.addr, working.addr]));
+        let response = ask_udp(addr, &query("example.com"));
+        assert_eq!(answers(&response), vec![(60, RecordData::A(Ipv4Address::new(192, 0, 2, 2)))]);
+        assert_eq!((failing.queries(), working.queries()), ((1, 0), (1, 0)));
+
+        // SERVFAIL once every attempt is used up.
+        let silent = Stub::new(|_, _, _| None);
+        let (_handle, addr) = spawn(Forwarder::new(vec![silent.addr]).timeout(Duration::from_millis(100)).attempts(3));
+        let response = ask_udp(addr, &query("example.com"));
+        assert_eq!(response.rcode, Rcode::ServFail);
+        assert_eq!(silent.queries(), (3, 0));
+    }
+}
@@ -0,0 +1,522 @@
+//! `/etc/hosts`, with blocks of entries owned by znet.
+//!
+//! A block sits between two marker lines naming its tag and the process that wrote it,
+//! the rest of the file is never touched:
+//!
+//! ```text
+//! # BEGIN znet wg0 (pid 4242)
+//! 10.8.0.10    intranet.corp.example intranet
+//! # END znet wg0
+//! ```
+//!
+//! ```
+//! use znet::dns::hosts::Hosts;
+//!
+//! let hosts = Hosts::parse("127.0.0.1 localhost\n::1 localhost ip6-localhost # loopback\n");
+//! assert_eq!(hosts.lookup("LOCALHOST.").len(), 2);
+//! assert_eq!(hosts.lookup_addr("::1".parse().unwrap()), Some("localhost"));
+//! ```
+use crate::sys;
+
+use std::io;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+
+const HOSTS: &str = "etc/hosts";
+const TEMP_SUFFIX: &str = ".znet-tmp";
+const BEGIN: &str = "# BEGIN znet ";
+const END: &str = "# END znet ";
+
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn invalid_input<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+}
+
+/// An address and its names, the canonical one first. There is always one.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HostsEntry {
+    pub addr: IpAddr,
+    names: Vec<String>,
+}
+
+impl HostsEntry {
+    pub fn new(addr: IpAddr, name: &str) -> HostsEntry {
+        HostsEntry { addr, names: vec![name.to_string()] }
+    }
+
+    pub fn alias(mut self, name: &str) -> HostsEntry {
+        self.names.push(name.to_string());
+        self
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn name(&self) -> &str {
+        &self.names[0]
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.names[1..]
+    }
+
+    /// One line of hosts(5), `None` for blank lines, comments and garbage.
+    pub fn parse(line: &str) -> Option<HostsEntry> {
+        let line = line.split('#').next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        // The zone of a link-local address (`fe80::1%lo0`) is dropped.
+        let addr = fields.next()?.split('%').next()?.parse().ok()?;
+        let names: Vec<String> = fields.map(|name| name.to_string()).collect();
+        if names.is_empty() {
+            return None;
+        }
+        Some(HostsEntry { addr, names })
+    }
+
+    fn check(&self) -> Result<(), io::Error> {
+        for name in self.names.iter() {
+            if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '#') {
+                return Err(invalid_input(format!("invalid host name {:?}", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for HostsEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}", self.addr, self.names.join(" "))
+    }
+}
+
+
+/// The entries of a hosts file, in order.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Hosts {
+    entries: Vec<HostsEntry>,
+}
+
+impl Hosts {
+    pub fn new(entries: Vec<HostsEntry>) -> Hosts {
+        Hosts { entries }
+    }
+
+    pub fn parse(contents: &str) -> Hosts {
+        Hosts { entries: contents.lines().filter_map(HostsEntry::parse).collect() }
+    }
+
+    pub fn entries(&self) -> &[HostsEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every address of `name`, in the order of the file like the system resolver,
+    /// which answers with the first one. Names compare without case and trailing dot.
+    pub fn lookup(&self, name: &str) -> Vec<IpAddr> {
+        let mut addrs = Vec::new();
+        for entry in self.entries.iter() {
+            if entry.names.iter().any(|n| same_name(n, name)) && !addrs.contains(&entry.addr) {
+                addrs.push(entry.addr);
+            }
+        }
+        addrs
+    }
+
+    /// The canonical name of `addr`, that of its first entry.
+    pub fn lookup_addr(&self, addr: IpAddr) -> Option<&str> {
+        self.entries.iter().find(|entry| entry.addr == addr).map(|entry| entry.name())
+    }
+}
+
+impl fmt::Display for Hosts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+
+// A znet block, by line: `begin` and `end` are the marker lines.
+#[derive(Clone, Debug)]
+struct Block {
+    tag: String,
+    pid: Option<u32>,
+    begin: usize,
+    end: usize,
+}
+
+impl Block {
+    // An unterminated block is not ours to remove, it stays as it is.
+    fn find(lines: &[&str]) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        let mut begin: Option<(usize, &str, Option<u32>)> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let line = line.trim_end();
+            if let Some(rest) = line.strip_prefix(BEGIN) {
+                let mut fields = rest.split_whitespace();
+                let tag = fields.next().unwrap_or("");
+                let pid = fields.next().filter(|field| *field == "(pid")
+                                .and_then(|_| fields.next())
+                                .and_then(|pid| pid.trim_end_matches(')').parse().ok());
+                begin = Some((i, tag, pid));
+            } else if let Some(rest) = line.strip_prefix(END) {
+                if let Some((start, tag, pid)) = begin.take() {
+                    if rest.trim() == tag {
+                        blocks.push(Block { tag: tag.to_string(), pid, begin: start, end: i });
+                    }
+                }
+            }
+        }
+        blocks
+    }
+
+    // Without a pid there is no telling, it is kept.
+    fn is_stale(&self) -> bool {
+        match self.pid {
+            Some(pid) => !is_running(pid),
+            None => false,
+        }
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    let ret = unsafe { sys::kill(pid as sys::pid_t, 0) };
+    // EPERM: running as someone else.
+    ret == 0 || io::Error::last_os_error().raw_os_error() != Some(sys::ESRCH)
+}
+
+fn check_tag(tag: &str) -> Result<(), io::Error> {
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        return Err(invalid_input(format!("invalid hosts block tag {:?}", tag)));
+    }
+    Ok(())
+}
+
+
+/// The hosts file under a root, `/` for the system one.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HostsFile {
+    root: PathBuf,
+}
+
+impl Default for HostsFile {
+    fn default() -> HostsFile {
+        HostsFile::new()
+    }
+}
+
+impl HostsFile {
+    pub fn new() -> HostsFile {
+        HostsFile::with_root("/")
+    }
+
+    pub fn with_root<P: AsRef<Path>>(root: P) -> HostsFile {
+        HostsFile { root: root.as_ref().to_path_buf() }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.root.join(HOSTS)
+    }
+
+    fn contents(&self) -> Result<String, io::Error> {
+        match fs::read_to_string(self.path()) {
+            Ok(contents) => Ok(contents),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Everything in the file, the znet blocks included.
+    pub fn read(&self) -> Result<Hosts, io::Error> {
+        self.contents().map(|contents| Hosts::parse(&contents))
+    }
+
+    /// The entries of the block `tag`.
+    pub fn block(&self, tag: &str) -> Result<Hosts, io::Error> {
+        let contents = self.contents()?;
+        let lines: Vec<&str> = contents.lines().collect();
+        let entries = Block::find(&lines).into_iter()
+            .filter(|block| block.tag == tag)
+            .flat_map(|block| lines[block.begin + 1..block.end].iter().filter_map(|line| HostsEntry::parse(line)).collect::<Vec<_>>())
+            .collect();
+        Ok(Hosts::new(entries))
+    }
+
+    /// The tags of the blocks in the file.
+    pub fn tags(&self) -> Result<Vec<String>, io::Error> {
+        let contents = self.contents()?;
+        let lines: Vec<&str> = contents.lines().collect();
+        Ok(Block::find(&lines).into_iter().map(|block| block.tag).collect())
+    }
+
+    /// Put `entries` in the block `tag`, replacing what it had, until the guard is
+    /// dropped. Blocks left behind by processes no longer running go at the same time.
+    ///
+    /// The file is only rewritten when it changes.
+    pub fn apply(&self, tag: &str, entries: &[HostsEntry]) -> Result<HostsGuard, io::Error> {
+        check_tag(tag)?;
+        for entry in entries {
+            entry.check()?;
+        }
+
+        let mut block = Vec::new();
+        if !entries.is_empty() {
+            block.push(format!("{}{} (pid {})", BEGIN, tag, std::process::id()));
+            block.extend(entries.iter().map(|entry| entry.to_string()));
+            block.push(format!("{}{}", END, tag));
+        }
+        self.update(|block| block.tag == tag || block.is_stale(), block)?;
+
+        Ok(HostsGuard { file: self.clone(), tag: tag.to_string(), remove: true })
+    }
+
+    /// Take the block `tag` out. Returns whether there was one.
+    pub fn remove(&self, tag: &str) -> Result<bool, io::Error> {
+        self.update(|block| block.tag == tag, Vec::new()).map(|removed| removed > 0)
+    }
+
+    /// Take out the blocks of processes no longer running, e.g. after a crash.
+    /// Returns how many there were.
+    pub fn remove_stale(&self) -> Result<usize, io::Error> {
+        self.update(|block| block.is_stale(), Vec::new())
+    }
+
+    /// Take out every znet block.
+    pub fn remove_all(&self) -> Result<usize, io::Error> {
+        self.update(|_| true, Vec::new())
+    }
+
+    // Drops the blocks matching `predicate` and appends `block`, writing only when
+    // something changed. Returns the number of blocks dropped.
+    fn update<F>(&self, predicate: F, block: Vec<String>) -> Result<usize, io::Error>
+        where F: Fn(&Block) -> bool
+    {
+        let contents = self.contents()?;
+        let lines: Vec<&str> = contents.lines().collect();
+        let removed: Vec<Block> = Block::find(&lines).into_iter().filter(|block| predicate(block)).collect();
+        if removed.is_empty() && block.is_empty() {
+            return Ok(0);
+        }
+        for block in removed.iter().filter(|block| block.is_stale()) {
+            debug!("removing the stale hosts block {} of pid {:?}", block.tag, block.pid);
+        }
+
+        let mut kept: Vec<&str> = lines.iter().enumerate()
+            .filter(|&(i, _)| !removed.iter().any(|block| block.begin <= i && i <= block.end))
+            .map(|(_, line)| *line)
+            .collect();
+        kept.extend(block.iter().map(|line| line.as_str()));
+
+        let mut new_contents = kept.join("\n");
+        if !new_contents.is_empty() {
+            new_contents.push('\n');
+        }
+        if new_contents != contents {
+            self.write(&new_contents)?;
+        }
+        Ok(removed.len())
+    }
+
+    // Written next to the file and renamed over it, a reader sees either version.
+    fn write(&self, contents: &str) -> Result<(), io::Error> {
+        let path = self.path();
+        // A symlinked hosts file gets its target replaced.
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        let mode = fs::metadata(&path).map(|metadata| metadata.permissions().mode()).unwrap_or(0o644);
+
+        let mut temp = path.clone().into_os_string();
+        temp.push(TEMP_SUFFIX);
+        let temp = PathBuf::from(temp);
+        let _ = fs::remove_file(&temp);
+
+        let mut file = OpenOptions::new().write(true).create_new(true).mode(mode).open(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    }
+}
+
+/// Removes the block when dropped.
+#[derive(Debug)]
+pub struct HostsGuard {
+    file: HostsFile,
+    tag: String,
+    remove: bool,
+}
+
+impl HostsGuard {
+    /// Keep the block, for a later `HostsFile::remove`.
+    pub fn forget(mut self) {
+        self.remove = false;
+    }
+}
+
+impl Drop for HostsGuard {
+    fn drop(&mut self) {
+        if !self.remove {
+            return;
+        }
+        if let Err(e) = self.file.remove(&self.tag) {
+            error!("failed to remove the {} block from {}: {}", self.tag, self.file.path().display(), e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::scratch::Root;
+
+    use std::os::unix::fs::{symlink, MetadataExt};
+    use std::process::Command;
+
+    const SYSTEM: &str = "127.0.0.1\tlocalhost\n# The following lines are desirable for IPv6 capable hosts\n::1 localhost ip6-localhost\n";
+
+    fn entry(addr: &str, name: &str) -> HostsEntry {
+        HostsEntry::new(addr.parse().unwrap(), name)
+    }
+
+    // The pid of a process that exited.
+    fn dead_pid() -> u32 {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(HostsEntry::parse("fe80::1%lo0 router gw # the router"),
+                   Some(entry("fe80::1", "router").alias("gw")));
+        assert_eq!(HostsEntry::parse("10.0.0.1"), None);
+        assert_eq!(HostsEntry::parse("# 10.0.0.1 commented"), None);
+        assert_eq!(HostsEntry::parse("host 10.0.0.1"), None);
+
+        let entry = entry("10.0.0.1", "a.example").alias("a");
+        assert_eq!(entry.name(), "a.example");
+        assert_eq!(entry.aliases(), &["a".to_string()]);
+        assert_eq!(entry.names(), &["a.example".to_string(), "a".to_string()]);
+        assert_eq!(entry.to_string(), "10.0.0.1\ta.example a");
+
+        let hosts = Hosts::parse(SYSTEM);
+        assert_eq!(hosts.entries().len(), 2);
+        assert_eq!(hosts.lookup("localhost"), vec!["127.0.0.1".parse::<IpAddr>().unwrap(), "::1".parse().unwrap()]);
+        assert_eq!(hosts.lookup("IP6-LOCALHOST."), vec!["::1".parse::<IpAddr>().unwrap()]);
+        assert!(hosts.lookup("example.com").is_empty());
+        assert_eq!(hosts.lookup_addr("::1".parse().unwrap()), Some("localhost"));
+        assert_eq!(Hosts::parse(&hosts.to_string()), hosts);
+    }
+
+    #[test]
+    fn apply_remove() {
+        let root = Root::new();
+        fs::write(root.etc("hosts"), SYSTEM).unwrap();
+        let file = HostsFile::with_root(root.path());
+
+        assert!(file.apply("bad tag", &[]).is_err());
+        assert!(file.apply("wg0", &[entry("10.8.0.10", "bad#name")]).is_err());
+        assert!(file.apply("wg0", &[entry("10.8.0.10", "")]).is_err());
+
+        let entries = [entry("10.8.0.10", "intranet.corp.example").alias("intranet")];
+        let guard = file.apply("wg0", &entries).unwrap();
+        let contents = fs::read_to_string(file.path()).unwrap();
+        assert!(contents.starts_with(SYSTEM));
+        assert_eq!(&contents[SYSTEM.len()..],
+                   format!("# BEGIN znet wg0 (pid {})\n10.8.0.10\tintranet.corp.example intranet\n# END znet wg0\n", std::process::id()));
+        assert_eq!(file.block("wg0").unwrap().entries(), &entries);
+        assert_eq!(file.tags().unwrap(), vec!["wg0".to_string()]);
+        assert_eq!(file.read().unwrap().lookup("intranet"), vec!["10.8.0.10".parse::<IpAddr>().unwrap()]);
+
+        // Applying the same again leaves the file alone, the same inode.
+        let inode = fs::metadata(file.path()).unwrap().ino();
+        file.apply("wg0", &entries).unwrap().forget();
+        assert_eq!(fs::metadata(file.path()).unwrap().ino(), inode);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), contents);
+
+        // Another tag goes in a block of its own, new entries replace the old ones.
+        file.apply("wg1", &[entry("10.9.0.1", "lab")]).unwrap().forget();
+        file.apply("wg0", &[entry("10.8.0.11", "intranet")]).unwrap().forget();
+        assert_eq!(file.tags().unwrap(), vec!["wg1".to_string(), "wg0".to_string()]);
+        assert_eq!(file.block("wg0").unwrap().entries(), &[entry("10.8.0.11", "intranet")]);
+
+        drop(guard);
+        assert_eq!(file.tags().unwrap(), vec!["wg1".to_string()]);
+        assert!(!file.remove("wg0").unwrap());
+        assert_eq!(file.remove_all().unwrap(), 1);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), SYSTEM);
+        // Nothing to do for no entries.
+        file.apply("wg0", &[]).unwrap();
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), SYSTEM);
+    }
+
+    #[test]
+    fn stale_and_unterminated() {
+        let root = Root::new();
+        let dead = dead_pid();
+        let stale = format!("# BEGIN znet old (pid {})\n10.8.0.1 old\n# END znet old\n", dead);
+        let running = format!("# BEGIN znet live (pid {})\n10.8.0.2 live\n# END znet live\n", std::process::id());
+        let unknown = "# BEGIN znet manual\n10.8.0.3 manual\n# END znet manual\n";
+        let unterminated = format!("# BEGIN znet cut (pid {})\n10.8.0.4 cut\n", dead);
+        fs::write(root.etc("hosts"), format!("{}{}{}{}{}", SYSTEM, stale, running, unknown, unterminated)).unwrap();
+        let file = HostsFile::with_root(root.path());
+        assert_eq!(file.tags().unwrap(), vec!["old".to_string(), "live".to_string(), "manual".to_string()]);
+
+        // A dead pid goes with the next apply, a live one or none stays; so does
+        // a block without its end, which may be someone else's text.
+        file.apply("wg0", &[entry("10.8.0.10", "new")]).unwrap().forget();
+        let contents = fs::read_to_string(file.path()).unwrap();
+        assert!(!contents.contains("old"));
+        assert!(contents.starts_with(&format!("{}{}{}{}", SYSTEM, running, unknown, unterminated)));
+        assert_eq!(file.remove_stale().unwrap(), 0);
+
+        assert_eq!(file.remove_all().unwrap(), 3);
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), format!("{}{}", SYSTEM, unterminated));
+    }
+
+    #[test]
+    fn symlink_followed() {
+        let root = Root::new();
+        let target = root.path().join("hosts.real");
+        fs::write(&target, SYSTEM).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink("../hosts.real", root.etc("hosts")).unwrap();
+        let file = HostsFile::with_root(root.path());
+
+        file.apply("wg0", &[entry("10.8.0.10", "intranet")]).unwrap().forget();
+        assert_eq!(fs::read_link(file.path()).unwrap(), PathBuf::from("../hosts.real"));
+        assert!(fs::read_to_string(&target).unwrap().contains("intranet"));
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o640);
+
+        assert!(file.remove("wg0").unwrap());
+        assert_eq!(fs::read_to_string(&target).unwrap(), SYSTEM);
+        assert!(fs::symlink_metadata(file.path()).unwrap().file_type().is_symlink());
+    }
+
+    #[test]
+    fn missing_file() {
+        let root = Root::new();
+        let file = HostsFile::with_root(root.path());
+        assert!(file.read().unwrap().is_empty());
+        assert_eq!(file.remove_stale().unwrap(), 0);
+        assert!(fs::metadata(file.path()).is_err());
+    }
+}
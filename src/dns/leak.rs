@@ -0,0 +1,663 @@
+// DNS leaks: plain DNS and DNS over TLS leaving by some interface other than the tunnel.
+use super::wire::{self, Packet, Question, Repr};
+
+use crate::interface::Interface;
+use crate::poller::{Interest, Poller, Event};
+use crate::raw_socket::{BufferReader, LinkLayer, RawSocket};
+use crate::sys;
+
+use smoltcp::wire::{IpAddress, IpEndpoint, IpProtocol};
+use smoltcp::wire::{EthernetFrame, EthernetProtocol, Ipv4Packet, Ipv6Packet, TcpPacket, UdpPacket};
+
+use std::io;
+use std::fmt;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+
+pub const DOT_PORT: u16 = 853;
+
+// Past this many flows the table starts over and the flows seen again are reported again.
+const MAX_FLOWS: usize = 4096;
+// PACKET_OUTGOING
+#[cfg(target_os = "linux")]
+const SLL2_OUTGOING: u8 = 4;
+// How long an interface name is trusted, it may be renamed.
+#[cfg(target_os = "linux")]
+const NAME_TTL: Duration = Duration::from_secs(1);
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LeakKind {
+    // UDP to port 53
+    Udp,
+    // TCP to port 53
+    Tcp,
+    // TCP to port 853
+    Tls,
+}
+
+impl fmt::Display for LeakKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LeakKind::Udp => write!(f, "dns/udp"),
+            LeakKind::Tcp => write!(f, "dns/tcp"),
+            LeakKind::Tls => write!(f, "dot"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Flow {
+    pub kind: LeakKind,
+    pub src: IpEndpoint,
+    pub dst: IpEndpoint,
+}
+
+impl fmt::Display for Flow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.kind, self.src, self.dst)
+    }
+}
+
+/// The first packet of a flow that leaked.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Leak {
+    pub interface: String,
+    pub flow: Flow,
+    // The first question of a UDP query, TCP flows are reported on their SYN.
+    pub question: Option<Question>,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.interface, self.flow)?;
+        if let Some(ref question) = self.question {
+            write!(f, " ({})", question)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FlowStats {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LeakStats {
+    pub packets: u64,
+    // IP packets, headers included
+    pub bytes: u64,
+    pub flows: u64,
+    // Packets of each kind
+    pub udp: u64,
+    pub tcp: u64,
+    pub tls: u64,
+}
+
+impl fmt::Display for LeakStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} flows, {} packets ({} bytes): {} dns/udp, {} dns/tcp, {} dot",
+               self.flows, self.packets, self.bytes, self.udp, self.tcp, self.tls)
+    }
+}
+
+
+/// Watches for DNS leaving outside of the tunnel.
+///
+/// Linux captures on every interface at once and only looks at outgoing packets,
+/// the other systems capture on each interface up when `start` is called.
+///
+/// ```no_run
+/// use znet::dns::LeakDetector;
+/// use smoltcp::wire::{IpAddress, IpEndpoint};
+///
+/// let resolver = IpEndpoint::new(IpAddress::v4(10, 8, 0, 1), 53);
+/// let mut monitor = LeakDetector::new("wg0").redirect(resolver).start().unwrap();
+/// while let Some(leak) = monitor.next_leak(None).unwrap() {
+///     println!("{}", leak);
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LeakDetector {
+    tunnel: String,
+    allowed: Vec<IpAddress>,
+    redirect: Option<IpEndpoint>,
+    mark: Option<u32>,
+}
+
+impl LeakDetector {
+    pub fn new(tunnel: &str) -> LeakDetector {
+        LeakDetector { tunnel: tunnel.to_string(), allowed: Vec::new(), redirect: None, mark: None }
+    }
+
+    /// A server that may be queried outside of the tunnel, e.g. the one the tunnel
+    /// itself is set up with.
+    pub fn allow(mut self, addr: IpAddress) -> LeakDetector {
+        self.allowed.push(addr);
+        self
+    }
+
+    /// Send plain DNS leaving outside of the tunnel to `resolver` instead (Linux only),
+    /// the queries of the other address family and DNS over TLS are only reported.
+    /// Redirected queries no longer leak, so they are not reported.
+    pub fn redirect(mut self, resolver: IpEndpoint) -> LeakDetector {
+        self.redirect = Some(resolver);
+        self
+    }
+
+    /// Leave the packets carrying this fwmark alone, like `KillSwitch::mark`.
+    pub fn mark(mut self, mark: u32) -> LeakDetector {
+        self.mark = Some(mark);
+        self
+    }
+
+    pub fn start(&self) -> Result<LeakMonitor, io::Error> {
+        let mut tracker = Tracker::new(&self.tunnel, self.allowed.clone());
+        if let Some(resolver) = self.redirect {
+            // Whatever is left going to the resolver outside of the tunnel was redirected there.
+            tracker.allowed.push(resolver.addr);
+        }
+
+        let captures = open_captures(&self.tunnel)?;
+        let mut poller = Poller::new()?;
+        for (token, capture) in captures.iter().enumerate() {
+            poller.register(&capture.socket, token, Interest::READABLE)?;
+        }
+        let blen = captures.iter().map(|capture| capture.socket.blen()).max().unwrap_or(0);
+
+        let redirecting = match self.redirect {
+            Some(resolver) => {
+                redirect(&self.tunnel, resolver, &self.allowed, self.mark)?;
+                true
+            },
+            None => false,
+        };
+
+        Ok(LeakMonitor {
+            captures,
+            poller,
+            events: Vec::new(),
+            buf: vec![0u8; blen],
+            tracker,
+            redirecting,
+        })
+    }
+}
+
+
+struct Capture {
+    socket: RawSocket,
+    // None when capturing on every interface
+    interface: Option<Interface>,
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn open_captures(_tunnel: &str) -> Result<Vec<Capture>, io::Error> {
+    Ok(vec![Capture { socket: RawSocket::any()?, interface: None }])
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn open_captures(tunnel: &str) -> Result<Vec<Capture>, io::Error> {
+    use crate::interface::{interfaces_filtered, Flags};
+
+    let mut captures = Vec::new();
+    let up = interfaces_filtered(|iface| {
+        iface.name() != tunnel && !iface.is_loopback() && iface.flags().contains(Flags::IFF_UP)
+    });
+    for iface in up {
+        match RawSocket::with_ifname(&iface.name()) {
+            Ok(socket) => captures.push(Capture { socket: socket, interface: Some(iface) }),
+            Err(e) => warn!("not watching {} for DNS leaks: {}", iface.name(), e),
+        }
+    }
+    if captures.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no interface to watch"));
+    }
+    Ok(captures)
+}
+
+
+// What has been seen so far, fed with IP packets.
+struct Tracker {
+    tunnel: String,
+    allowed: Vec<IpAddress>,
+    flows: HashMap<(String, Flow), FlowStats>,
+    stats: LeakStats,
+    pending: VecDeque<Leak>,
+    // ifindex -> (name, is loopback, looked up at), capturing on every interface only
+    #[cfg(target_os = "linux")]
+    names: HashMap<i32, (String, bool, Instant)>,
+}
+
+impl Tracker {
+    fn new(tunnel: &str, allowed: Vec<IpAddress>) -> Tracker {
+        Tracker {
+            tunnel: tunnel.to_string(),
+            allowed,
+            flows: HashMap::new(),
+            stats: LeakStats::default(),
+            pending: VecDeque::new(),
+            #[cfg(target_os = "linux")]
+            names: HashMap::new(),
+        }
+    }
+
+    fn handle_frame(&mut self, link_layer: LinkLayer, interface: Option<&Interface>, frame: &[u8]) {
+        let (name, packet) = match (link_layer, interface) {
+            #[cfg(target_os = "linux")]
+            (LinkLayer::LinuxSll2, _) => {
+                match self.handle_sll2_frame(frame) {
+                    Some(found) => found,
+                    None => return,
+                }
+            },
+            (_, Some(iface)) => {
+                let packet = match link_layer {
+                    LinkLayer::Eth => {
+                        match EthernetFrame::new_checked(frame).map(|frame| frame.ethertype()) {
+                            Ok(EthernetProtocol::Ipv4) | Ok(EthernetProtocol::Ipv6) => &frame[EthernetFrame::<&[u8]>::header_len()..],
+                            _ => return,
+                        }
+                    },
+                    LinkLayer::IpWithPI(prefix_len) if frame.len() >= prefix_len => &frame[prefix_len..],
+                    link_layer if link_layer.is_ip() => frame,
+                    _ => return,
+                };
+                // The capture sees both ways, a query to one of our addresses is coming in.
+                match ip_dst_addr(packet) {
+                    Some(addr) if iface.addrs().iter().all(|cidr| cidr.address() != addr) => (),
+                    _ => return,
+                }
+                (iface.name(), packet)
+            },
+            _ => return,
+        };
+
+        if let Some(leak) = self.inspect(&name, packet) {
+            self.pending.push_back(leak);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn handle_sll2_frame<'a>(&mut self, frame: &'a [u8]) -> Option<(String, &'a [u8])> {
+        use byteorder::{ByteOrder, NetworkEndian};
+        use crate::raw_socket::SLL2_HDR_LEN;
+
+        if frame.len() < SLL2_HDR_LEN || frame[10] != SLL2_OUTGOING {
+            return None;
+        }
+        let protocol = NetworkEndian::read_u16(&frame[0..2]);
+        if protocol != sys::ETH_P_IP as u16 && protocol != sys::ETH_P_IPV6 as u16 {
+            return None;
+        }
+
+        let ifindex = NetworkEndian::read_i32(&frame[4..8]);
+        let now = Instant::now();
+        let known = match self.names.get(&ifindex) {
+            Some(&(_, _, looked_up)) => now - looked_up < NAME_TTL,
+            None => false,
+        };
+        if !known {
+            // Gone already, a miss is looked up again with the next packet.
+            let name = sys::if_index_to_name(ifindex as u32);
+            if name.is_empty() {
+                self.names.remove(&ifindex);
+                return None;
+            }
+            let hwtype = NetworkEndian::read_u16(&frame[8..10]);
+            self.names.insert(ifindex, (name, hwtype == sys::ARPHRD_LOOPBACK, now));
+        }
+
+        let (name, is_loopback, _) = &self.names[&ifindex];
+        if *is_loopback || *name == self.tunnel {
+            return None;
+        }
+        Some((name.clone(), &frame[SLL2_HDR_LEN..]))
+    }
+
+    fn inspect(&mut self, interface: &str, packet: &[u8]) -> Option<Leak> {
+        if interface == self.tunnel {
+            return None;
+        }
+
+        let (src_addr, dst_addr, protocol, payload) = match packet.first().map(|byte| byte >> 4) {
+            Some(4) => {
+                let packet = Ipv4Packet::new_checked(packet).ok()?;
+                let header_len = packet.header_len() as usize;
+                let total_len = packet.total_len() as usize;
+                (IpAddress::Ipv4(packet.src_addr()), IpAddress::Ipv4(packet.dst_addr()),
+                 packet.protocol(), &packet.into_inner()[header_len..total_len])
+            },
+            // Extension headers are not followed.
+            Some(6) => {
+                let packet = Ipv6Packet::new_checked(packet).ok()?;
+                let header_len = packet.header_len();
+                let total_len = packet.total_len();
+                (IpAddress::Ipv6(packet.src_addr()), IpAddress::Ipv6(packet.dst_addr()),
+                 packet.next_header(), &packet.into_inner()[header_len..total_len])
+            },
+            _ => return None,
+        };
+        if self.allowed.contains(&dst_addr) {
+            return None;
+        }
+
+        let (kind, src_port, dst_port, question) = match protocol {
+            IpProtocol::Udp => {
+                let udp = UdpPacket::new_checked(payload).ok()?;
+                if udp.dst_port() != wire::PORT {
+                    return None;
+                }
+                let question = Packet::new_checked(udp.payload()).ok()
+                    .and_then(|dns| Repr::parse(&dns).ok())
+                    .and_then(|repr| repr.questions.into_iter().next());
+                (LeakKind::Udp, udp.src_port(), udp.dst_port(), question)
+            },
+            IpProtocol::Tcp => {
+                let tcp = TcpPacket::new_checked(payload).ok()?;
+                let kind = match tcp.dst_port() {
+                    wire::PORT => LeakKind::Tcp,
+                    DOT_PORT => LeakKind::Tls,
+                    _ => return None,
+                };
+                (kind, tcp.src_port(), tcp.dst_port(), None)
+            },
+            _ => return None,
+        };
+
+        self.stats.packets += 1;
+        self.stats.bytes += packet.len() as u64;
+        match kind {
+            LeakKind::Udp => self.stats.udp += 1,
+            LeakKind::Tcp => self.stats.tcp += 1,
+            LeakKind::Tls => self.stats.tls += 1,
+        }
+
+        let flow = Flow {
+            kind,
+            src: IpEndpoint::new(src_addr, src_port),
+            dst: IpEndpoint::new(dst_addr, dst_port),
+        };
+        let key = (interface.to_string(), flow);
+        if let Some(stats) = self.flows.get_mut(&key) {
+            stats.packets += 1;
+            stats.bytes += packet.len() as u64;
+            return None;
+        }
+
+        if self.flows.len() >= MAX_FLOWS {
+            self.flows.clear();
+        }
+        self.flows.insert(key, FlowStats { packets: 1, bytes: packet.len() as u64 });
+        self.stats.flows += 1;
+        debug!("DNS leak on {}: {}", interface, flow);
+
+        Some(Leak { interface: interface.to_string(), flow, question })
+    }
+}
+
+fn ip_dst_addr(packet: &[u8]) -> Option<IpAddress> {
+    match packet.first().map(|byte| byte >> 4) {
+        Some(4) => Ipv4Packet::new_checked(packet).ok().map(|packet| IpAddress::Ipv4(packet.dst_addr())),
+        Some(6) => Ipv6Packet::new_checked(packet).ok().map(|packet| IpAddress::Ipv6(packet.dst_addr())),
+        _ => None,
+    }
+}
+
+
+/// A running `LeakDetector`, the redirection goes away when it is dropped.
+pub struct LeakMonitor {
+    captures: Vec<Capture>,
+    poller: Poller,
+    events: Vec<Event>,
+    buf: Vec<u8>,
+    tracker: Tracker,
+    redirecting: bool,
+}
+
+impl LeakMonitor {
+    /// Wait for the next flow leaking, `None` when the timeout expires first.
+    pub fn next_leak(&mut self, timeout: Option<Duration>) -> Result<Option<Leak>, io::Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(leak) = self.tracker.pending.pop_front() {
+                return Ok(Some(leak));
+            }
+
+            let now = Instant::now();
+            let remaining = deadline.map(|deadline| if deadline > now { deadline - now } else { Duration::from_secs(0) });
+            match self.poller.poll(&mut self.events, remaining) {
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            for event in self.events.iter() {
+                let capture = &mut self.captures[event.token];
+                // One read per wakeup, the BPF devices on macOS are not non-blocking.
+                let len = match capture.socket.recv(&mut self.buf) {
+                    Ok(len) => len,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Err(e),
+                };
+                let link_layer = capture.socket.link_layer();
+                for (start, end) in BufferReader::new(&self.buf, len) {
+                    self.tracker.handle_frame(link_layer, capture.interface.as_ref(), &self.buf[start..end]);
+                }
+            }
+
+            if self.tracker.pending.is_empty() && deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Look at an IP packet about to leave by `interface`, for a TUN relay to feed
+    /// what it forwards. A new flow leaking is returned rather than queued.
+    pub fn inspect(&mut self, interface: &str, packet: &[u8]) -> Option<Leak> {
+        self.tracker.inspect(interface, packet)
+    }
+
+    pub fn stats(&self) -> LeakStats {
+        self.tracker.stats
+    }
+
+    pub fn flows(&self) -> impl Iterator<Item = (&str, &Flow, &FlowStats)> {
+        self.tracker.flows.iter().map(|((interface, flow), stats)| (interface.as_str(), flow, stats))
+    }
+
+    pub fn is_redirecting(&self) -> bool {
+        self.redirecting
+    }
+}
+
+impl Drop for LeakMonitor {
+    fn drop(&mut self) {
+        if !self.redirecting {
+            return;
+        }
+        if let Err(e) = remove_redirect() {
+            error!("failed to remove the DNS redirection: {}", e);
+        }
+    }
+}
+
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use crate::firewall::nft::{Batch, Hook, Rule};
+        use smoltcp::wire::IpCidr;
+
+        pub const REDIRECT_TABLE: &str = "znet_dns";
+
+        fn redirect(tunnel: &str, resolver: IpEndpoint, allowed: &[IpAddress], mark: Option<u32>) -> Result<(), io::Error> {
+            let mut batch = Batch::new(sys::NFPROTO_INET);
+            batch.flush_table(REDIRECT_TABLE);
+            batch.add_table(REDIRECT_TABLE);
+            batch.add_chain(REDIRECT_TABLE, "output", Some(Hook {
+                hooknum: sys::NF_INET_LOCAL_OUT,
+                priority: sys::NF_IP_PRI_NAT_DST,
+                kind: "nat",
+                policy: sys::NF_ACCEPT,
+            }));
+
+            batch.add_rule(REDIRECT_TABLE, "output", Rule::new().oifname("lo").accept());
+            batch.add_rule(REDIRECT_TABLE, "output", Rule::new().oifname(tunnel).accept());
+            if let Some(mark) = mark {
+                batch.add_rule(REDIRECT_TABLE, "output", Rule::new().mark(mark).accept());
+            }
+            for addr in allowed.iter().chain(Some(&resolver.addr)) {
+                let prefix_len = if addr.as_bytes().len() == 16 { 128 } else { 32 };
+                batch.add_rule(REDIRECT_TABLE, "output", Rule::new().daddr(&IpCidr::new(*addr, prefix_len)).accept());
+            }
+
+            // DNAT does not cross address families, the resolver's is the only one redirected.
+            let nfproto = if resolver.addr.as_bytes().len() == 16 { sys::NFPROTO_IPV6 } else { sys::NFPROTO_IPV4 };
+            for protocol in [IpProtocol::Udp, IpProtocol::Tcp].iter() {
+                let rule = Rule::new().nfproto(nfproto)
+                                      .l4proto(u8::from(*protocol))
+                                      .dport(wire::PORT)
+                                      .dnat(&resolver);
+                batch.add_rule(REDIRECT_TABLE, "output", rule);
+            }
+
+            batch.commit()
+        }
+
+        /// Remove the DNS redirection, e.g. one left behind by a process that crashed.
+        pub fn remove_redirect() -> Result<(), io::Error> {
+            let mut batch = Batch::new(sys::NFPROTO_INET);
+            batch.flush_table(REDIRECT_TABLE);
+            batch.commit()
+        }
+    } else {
+        fn redirect(_tunnel: &str, _resolver: IpEndpoint, _allowed: &[IpAddress], _mark: Option<u32>) -> Result<(), io::Error> {
+            Err(io::Error::new(io::ErrorKind::Other, "redirecting DNS is not supported on this platform"))
+        }
+
+        pub fn remove_redirect() -> Result<(), io::Error> {
+            Ok(())
+        }
+    }
+}
+
+
+#[cfg(all(test, target_os = "linux", target_env = "gnu"))]
+mod tests {
+    use super::*;
+    use super::super::wire::Type;
+    use crate::interface::link::{LinkBuilder, Namespace};
+    use crate::netns::scratch::Scratch;
+
+    use std::net::{SocketAddr, TcpStream, UdpSocket};
+
+    // `name` in `a` and `peer` in `b`, the peer's MAC is `02:00:00:00:00:<last>`.
+    fn veth(a: &Scratch, name: &str, addr: &str, b: &Scratch, peer: &str, peer_addr: &str, last: u8) {
+        let peer_netns = Namespace::from(&**b);
+        a.run(|| LinkBuilder::veth(name, peer).peer_netns(peer_netns).up().create().map(|_| ())).unwrap().unwrap();
+        a.ip(&["addr", "add", addr, "dev", name]);
+        b.ip(&["link", "set", peer, "address", &format!("02:00:00:00:00:{:02x}", last)]);
+        b.ip(&["addr", "add", peer_addr, "dev", peer]);
+        b.ip(&["link", "set", peer, "up"]);
+    }
+
+    fn leaks(netns: &Scratch, monitor: &mut LeakMonitor) -> Vec<Leak> {
+        netns.run(|| {
+            let mut leaks = Vec::new();
+            while let Some(leak) = monitor.next_leak(Some(Duration::from_millis(500))).unwrap() {
+                leaks.push(leak);
+            }
+            leaks
+        }).unwrap()
+    }
+
+    fn dot(netns: &Scratch, addr: &str) {
+        let addr = addr.parse::<SocketAddr>().unwrap();
+        // Nothing answers, the SYN is what is looked for.
+        let _ = netns.run(|| TcpStream::connect_timeout(&addr, Duration::from_millis(200))).unwrap();
+    }
+
+    fn endpoint(addr: &str) -> IpEndpoint {
+        let addr = addr.parse::<SocketAddr>().unwrap();
+        IpEndpoint::new(addr.ip().into(), addr.port())
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn leak_and_redirect() {
+        // host: wan0 10.81.0.1 to the resolver 10.81.0.53, tun0 10.82.0.1 as the tunnel
+        let (host, remote) = (Scratch::new(), Scratch::new());
+        veth(&host, "wan0", "10.81.0.1/24", &remote, "wan1", "10.81.0.53/24", 0x53);
+        veth(&host, "tun0", "10.82.0.1/24", &remote, "tun1", "10.82.0.2/24", 0x02);
+        // Servers that do not exist, for the packets to leave without ARP.
+        host.ip(&["neigh", "add", "10.81.0.99", "lladdr", "02:00:00:00:00:53", "dev", "wan0"]);
+        host.ip(&["neigh", "add", "10.82.0.99", "lladdr", "02:00:00:00:00:02", "dev", "tun0"]);
+
+        let query = Repr::query(0x1234, "example.com".parse().unwrap(), Type::A).to_bytes().unwrap();
+        let client = host.run(|| UdpSocket::bind("0.0.0.0:0")).unwrap().unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+
+        let mut monitor = host.run(|| LeakDetector::new("tun0").start()).unwrap().unwrap();
+        assert!(!monitor.is_redirecting());
+        client.send_to(&query, "10.81.0.99:53").unwrap();
+        client.send_to(&query, "10.82.0.99:53").unwrap();
+        client.send_to(&query, "10.81.0.99:5353").unwrap();
+        dot(&host, "10.81.0.99:853");
+        dot(&host, "10.82.0.99:853");
+
+        let client_port = client.local_addr().unwrap().port();
+        let found = leaks(&host, &mut monitor);
+        assert_eq!(found.len(), 2, "{:?}", found);
+        let udp = found.iter().find(|leak| leak.flow.kind == LeakKind::Udp).unwrap();
+        assert_eq!(udp.interface, "wan0");
+        assert_eq!(udp.flow.src, endpoint(&format!("10.81.0.1:{}", client_port)));
+        assert_eq!(udp.flow.dst, endpoint("10.81.0.99:53"));
+        assert_eq!(udp.question.as_ref().map(|question| question.to_string()), Some("example.com A".to_string()));
+        let tls = found.iter().find(|leak| leak.flow.kind == LeakKind::Tls).unwrap();
+        assert_eq!(tls.interface, "wan0");
+        assert_eq!(tls.flow.dst, endpoint("10.81.0.99:853"));
+        assert_eq!(tls.question, None);
+
+        // The same flow again is counted, not reported.
+        client.send_to(&query, "10.81.0.99:53").unwrap();
+        assert!(leaks(&host, &mut monitor).is_empty());
+        let stats = monitor.stats();
+        assert_eq!((stats.flows, stats.packets, stats.udp, stats.tcp, stats.tls), (2, 3, 2, 0, 1));
+        let udp_len = (20 + 8 + query.len()) as u64;
+        assert!(stats.bytes > 2 * udp_len + 40, "{}", stats);
+        let (_, _, flow_stats) = monitor.flows().find(|&(_, flow, _)| flow.kind == LeakKind::Udp).unwrap();
+        assert_eq!(*flow_stats, FlowStats { packets: 2, bytes: 2 * udp_len });
+        host.run(move || drop(monitor)).unwrap();
+
+        // Redirected to the resolver, which answers in place of 10.81.0.99.
+        let resolver = remote.run(|| UdpSocket::bind("10.81.0.53:53")).unwrap().unwrap();
+        resolver.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+        let detector = LeakDetector::new("tun0").redirect(endpoint("10.81.0.53:53"));
+        let mut monitor = host.run(|| detector.start()).unwrap().unwrap();
+        assert!(monitor.is_redirecting());
+
+        client.send_to(&query, "10.81.0.99:53").unwrap();
+        let mut buf = [0; 512];
+        let (len, from) = resolver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &query[..]);
+        assert_eq!(from, format!("10.81.0.1:{}", client_port).parse().unwrap());
+        resolver.send_to(&buf[..len], from).unwrap();
+        let (_, from) = client.recv_from(&mut buf).unwrap();
+        assert_eq!(from, "10.81.0.99:53".parse().unwrap());
+        // DNS over TLS is only reported.
+        dot(&host, "10.81.0.99:853");
+
+        let found = leaks(&host, &mut monitor);
+        assert_eq!(found.iter().map(|leak| leak.flow.kind).collect::<Vec<_>>(), vec![LeakKind::Tls]);
+        let stats = monitor.stats();
+        assert_eq!((stats.flows, stats.udp, stats.tls), (1, 0, 1));
+
+        // Gone with the monitor.
+        host.run(move || drop(monitor)).unwrap();
+        client.send_to(&query, "10.81.0.99:53").unwrap();
+        assert!(resolver.recv_from(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,602 @@
+use super::{Dns, DnsOptions};
+use super::{DnsConfig, DnsConfigurator};
+
+use std::io;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::os::unix::fs::{OpenOptionsExt, symlink};
+
+
+const RESOLV_CONF: &str = "etc/resolv.conf";
+const BACKUP_SUFFIX: &str = ".znet";
+const TEMP_SUFFIX: &str = ".znet-tmp";
+const HEADER: &str = "# Generated by znet, the original is saved as resolv.conf.znet";
+// The upstream servers of systemd-resolved, its stub resolv.conf only has 127.0.0.53.
+const RESOLVED_RESOLV_CONF: &str = "/run/systemd/resolve/resolv.conf";
+
+
+/// What the resolv.conf symlink points into, if it is one.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Manager {
+    // A regular file
+    File,
+    SystemdResolved,
+    Resolvconf,
+    NetworkManager,
+    Symlink(PathBuf),
+}
+
+impl Manager {
+    fn from_target(target: PathBuf) -> Manager {
+        let managers = [
+            ("systemd/resolve", Manager::SystemdResolved),
+            ("resolvconf", Manager::Resolvconf),
+            ("NetworkManager", Manager::NetworkManager),
+        ];
+        let text = target.to_string_lossy().into_owned();
+        managers.iter()
+                .find(|&&(needle, _)| text.contains(needle))
+                .map(|(_, manager)| manager.clone())
+                .unwrap_or(Manager::Symlink(target))
+    }
+
+    /// Whether a daemon may rewrite resolv.conf behind our back.
+    pub fn is_managed(&self) -> bool {
+        !matches!(*self, Manager::File | Manager::Symlink(_))
+    }
+}
+
+
+impl Dns {
+    /// Parse resolv.conf(5), the nameservers and domain go to the `default_` fields.
+    pub fn from_resolv_conf(contents: &str) -> Dns {
+        let mut dns = Dns::default();
+        let mut addrs = Vec::new();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    // Link-local IPv6 servers may have a zone, `fe80::1%eth0`.
+                    let addr = words.next().and_then(|word| word.split('%').next());
+                    if let Some(Ok(addr)) = addr.map(|addr| addr.parse::<IpAddr>()) {
+                        addrs.push(addr);
+                    }
+                },
+                // `domain` and `search` are mutually exclusive, the last one wins.
+                Some("domain") => {
+                    dns.default_domain_name = words.next().map(|word| word.to_string());
+                    dns.search.clear();
+                },
+                Some("search") => {
+                    dns.search = words.map(|word| word.to_string()).collect();
+                    dns.default_domain_name = None;
+                },
+                Some("options") => {
+                    for option in words {
+                        parse_option(&mut dns.options, option);
+                    }
+                },
+                _ => { },
+            }
+        }
+
+        if !addrs.is_empty() {
+            dns.default_addrs = Some(addrs);
+        }
+        dns
+    }
+
+    pub fn to_resolv_conf(&self) -> String {
+        let mut contents = String::new();
+        for addr in self.addrs() {
+            contents.push_str(&format!("nameserver {}\n", addr));
+        }
+        if let Some(domain_name) = self.domain_name() {
+            contents.push_str(&format!("domain {}\n", domain_name));
+        }
+        if !self.search.is_empty() {
+            contents.push_str(&format!("search {}\n", self.search.join(" ")));
+        }
+        if !self.options.is_empty() {
+            let options = &self.options;
+            let mut words = Vec::new();
+            if let Some(ndots) = options.ndots {
+                words.push(format!("ndots:{}", ndots));
+            }
+            if let Some(timeout) = options.timeout {
+                words.push(format!("timeout:{}", timeout));
+            }
+            if let Some(attempts) = options.attempts {
+                words.push(format!("attempts:{}", attempts));
+            }
+            if options.rotate {
+                words.push("rotate".to_string());
+            }
+            words.extend(options.other.iter().cloned());
+            contents.push_str(&format!("options {}\n", words.join(" ")));
+        }
+        contents
+    }
+}
+
+fn parse_option(options: &mut DnsOptions, option: &str) {
+    let mut parts = option.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let value = parts.next().and_then(|value| value.parse::<u8>().ok());
+    match (name, value) {
+        ("ndots", Some(n)) => options.ndots = Some(n),
+        ("timeout", Some(n)) => options.timeout = Some(n),
+        ("attempts", Some(n)) => options.attempts = Some(n),
+        ("rotate", _) => options.rotate = true,
+        _ => options.other.push(option.to_string()),
+    }
+}
+
+
+/// `etc/resolv.conf` under a root directory, `/` but for tests and containers.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ResolvConf {
+    root: PathBuf,
+}
+
+impl Default for ResolvConf {
+    fn default() -> ResolvConf {
+        ResolvConf::new()
+    }
+}
+
+impl ResolvConf {
+    pub fn new() -> ResolvConf {
+        ResolvConf::with_root("/")
+    }
+
+    pub fn with_root<P: AsRef<Path>>(root: P) -> ResolvConf {
+        ResolvConf { root: root.as_ref().to_path_buf() }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.root.join(RESOLV_CONF)
+    }
+
+    pub fn backup_path(&self) -> PathBuf {
+        self.sibling(BACKUP_SUFFIX)
+    }
+
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    pub fn read(&self) -> Result<Dns, io::Error> {
+        fs::read_to_string(self.path()).map(|contents| Dns::from_resolv_conf(&contents))
+    }
+
+    pub fn manager(&self) -> Result<Manager, io::Error> {
+        match fs::read_link(self.path()) {
+            Ok(target) => Ok(Manager::from_target(target)),
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => Ok(Manager::File),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether a backup is in place, i.e. `apply` ran and was not restored.
+    pub fn is_applied(&self) -> bool {
+        fs::symlink_metadata(self.backup_path()).is_ok()
+    }
+
+    /// Replace resolv.conf with `dns`, keeping the original (a symlink stays a symlink)
+    /// next to it until the guard is dropped.
+    ///
+    /// An existing backup is left alone, it holds the original of an earlier `apply`.
+    pub fn apply(&self, dns: &Dns) -> Result<ResolvConfGuard, io::Error> {
+        let manager = self.manager()?;
+        if manager.is_managed() {
+            warn!("{} is managed by {:?}, which may overwrite it", self.path().display(), manager);
+        }
+
+        if !self.is_applied() {
+            self.backup()?;
+        }
+        let contents = format!("{}\n{}", HEADER, dns.to_resolv_conf());
+        self.write(&contents)?;
+
+        Ok(ResolvConfGuard { conf: self.clone(), restore: true })
+    }
+
+    fn backup(&self) -> Result<(), io::Error> {
+        let path = self.path();
+        let temp = self.sibling(TEMP_SUFFIX);
+        let _ = fs::remove_file(&temp);
+
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.file_type().is_symlink() {
+            symlink(fs::read_link(&path)?, &temp)?;
+        } else {
+            fs::copy(&path, &temp)?;
+            File::open(&temp)?.sync_all()?;
+        }
+        fs::rename(&temp, self.backup_path())
+    }
+
+    fn write(&self, contents: &str) -> Result<(), io::Error> {
+        let temp = self.sibling(TEMP_SUFFIX);
+        let _ = fs::remove_file(&temp);
+
+        let mut file = OpenOptions::new().write(true).create_new(true).mode(0o644).open(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        // Replaces a symlink itself, not its target.
+        fs::rename(&temp, self.path())
+    }
+
+    /// Put the original back, also after a crash. Returns whether there was one.
+    pub fn restore(&self) -> Result<bool, io::Error> {
+        if !self.is_applied() {
+            return Ok(false);
+        }
+        fs::rename(self.backup_path(), self.path())?;
+        Ok(true)
+    }
+}
+
+
+/// Restores the original resolv.conf when dropped.
+#[derive(Debug)]
+pub struct ResolvConfGuard {
+    conf: ResolvConf,
+    restore: bool,
+}
+
+impl ResolvConfGuard {
+    /// Keep the new resolv.conf, the backup stays for a later `ResolvConf::restore`.
+    pub fn forget(mut self) {
+        self.restore = false;
+    }
+}
+
+impl Drop for ResolvConfGuard {
+    fn drop(&mut self) {
+        if !self.restore {
+            return;
+        }
+        if let Err(e) = self.conf.restore() {
+            error!("failed to restore {}: {}", self.conf.path().display(), e);
+        }
+    }
+}
+
+
+pub fn get_default_dns() -> Option<Vec<IpAddr>> {
+    ResolvConf::new().read().ok().and_then(|dns| dns.default_addrs)
+}
+
+
+fn has_program(name: &str) -> bool {
+    env::var_os("PATH").map(|paths| env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+                       .unwrap_or(false)
+}
+
+fn run(program: &str, args: &[&str], input: Option<&str>) -> Result<(), io::Error> {
+    let mut child = Command::new(program).args(args)
+                                         .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+                                         .stdout(Stdio::null())
+                                         .stderr(Stdio::piped())
+                                         .spawn()?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  format!("{} {}: {}", program, args.join(" "), stderr.trim())));
+    }
+    Ok(())
+}
+
+fn warn_split_dns(config: &DnsConfig, backend: &str) {
+    if !config.match_domains.is_empty() {
+        warn!("{} has no split DNS, every query goes to {:?}", backend, config.servers);
+    }
+}
+
+
+/// Writes resolv.conf itself, keeping its options.
+#[derive(Debug)]
+pub struct ResolvConfConfigurator {
+    conf: ResolvConf,
+    guard: Option<ResolvConfGuard>,
+}
+
+impl ResolvConfConfigurator {
+    pub fn new(conf: ResolvConf) -> ResolvConfConfigurator {
+        ResolvConfConfigurator { conf, guard: None }
+    }
+}
+
+impl DnsConfigurator for ResolvConfConfigurator {
+    fn current(&self) -> Result<DnsConfig, io::Error> {
+        self.conf.read().map(|dns| DnsConfig::from(&dns))
+    }
+
+    fn apply(&mut self, config: &DnsConfig) -> Result<(), io::Error> {
+        warn_split_dns(config, "resolv.conf");
+        let options = self.conf.read()?.options;
+        let dns = Dns { search: config.search_domains.clone(), options, ..Dns::manual(config.servers.clone()) };
+
+        let guard = self.conf.apply(&dns)?;
+        match self.guard {
+            // The backup already holds the original.
+            Some(_) => guard.forget(),
+            None => self.guard = Some(guard),
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<(), io::Error> {
+        if let Some(guard) = self.guard.take() {
+            guard.forget();
+            self.conf.restore()?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Adds a record for the interface to resolvconf(8), Debian's or openresolv.
+#[derive(Debug)]
+pub struct ResolvconfConfigurator {
+    record: String,
+    applied: bool,
+}
+
+impl ResolvconfConfigurator {
+    pub fn new(interface: &str) -> ResolvconfConfigurator {
+        ResolvconfConfigurator { record: format!("{}.znet", interface), applied: false }
+    }
+}
+
+impl DnsConfigurator for ResolvconfConfigurator {
+    fn current(&self) -> Result<DnsConfig, io::Error> {
+        ResolvConf::new().read().map(|dns| DnsConfig::from(&dns))
+    }
+
+    fn apply(&mut self, config: &DnsConfig) -> Result<(), io::Error> {
+        warn_split_dns(config, "resolvconf");
+        let dns = Dns { search: config.search_domains.clone(), ..Dns::manual(config.servers.clone()) };
+        run("resolvconf", &["-a", &self.record], Some(&dns.to_resolv_conf()))?;
+        self.applied = true;
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<(), io::Error> {
+        if self.applied {
+            run("resolvconf", &["-d", &self.record], None)?;
+            self.applied = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ResolvconfConfigurator {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            error!("failed to remove the resolvconf record {}: {}", self.record, e);
+        }
+    }
+}
+
+
+/// Sets the DNS of the interface link in systemd-resolved, with `resolvectl`.
+#[derive(Debug)]
+pub struct ResolvedConfigurator {
+    interface: String,
+    applied: bool,
+}
+
+impl ResolvedConfigurator {
+    pub fn new(interface: &str) -> ResolvedConfigurator {
+        ResolvedConfigurator { interface: interface.to_string(), applied: false }
+    }
+}
+
+impl DnsConfigurator for ResolvedConfigurator {
+    fn current(&self) -> Result<DnsConfig, io::Error> {
+        fs::read_to_string(RESOLVED_RESOLV_CONF).map(|contents| DnsConfig::from(&Dns::from_resolv_conf(&contents)))
+    }
+
+    fn apply(&mut self, config: &DnsConfig) -> Result<(), io::Error> {
+        let servers = config.servers.iter().map(|addr| addr.to_string()).collect::<Vec<String>>();
+        // `~` marks routing-only domains, `~.` routes every query to the link.
+        let mut domains = config.search_domains.clone();
+        if config.match_domains.is_empty() {
+            domains.push("~.".to_string());
+        } else {
+            domains.extend(config.match_domains.iter().map(|domain| format!("~{}", domain)));
+        }
+        let default_route = if config.match_domains.is_empty() { "yes" } else { "no" };
+
+        let interface = self.interface.as_str();
+        let mut dns = vec!["dns", interface];
+        dns.extend(servers.iter().map(|addr| addr.as_str()));
+        let mut domain = vec!["domain", interface];
+        domain.extend(domains.iter().map(|domain| domain.as_str()));
+
+        // A revert also undoes a half applied configuration.
+        self.applied = true;
+        run("resolvectl", &dns, None)?;
+        run("resolvectl", &domain, None)?;
+        run("resolvectl", &["default-route", interface, default_route], None)
+    }
+
+    fn restore(&mut self) -> Result<(), io::Error> {
+        if self.applied {
+            run("resolvectl", &["revert", &self.interface], None)?;
+            self.applied = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ResolvedConfigurator {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore() {
+            error!("failed to revert the DNS of {}: {}", self.interface, e);
+        }
+    }
+}
+
+
+/// The configurator for whatever manages resolv.conf, `interface` is the tunnel.
+pub fn configurator(interface: &str) -> Result<Box<dyn DnsConfigurator>, io::Error> {
+    let conf = ResolvConf::new();
+    Ok(match conf.manager()? {
+        Manager::SystemdResolved if has_program("resolvectl") => Box::new(ResolvedConfigurator::new(interface)),
+        Manager::Resolvconf if has_program("resolvconf") => Box::new(ResolvconfConfigurator::new(interface)),
+        _ => Box::new(ResolvConfConfigurator::new(conf)),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::scratch::Root;
+
+    use std::mem;
+
+    const ORIGINAL: &str = "# from DHCP\nnameserver 192.168.1.1\nsearch lan\n";
+
+    #[test]
+    fn parse() {
+        let dns = Dns::from_resolv_conf("\
+            # comment\n\
+            ; nameserver 10.0.0.1\n\
+            nameserver 192.168.1.1\n\
+            nameserver fe80::1%eth0\n\
+            nameserver bogus\n\
+            options ndots:2 timeout:3 attempts:4 rotate edns0 ndots:x\n\
+            search example.com example.org\n");
+        assert_eq!(dns.default_addrs, Some(vec!["192.168.1.1".parse().unwrap(), "fe80::1".parse().unwrap()]));
+        assert_eq!(dns.search, vec!["example.com".to_string(), "example.org".to_string()]);
+        assert_eq!(dns.default_domain_name, None);
+        assert_eq!(dns.options, DnsOptions {
+            ndots: Some(2),
+            timeout: Some(3),
+            attempts: Some(4),
+            rotate: true,
+            other: vec!["edns0".to_string(), "ndots:x".to_string()],
+        });
+
+        let empty = Dns::from_resolv_conf("");
+        assert_eq!(empty, Dns::default());
+    }
+
+    #[test]
+    fn domain_and_search() {
+        let dns = Dns::from_resolv_conf("search a.example b.example\ndomain c.example\n");
+        assert_eq!(dns.default_domain_name, Some("c.example".to_string()));
+        assert!(dns.search.is_empty());
+
+        let dns = Dns::from_resolv_conf("domain c.example\nsearch a.example b.example\n");
+        assert_eq!(dns.default_domain_name, None);
+        assert_eq!(dns.search, vec!["a.example".to_string(), "b.example".to_string()]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let dns = Dns::from_resolv_conf("nameserver 10.0.0.1\nnameserver ::1\nsearch lan\noptions ndots:1 rotate edns0\n");
+        assert_eq!(Dns::from_resolv_conf(&dns.to_resolv_conf()), dns);
+    }
+
+    #[test]
+    fn apply_restore_file() {
+        let root = Root::new();
+        fs::write(root.etc("resolv.conf"), ORIGINAL).unwrap();
+        let conf = ResolvConf::with_root(root.path());
+        assert_eq!(conf.manager().unwrap(), Manager::File);
+        assert!(!conf.is_applied());
+
+        let dns = Dns::manual(vec!["10.8.0.1".parse().unwrap()]);
+        let guard = conf.apply(&dns).unwrap();
+        assert!(conf.is_applied());
+        assert_eq!(fs::read_to_string(conf.backup_path()).unwrap(), ORIGINAL);
+        let contents = fs::read_to_string(conf.path()).unwrap();
+        assert!(contents.starts_with(HEADER));
+        assert_eq!(conf.read().unwrap().default_addrs, Some(vec!["10.8.0.1".parse().unwrap()]));
+
+        // A second apply keeps the first backup.
+        conf.apply(&Dns::manual(vec!["10.8.0.2".parse().unwrap()])).unwrap().forget();
+        assert_eq!(fs::read_to_string(conf.backup_path()).unwrap(), ORIGINAL);
+
+        drop(guard);
+        assert!(!conf.is_applied());
+        assert_eq!(fs::read_to_string(conf.path()).unwrap(), ORIGINAL);
+        assert!(!conf.restore().unwrap());
+    }
+
+    #[test]
+    fn apply_restore_symlink() {
+        let root = Root::new();
+        let target = root.path().join("stub-resolv.conf");
+        fs::write(&target, ORIGINAL).unwrap();
+        symlink(&target, root.etc("resolv.conf")).unwrap();
+        let conf = ResolvConf::with_root(root.path());
+
+        let guard = conf.apply(&Dns::manual(vec!["10.8.0.1".parse().unwrap()])).unwrap();
+        // The symlink is replaced, its target is left alone.
+        assert!(!fs::symlink_metadata(conf.path()).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(conf.backup_path()).unwrap(), target);
+        assert_eq!(fs::read_to_string(&target).unwrap(), ORIGINAL);
+
+        drop(guard);
+        assert_eq!(fs::read_link(conf.path()).unwrap(), target);
+        assert!(!conf.is_applied());
+    }
+
+    #[test]
+    fn restore_after_crash() {
+        let root = Root::new();
+        fs::write(root.etc("resolv.conf"), ORIGINAL).unwrap();
+
+        // A crash: neither the guard nor anything else restores.
+        let guard = ResolvConf::with_root(root.path()).apply(&Dns::manual(vec!["10.8.0.1".parse().unwrap()])).unwrap();
+        mem::forget(guard);
+
+        let conf = ResolvConf::with_root(root.path());
+        assert!(conf.is_applied());
+        assert!(conf.restore().unwrap());
+        assert!(!conf.is_applied());
+        assert_eq!(fs::read_to_string(conf.path()).unwrap(), ORIGINAL);
+    }
+
+    #[test]
+    fn manager() {
+        let root = Root::new();
+        let conf = ResolvConf::with_root(root.path());
+        assert_eq!(conf.manager().unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let targets = [
+            ("../run/systemd/resolve/stub-resolv.conf", Manager::SystemdResolved),
+            ("../run/resolvconf/resolv.conf", Manager::Resolvconf),
+            ("/run/NetworkManager/resolv.conf", Manager::NetworkManager),
+            ("/etc/resolv.conf.static", Manager::Symlink(PathBuf::from("/etc/resolv.conf.static"))),
+        ];
+        for &(target, ref manager) in targets.iter() {
+            let _ = fs::remove_file(conf.path());
+            symlink(target, conf.path()).unwrap();
+            assert_eq!(&conf.manager().unwrap(), manager);
+            assert_eq!(manager.is_managed(), !matches!(*manager, Manager::Symlink(_)));
+        }
+    }
+}
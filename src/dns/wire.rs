@@ -0,0 +1,1266 @@
+//! DNS messages (RFC 1035) and EDNS(0) (RFC 6891), in the style of `smoltcp::wire`:
+//! `Packet` reads and writes the header of a buffer in place, `Repr` is the whole
+//! message with names decompressed.
+//!
+//! ```
+//! use znet::dns::wire::{Packet, Repr, RecordData, Type};
+//!
+//! let query = Repr::query(0x1234, "example.com".parse().unwrap(), Type::A);
+//! let mut buffer = vec![0u8; query.buffer_len().unwrap()];
+//! query.emit(&mut Packet::new_unchecked(&mut buffer[..])).unwrap();
+//!
+//! let packet = Packet::new_checked(&buffer[..]).unwrap();
+//! let mut reply = Repr::parse(&packet).unwrap().reply();
+//! reply.answer(300, RecordData::A("192.0.2.1".parse().unwrap()));
+//! assert_eq!(reply.answers.len(), 1);
+//! ```
+use byteorder::{ByteOrder, NetworkEndian};
+use smoltcp::{Error, Result};
+use smoltcp::wire::{Ipv4Address, Ipv6Address};
+use smoltcp::wire::pretty_print::{PrettyPrint, PrettyIndent};
+
+use std::fmt;
+use std::str::FromStr;
+use std::hash::{Hash, Hasher};
+
+
+pub const PORT: u16 = 53;
+pub const HEADER_LEN: usize = field::ARCOUNT.end;
+
+const MAX_LABEL_LEN: usize = 63;
+const MAX_NAME_LEN: usize = 255;
+const MAX_STRING_LEN: usize = 255;
+// Offsets a compression pointer can hold
+const MAX_POINTER: usize = 0x3fff;
+
+mod field {
+    #![allow(non_snake_case)]
+
+    pub type Field = ::core::ops::Range<usize>;
+
+    pub const ID:      Field = 0..2;
+    pub const FLAGS:   Field = 2..4;
+    pub const QDCOUNT: Field = 4..6;
+    pub const ANCOUNT: Field = 6..8;
+    pub const NSCOUNT: Field = 8..10;
+    pub const ARCOUNT: Field = 10..12;
+}
+
+bitflags! {
+    /// The flag bits of the header, the opcode and rcode are apart.
+    pub struct Flags: u16 {
+        const RESPONSE            = 0x8000;
+        const AUTHORITATIVE       = 0x0400;
+        const TRUNCATED           = 0x0200;
+        const RECURSION_DESIRED   = 0x0100;
+        const RECURSION_AVAILABLE = 0x0080;
+        const AUTHENTIC_DATA      = 0x0020;
+        const CHECKING_DISABLED   = 0x0010;
+    }
+}
+
+const OPCODE_MASK: u16 = 0x7800;
+const OPCODE_SHIFT: u16 = 11;
+const RCODE_MASK: u16 = 0x000f;
+
+
+macro_rules! u_enum {
+    ($(#[$attr:meta])* pub enum $name:ident($ty:ty) { $($variant:ident = $value:expr),+ $(,)* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub enum $name {
+            $($variant,)+
+            Unknown($ty),
+        }
+
+        impl From<$ty> for $name {
+            fn from(value: $ty) -> $name {
+                match value {
+                    $($value => $name::$variant,)+
+                    other => $name::Unknown(other),
+                }
+            }
+        }
+
+        impl From<$name> for $ty {
+            fn from(value: $name) -> $ty {
+                match value {
+                    $($name::$variant => $value,)+
+                    $name::Unknown(other) => other,
+                }
+            }
+        }
+    }
+}
+
+u_enum! {
+    pub enum Opcode(u8) {
+        Query  = 0,
+        Status = 2,
+        Notify = 4,
+        Update = 5,
+    }
+}
+
+u_enum! {
+    pub enum Rcode(u8) {
+        NoError  = 0,
+        FormErr  = 1,
+        ServFail = 2,
+        NxDomain = 3,
+        NotImp   = 4,
+        Refused  = 5,
+    }
+}
+
+u_enum! {
+    pub enum Type(u16) {
+        A     = 1,
+        Ns    = 2,
+        Cname = 5,
+        Soa   = 6,
+        Ptr   = 12,
+        Mx    = 15,
+        Txt   = 16,
+        Aaaa  = 28,
+        Srv   = 33,
+        Opt   = 41,
+        Any   = 255,
+    }
+}
+
+u_enum! {
+    pub enum Class(u16) {
+        In  = 1,
+        Ch  = 3,
+        Any = 255,
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Unknown(value) => write!(f, "TYPE{}", value),
+            _ => write!(f, "{}", format!("{:?}", self).to_uppercase()),
+        }
+    }
+}
+
+impl fmt::Display for Rcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rcode::Unknown(value) => write!(f, "RCODE{}", value),
+            _ => write!(f, "{}", format!("{:?}", self).to_uppercase()),
+        }
+    }
+}
+
+
+/// A read/write wrapper around the header of a DNS message buffer.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Packet<T: AsRef<[u8]>> {
+    buffer: T
+}
+
+impl<T: AsRef<[u8]>> Packet<T> {
+    pub fn new_unchecked(buffer: T) -> Packet<T> {
+        Packet { buffer }
+    }
+
+    pub fn new_checked(buffer: T) -> Result<Packet<T>> {
+        let packet = Self::new_unchecked(buffer);
+        packet.check_len()?;
+        Ok(packet)
+    }
+
+    /// Returns `Err(Error::Truncated)` if the buffer is shorter than the header.
+    pub fn check_len(&self) -> Result<()> {
+        if self.buffer.as_ref().len() < HEADER_LEN {
+            Err(Error::Truncated)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    #[inline]
+    pub fn id(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::ID])
+    }
+
+    #[inline]
+    fn raw_flags(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::FLAGS])
+    }
+
+    #[inline]
+    pub fn flags(&self) -> Flags {
+        Flags::from_bits_truncate(self.raw_flags())
+    }
+
+    #[inline]
+    pub fn opcode(&self) -> Opcode {
+        Opcode::from(((self.raw_flags() & OPCODE_MASK) >> OPCODE_SHIFT) as u8)
+    }
+
+    #[inline]
+    pub fn rcode(&self) -> Rcode {
+        Rcode::from((self.raw_flags() & RCODE_MASK) as u8)
+    }
+
+    #[inline]
+    pub fn is_response(&self) -> bool {
+        self.flags().contains(Flags::RESPONSE)
+    }
+
+    #[inline]
+    pub fn question_count(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::QDCOUNT])
+    }
+
+    #[inline]
+    pub fn answer_count(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::ANCOUNT])
+    }
+
+    #[inline]
+    pub fn authority_count(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::NSCOUNT])
+    }
+
+    #[inline]
+    pub fn additional_count(&self) -> u16 {
+        NetworkEndian::read_u16(&self.buffer.as_ref()[field::ARCOUNT])
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> Packet<&'a T> {
+    /// The sections after the header.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.buffer.as_ref()[HEADER_LEN..]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T> {
+    #[inline]
+    pub fn set_id(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::ID], value)
+    }
+
+    #[inline]
+    fn set_raw_flags(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::FLAGS], value)
+    }
+
+    #[inline]
+    pub fn set_flags(&mut self, value: Flags) {
+        let raw = self.raw_flags() & (OPCODE_MASK | RCODE_MASK);
+        self.set_raw_flags(raw | value.bits())
+    }
+
+    #[inline]
+    pub fn set_opcode(&mut self, value: Opcode) {
+        let raw = self.raw_flags() & !OPCODE_MASK;
+        self.set_raw_flags(raw | ((u8::from(value) as u16) << OPCODE_SHIFT) & OPCODE_MASK)
+    }
+
+    #[inline]
+    pub fn set_rcode(&mut self, value: Rcode) {
+        let raw = self.raw_flags() & !RCODE_MASK;
+        self.set_raw_flags(raw | (u8::from(value) as u16) & RCODE_MASK)
+    }
+
+    #[inline]
+    pub fn set_question_count(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::QDCOUNT], value)
+    }
+
+    #[inline]
+    pub fn set_answer_count(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::ANCOUNT], value)
+    }
+
+    #[inline]
+    pub fn set_authority_count(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::NSCOUNT], value)
+    }
+
+    #[inline]
+    pub fn set_additional_count(&mut self, value: u16) {
+        NetworkEndian::write_u16(&mut self.buffer.as_mut()[field::ARCOUNT], value)
+    }
+
+    /// The sections after the header.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[HEADER_LEN..]
+    }
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Packet<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+}
+
+
+/// A domain name, compared without regard to ASCII case.
+#[derive(Clone, Debug, Default)]
+pub struct Name {
+    labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+    pub fn root() -> Name {
+        Name { labels: Vec::new() }
+    }
+
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<Name> {
+        let name = Name { labels };
+        if name.labels.iter().any(|label| label.is_empty() || label.len() > MAX_LABEL_LEN)
+            || name.wire_len() > MAX_NAME_LEN {
+            return Err(Error::Malformed);
+        }
+        Ok(name)
+    }
+
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The length of the name uncompressed.
+    pub fn wire_len(&self) -> usize {
+        self.labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+    }
+
+    /// Whether the name is `other` or below it, `a.example.com` is in `example.com`.
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        self.labels.len() >= other.labels.len()
+            && self.labels.iter().rev().zip(other.labels.iter().rev())
+                   .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// The name without its first label.
+    pub fn parent(&self) -> Option<Name> {
+        if self.is_root() {
+            None
+        } else {
+            Some(Name { labels: self.labels[1..].to_vec() })
+        }
+    }
+
+    fn parse(message: &[u8], mut offset: usize) -> Result<(Name, usize)> {
+        let mut labels = Vec::new();
+        let mut wire_len = 1;
+        // Where the name ends in place, set at the first pointer.
+        let mut end = None;
+        let mut targets = Vec::new();
+
+        loop {
+            let len = *message.get(offset).ok_or(Error::Truncated)? as usize;
+            match len & 0xc0 {
+                0x00 if len == 0 => {
+                    offset += 1;
+                    break;
+                },
+                0x00 => {
+                    let label = message.get(offset + 1..offset + 1 + len).ok_or(Error::Truncated)?;
+                    wire_len += len + 1;
+                    if wire_len > MAX_NAME_LEN {
+                        return Err(Error::Malformed);
+                    }
+                    labels.push(label.to_vec());
+                    offset += len + 1;
+                },
+                0xc0 => {
+                    let low = *message.get(offset + 1).ok_or(Error::Truncated)? as usize;
+                    let target = ((len & 0x3f) << 8) | low;
+                    // Pointers go back to what was read before, and never to the
+                    // same place twice, that would be a loop.
+                    if target >= offset || targets.contains(&target) {
+                        return Err(Error::Malformed);
+                    }
+                    targets.push(target);
+                    if end.is_none() {
+                        end = Some(offset + 2);
+                    }
+                    offset = target;
+                },
+                _ => return Err(Error::Malformed),
+            }
+        }
+
+        Ok((Name { labels }, end.unwrap_or(offset)))
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.labels.len() == other.labels.len()
+            && self.labels.iter().zip(other.labels.iter()).all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name { }
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in self.labels.iter() {
+            state.write_usize(label.len());
+            for byte in label.iter() {
+                state.write_u8(byte.to_ascii_lowercase());
+            }
+        }
+    }
+}
+
+impl FromStr for Name {
+    type Err = Error;
+
+    /// A dotted name, the trailing dot is optional and `.` is the root.
+    fn from_str(s: &str) -> Result<Name> {
+        let s = s.trim_end_matches('.');
+        if s.is_empty() {
+            return Ok(Name::root());
+        }
+        Name::from_labels(s.split('.').map(|label| label.as_bytes().to_vec()).collect())
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            for &byte in label.iter() {
+                match byte {
+                    b'.' | b'\\' => write!(f, "\\{}", byte as char)?,
+                    0x21..=0x7e => write!(f, "{}", byte as char)?,
+                    _ => write!(f, "\\{:03}", byte)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Question {
+    pub name: Name,
+    pub kind: Type,
+    pub class: Class,
+}
+
+impl fmt::Display for Question {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.kind)
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RecordData {
+    A(Ipv4Address),
+    Aaaa(Ipv6Address),
+    Ns(Name),
+    Cname(Name),
+    Ptr(Name),
+    // Character strings, those over 255 bytes are split on the wire
+    Txt(Vec<Vec<u8>>),
+    Mx { preference: u16, exchange: Name },
+    Srv { priority: u16, weight: u16, port: u16, target: Name },
+    Soa { mname: Name, rname: Name, serial: u32, refresh: u32, retry: u32, expire: u32, minimum: u32 },
+    Unknown { kind: u16, data: Vec<u8> },
+}
+
+impl RecordData {
+    pub fn kind(&self) -> Type {
+        match *self {
+            RecordData::A(_) => Type::A,
+            RecordData::Aaaa(_) => Type::Aaaa,
+            RecordData::Ns(_) => Type::Ns,
+            RecordData::Cname(_) => Type::Cname,
+            RecordData::Ptr(_) => Type::Ptr,
+            RecordData::Txt(_) => Type::Txt,
+            RecordData::Mx { .. } => Type::Mx,
+            RecordData::Srv { .. } => Type::Srv,
+            RecordData::Soa { .. } => Type::Soa,
+            RecordData::Unknown { kind, .. } => Type::from(kind),
+        }
+    }
+
+    fn parse(kind: Type, message: &[u8], start: usize, end: usize) -> Result<RecordData> {
+        let data = &message[start..end];
+        let name = |offset: usize| -> Result<(Name, usize)> {
+            let (name, next) = Name::parse(message, offset)?;
+            if next > end { Err(Error::Malformed) } else { Ok((name, next)) }
+        };
+        let exact = |next: usize, value: RecordData| -> Result<RecordData> {
+            if next == end { Ok(value) } else { Err(Error::Malformed) }
+        };
+
+        match kind {
+            Type::A if data.len() == 4 => Ok(RecordData::A(Ipv4Address::from_bytes(data))),
+            Type::Aaaa if data.len() == 16 => Ok(RecordData::Aaaa(Ipv6Address::from_bytes(data))),
+            Type::A | Type::Aaaa => Err(Error::Malformed),
+            Type::Ns | Type::Cname | Type::Ptr => {
+                let (target, next) = name(start)?;
+                exact(next, match kind {
+                    Type::Ns => RecordData::Ns(target),
+                    Type::Cname => RecordData::Cname(target),
+                    _ => RecordData::Ptr(target),
+                })
+            },
+            Type::Txt => {
+                let mut strings = Vec::new();
+                let mut rest = data;
+                while !rest.is_empty() {
+                    let len = rest[0] as usize;
+                    let string = rest.get(1..1 + len).ok_or(Error::Malformed)?;
+                    strings.push(string.to_vec());
+                    rest = &rest[1 + len..];
+                }
+                Ok(RecordData::Txt(strings))
+            },
+            Type::Mx => {
+                if data.len() < 3 {
+                    return Err(Error::Malformed);
+                }
+                let (exchange, next) = name(start + 2)?;
+                exact(next, RecordData::Mx { preference: NetworkEndian::read_u16(&data[0..2]), exchange })
+            },
+            Type::Srv => {
+                if data.len() < 7 {
+                    return Err(Error::Malformed);
+                }
+                let (target, next) = name(start + 6)?;
+                exact(next, RecordData::Srv {
+                    priority: NetworkEndian::read_u16(&data[0..2]),
+                    weight: NetworkEndian::read_u16(&data[2..4]),
+                    port: NetworkEndian::read_u16(&data[4..6]),
+                    target,
+                })
+            },
+            Type::Soa => {
+                let (mname, next) = name(start)?;
+                let (rname, next) = name(next)?;
+                if end - next != 20 {
+                    return Err(Error::Malformed);
+                }
+                let numbers = &message[next..end];
+                Ok(RecordData::Soa {
+                    mname,
+                    rname,
+                    serial: NetworkEndian::read_u32(&numbers[0..4]),
+                    refresh: NetworkEndian::read_u32(&numbers[4..8]),
+                    retry: NetworkEndian::read_u32(&numbers[8..12]),
+                    expire: NetworkEndian::read_u32(&numbers[12..16]),
+                    minimum: NetworkEndian::read_u32(&numbers[16..20]),
+                })
+            },
+            _ => Ok(RecordData::Unknown { kind: u16::from(kind), data: data.to_vec() }),
+        }
+    }
+
+    fn emit(&self, emitter: &mut Emitter) -> Result<()> {
+        match *self {
+            RecordData::A(ref addr) => emitter.bytes(addr.as_bytes()),
+            RecordData::Aaaa(ref addr) => emitter.bytes(addr.as_bytes()),
+            RecordData::Ns(ref name) | RecordData::Cname(ref name) | RecordData::Ptr(ref name) => emitter.name(name, true),
+            RecordData::Txt(ref strings) => {
+                for string in strings.iter() {
+                    // Longer strings go in chunks, as for long SPF and DKIM records.
+                    if string.is_empty() {
+                        emitter.u8(0);
+                    }
+                    for chunk in string.chunks(MAX_STRING_LEN) {
+                        emitter.u8(chunk.len() as u8);
+                        emitter.bytes(chunk);
+                    }
+                }
+            },
+            RecordData::Mx { preference, ref exchange } => {
+                emitter.u16(preference);
+                emitter.name(exchange, true);
+            },
+            // RFC 2782: the target is not compressed.
+            RecordData::Srv { priority, weight, port, ref target } => {
+                emitter.u16(priority);
+                emitter.u16(weight);
+                emitter.u16(port);
+                emitter.name(target, false);
+            },
+            RecordData::Soa { ref mname, ref rname, serial, refresh, retry, expire, minimum } => {
+                emitter.name(mname, true);
+                emitter.name(rname, true);
+                for &number in [serial, refresh, retry, expire, minimum].iter() {
+                    emitter.u32(number);
+                }
+            },
+            RecordData::Unknown { ref data, .. } => emitter.bytes(data),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RecordData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RecordData::A(ref addr) => write!(f, "{}", addr),
+            RecordData::Aaaa(ref addr) => write!(f, "{}", addr),
+            RecordData::Ns(ref name) | RecordData::Cname(ref name) | RecordData::Ptr(ref name) => write!(f, "{}", name),
+            RecordData::Txt(ref strings) => {
+                let strings = strings.iter()
+                                     .map(|string| format!("{:?}", String::from_utf8_lossy(string)))
+                                     .collect::<Vec<String>>();
+                write!(f, "{}", strings.join(" "))
+            },
+            RecordData::Mx { preference, ref exchange } => write!(f, "{} {}", preference, exchange),
+            RecordData::Srv { priority, weight, port, ref target } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            RecordData::Soa { ref mname, ref rname, serial, refresh, retry, expire, minimum } => {
+                write!(f, "{} {} {} {} {} {} {}", mname, rname, serial, refresh, retry, expire, minimum)
+            },
+            RecordData::Unknown { ref data, .. } => write!(f, "\\# {}", data.len()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Record {
+    pub name: Name,
+    pub class: Class,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+impl Record {
+    pub fn kind(&self) -> Type {
+        self.data.kind()
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.name, self.ttl, self.kind(), self.data)
+    }
+}
+
+
+/// The OPT pseudo record of EDNS(0).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    // The upper 8 bits of the 12 bit rcode
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    // (code, data)
+    pub options: Vec<(u16, Vec<u8>)>,
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Edns {
+        Edns { udp_payload_size, extended_rcode: 0, version: 0, dnssec_ok: false, options: Vec::new() }
+    }
+
+    fn parse(record: &RawRecord, data: &[u8]) -> Result<Edns> {
+        if !record.name.is_root() {
+            return Err(Error::Malformed);
+        }
+        let mut options = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            if rest.len() < 4 {
+                return Err(Error::Malformed);
+            }
+            let code = NetworkEndian::read_u16(&rest[0..2]);
+            let len = NetworkEndian::read_u16(&rest[2..4]) as usize;
+            let value = rest.get(4..4 + len).ok_or(Error::Malformed)?;
+            options.push((code, value.to_vec()));
+            rest = &rest[4 + len..];
+        }
+
+        Ok(Edns {
+            udp_payload_size: record.class,
+            extended_rcode: (record.ttl >> 24) as u8,
+            version: (record.ttl >> 16) as u8,
+            dnssec_ok: record.ttl & 0x8000 != 0,
+            options,
+        })
+    }
+
+    fn emit(&self, emitter: &mut Emitter) -> Result<()> {
+        emitter.u8(0);
+        emitter.u16(u16::from(Type::Opt));
+        emitter.u16(self.udp_payload_size);
+        let ttl = ((self.extended_rcode as u32) << 24) | ((self.version as u32) << 16)
+                  | if self.dnssec_ok { 0x8000 } else { 0 };
+        emitter.u32(ttl);
+        let len = self.options.iter().map(|(_, data)| 4 + data.len()).sum::<usize>();
+        emitter.u16(to_u16(len)?);
+        for &(code, ref data) in self.options.iter() {
+            emitter.u16(code);
+            emitter.u16(to_u16(data.len())?);
+            emitter.bytes(data);
+        }
+        Ok(())
+    }
+}
+
+// The fixed fields of a resource record.
+struct RawRecord {
+    name: Name,
+    kind: Type,
+    class: u16,
+    ttl: u32,
+    data_start: usize,
+    data_end: usize,
+}
+
+impl RawRecord {
+    fn parse(message: &[u8], offset: usize) -> Result<RawRecord> {
+        let (name, offset) = Name::parse(message, offset)?;
+        let fixed = message.get(offset..offset + 10).ok_or(Error::Truncated)?;
+        let data_len = NetworkEndian::read_u16(&fixed[8..10]) as usize;
+        let data_start = offset + 10;
+        if message.len() < data_start + data_len {
+            return Err(Error::Truncated);
+        }
+        Ok(RawRecord {
+            name,
+            kind: Type::from(NetworkEndian::read_u16(&fixed[0..2])),
+            class: NetworkEndian::read_u16(&fixed[2..4]),
+            ttl: NetworkEndian::read_u32(&fixed[4..8]),
+            data_start,
+            data_end: data_start + data_len,
+        })
+    }
+}
+
+
+// A length or count that does not fit its 16 bit field.
+fn to_u16(value: usize) -> Result<u16> {
+    if value > 0xffff {
+        Err(Error::Exhausted)
+    } else {
+        Ok(value as u16)
+    }
+}
+
+// Writes a message, compressing names with pointers to their earlier suffixes.
+struct Emitter {
+    buffer: Vec<u8>,
+    suffixes: Vec<(Name, u16)>,
+}
+
+impl Emitter {
+    fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        let mut bytes = [0u8; 2];
+        NetworkEndian::write_u16(&mut bytes, value);
+        self.buffer.extend_from_slice(&bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        let mut bytes = [0u8; 4];
+        NetworkEndian::write_u32(&mut bytes, value);
+        self.buffer.extend_from_slice(&bytes);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    fn name(&mut self, name: &Name, compress: bool) {
+        for i in 0..name.labels.len() {
+            let suffix = Name { labels: name.labels[i..].to_vec() };
+            // Byte for byte, rewritten messages keep the case of every name.
+            if compress {
+                if let Some(&(_, offset)) = self.suffixes.iter().find(|&(known, _)| known.labels == suffix.labels) {
+                    self.u16(0xc000 | offset);
+                    return;
+                }
+            }
+            if self.buffer.len() <= MAX_POINTER {
+                self.suffixes.push((suffix, self.buffer.len() as u16));
+            }
+            let label = &name.labels[i];
+            self.u8(label.len() as u8);
+            self.bytes(label);
+        }
+        self.u8(0);
+    }
+
+    fn record(&mut self, record: &Record) -> Result<()> {
+        self.name(&record.name, true);
+        self.u16(u16::from(record.kind()));
+        self.u16(u16::from(record.class));
+        self.u32(record.ttl);
+        let len_at = self.buffer.len();
+        self.u16(0);
+        record.data.emit(self)?;
+        let len = to_u16(self.buffer.len() - len_at - 2)?;
+        NetworkEndian::write_u16(&mut self.buffer[len_at..len_at + 2], len);
+        Ok(())
+    }
+}
+
+
+/// A whole DNS message.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Repr {
+    pub id: u16,
+    pub flags: Flags,
+    pub opcode: Opcode,
+    pub rcode: Rcode,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    // Without the OPT record, that is `edns`
+    pub additionals: Vec<Record>,
+    pub edns: Option<Edns>,
+}
+
+impl Repr {
+    /// A recursive query for `name`.
+    pub fn query(id: u16, name: Name, kind: Type) -> Repr {
+        Repr {
+            id,
+            flags: Flags::RECURSION_DESIRED,
+            opcode: Opcode::Query,
+            rcode: Rcode::NoError,
+            questions: vec![Question { name, kind, class: Class::In }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+        }
+    }
+
+    /// An empty response to this query, with its id, questions and EDNS.
+    pub fn reply(&self) -> Repr {
+        Repr {
+            id: self.id,
+            flags: Flags::RESPONSE | Flags::RECURSION_AVAILABLE | (self.flags & (Flags::RECURSION_DESIRED | Flags::CHECKING_DISABLED)),
+            opcode: self.opcode,
+            rcode: Rcode::NoError,
+            questions: self.questions.clone(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: self.edns.as_ref().map(|edns| Edns { options: Vec::new(), ..edns.clone() }),
+        }
+    }
+
+    /// Add an answer for the first question.
+    pub fn answer(&mut self, ttl: u32, data: RecordData) {
+        let name = self.questions.first().map(|question| question.name.clone()).unwrap_or_default();
+        self.answers.push(Record { name, class: Class::In, ttl, data });
+    }
+
+    pub fn parse<T: AsRef<[u8]> + ?Sized>(packet: &Packet<&T>) -> Result<Repr> {
+        packet.check_len()?;
+        let message = packet.buffer.as_ref();
+        let mut offset = HEADER_LEN;
+
+        let mut questions = Vec::new();
+        for _ in 0..packet.question_count() {
+            let (name, next) = Name::parse(message, offset)?;
+            let fixed = message.get(next..next + 4).ok_or(Error::Truncated)?;
+            questions.push(Question {
+                name,
+                kind: Type::from(NetworkEndian::read_u16(&fixed[0..2])),
+                class: Class::from(NetworkEndian::read_u16(&fixed[2..4])),
+            });
+            offset = next + 4;
+        }
+
+        let counts = [packet.answer_count(), packet.authority_count(), packet.additional_count()];
+        let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+        let mut edns = None;
+        for (section, &count) in sections.iter_mut().zip(counts.iter()) {
+            for _ in 0..count {
+                let raw = RawRecord::parse(message, offset)?;
+                offset = raw.data_end;
+                if raw.kind == Type::Opt {
+                    if edns.is_some() {
+                        return Err(Error::Malformed);
+                    }
+                    edns = Some(Edns::parse(&raw, &message[raw.data_start..raw.data_end])?);
+                    continue;
+                }
+                let data = RecordData::parse(raw.kind, message, raw.data_start, raw.data_end)?;
+                section.push(Record { name: raw.name, class: Class::from(raw.class), ttl: raw.ttl, data });
+            }
+        }
+        let [answers, authorities, additionals] = sections;
+
+        Ok(Repr {
+            id: packet.id(),
+            flags: packet.flags(),
+            opcode: packet.opcode(),
+            rcode: packet.rcode(),
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+        })
+    }
+
+    /// The message in wire format, names compressed.
+    ///
+    /// Returns `Err(Error::Exhausted)` if a section or record is too long for its length field.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut emitter = Emitter { buffer: vec![0u8; HEADER_LEN], suffixes: Vec::new() };
+        {
+            let mut packet = Packet::new_unchecked(&mut emitter.buffer[..]);
+            packet.set_id(self.id);
+            packet.set_raw_flags(0);
+            packet.set_flags(self.flags);
+            packet.set_opcode(self.opcode);
+            packet.set_rcode(self.rcode);
+            packet.set_question_count(to_u16(self.questions.len())?);
+            packet.set_answer_count(to_u16(self.answers.len())?);
+            packet.set_authority_count(to_u16(self.authorities.len())?);
+            packet.set_additional_count(to_u16(self.additionals.len() + self.edns.iter().count())?);
+        }
+
+        for question in self.questions.iter() {
+            emitter.name(&question.name, true);
+            emitter.u16(u16::from(question.kind));
+            emitter.u16(u16::from(question.class));
+        }
+        for record in self.answers.iter().chain(self.authorities.iter()).chain(self.additionals.iter()) {
+            emitter.record(record)?;
+        }
+        if let Some(ref edns) = self.edns {
+            edns.emit(&mut emitter)?;
+        }
+        Ok(emitter.buffer)
+    }
+
+    pub fn buffer_len(&self) -> Result<usize> {
+        self.to_bytes().map(|bytes| bytes.len())
+    }
+
+    /// # Panics
+    /// This function panics if the buffer is shorter than `buffer_len`.
+    pub fn emit<T: AsRef<[u8]> + AsMut<[u8]>>(&self, packet: &mut Packet<T>) -> Result<()> {
+        let bytes = self.to_bytes()?;
+        packet.buffer.as_mut()[..bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+impl fmt::Display for Repr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.flags.contains(Flags::RESPONSE) { "response" } else { "query" };
+        write!(f, "DNS {} id=0x{:04x}", kind, self.id)?;
+        if self.opcode != Opcode::Query {
+            write!(f, " opcode={:?}", self.opcode)?;
+        }
+        if self.flags.contains(Flags::RESPONSE) {
+            write!(f, " rcode={}", self.rcode)?;
+        }
+        if self.flags.contains(Flags::TRUNCATED) {
+            write!(f, " truncated")?;
+        }
+        for question in self.questions.iter() {
+            write!(f, " {}?", question)?;
+        }
+        for answer in self.answers.iter() {
+            write!(f, " {} {}", answer.kind(), answer.data)?;
+        }
+        if let Some(ref edns) = self.edns {
+            write!(f, " edns={}", edns.udp_payload_size)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: AsRef<[u8]>> PrettyPrint for Packet<T> {
+    fn pretty_print(buffer: &dyn AsRef<[u8]>, f: &mut fmt::Formatter,
+                    indent: &mut PrettyIndent) -> fmt::Result {
+        match Packet::new_checked(buffer.as_ref()).and_then(|packet| Repr::parse(&packet)) {
+            Ok(repr) => write!(f, "{}{}", indent, repr),
+            Err(err) => write!(f, "{}DNS ({})", indent, err),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(text: &str) -> Name {
+        text.parse().unwrap()
+    }
+
+    fn record(owner: &str, ttl: u32, data: RecordData) -> Record {
+        Record { name: name(owner), class: Class::In, ttl, data }
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Repr> {
+        Packet::new_checked(bytes).and_then(|packet| Repr::parse(&packet))
+    }
+
+    // A header with these counts followed by `body`.
+    fn message(counts: [u16; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x12, 0x34, 0x81, 0x80];
+        for &count in counts.iter() {
+            bytes.extend_from_slice(&count.to_be_bytes());
+        }
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut repr = Repr::query(0xbeef, name("example.com"), Type::Any).reply();
+        repr.rcode = Rcode::NxDomain;
+        repr.answers = vec![
+            record("example.com", 300, RecordData::A(Ipv4Address::new(192, 0, 2, 1))),
+            record("example.com", 300, RecordData::Aaaa("2001:db8::1".parse().unwrap())),
+            record("example.com", 60, RecordData::Ns(name("ns1.example.com"))),
+            record("www.example.com", 60, RecordData::Cname(name("example.com"))),
+            record("1.2.0.192.in-addr.arpa", 60, RecordData::Ptr(name("example.com"))),
+            record("example.com", 60, RecordData::Txt(vec![b"v=spf1 -all".to_vec(), Vec::new(), b"\"quoted\"".to_vec()])),
+            record("example.com", 60, RecordData::Mx { preference: 10, exchange: name("mail.example.com") }),
+            record("_sip._udp.example.com", 60, RecordData::Srv { priority: 1, weight: 2, port: 5060, target: name("sip.example.com") }),
+            record("example.com", 60, RecordData::Unknown { kind: 99, data: vec![1, 2, 3] }),
+            record("example.com", 60, RecordData::Unknown { kind: 64, data: Vec::new() }),
+        ];
+        repr.authorities = vec![
+            record("example.com", 3600, RecordData::Soa {
+                mname: name("ns1.example.com"),
+                rname: name("hostmaster.example.com"),
+                serial: 2019010101,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300,
+            }),
+        ];
+        repr.additionals = vec![record("ns1.example.com", 60, RecordData::A(Ipv4Address::new(192, 0, 2, 53)))];
+        repr.edns = Some(Edns {
+            udp_payload_size: 1232,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![(10, vec![0; 8]), (12, Vec::new())],
+        });
+
+        let bytes = repr.to_bytes().unwrap();
+        assert_eq!(repr.buffer_len().unwrap(), bytes.len());
+        let parsed = parse(&bytes).unwrap();
+        assert_eq!(parsed, repr);
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+
+        let mut buffer = vec![0xff; bytes.len() + 4];
+        repr.emit(&mut Packet::new_unchecked(&mut buffer[..])).unwrap();
+        assert_eq!(&buffer[..bytes.len()], &bytes[..]);
+
+        let packet = Packet::new_checked(&bytes[..]).unwrap();
+        assert_eq!(packet.id(), 0xbeef);
+        assert!(packet.is_response());
+        assert_eq!(packet.rcode(), Rcode::NxDomain);
+        assert_eq!(packet.opcode(), Opcode::Query);
+        assert_eq!(packet.flags(), Flags::RESPONSE | Flags::RECURSION_DESIRED | Flags::RECURSION_AVAILABLE);
+        assert_eq!((packet.question_count(), packet.answer_count()), (1, 10));
+        assert_eq!((packet.authority_count(), packet.additional_count()), (1, 2));
+    }
+
+    #[test]
+    fn compression() {
+        let mut repr = Repr::query(1, name("example.com"), Type::A).reply();
+        repr.answers = vec![
+            record("www.example.com", 60, RecordData::Cname(name("web.example.com"))),
+            record("_x._tcp.example.com", 60, RecordData::Srv { priority: 0, weight: 0, port: 1, target: name("example.com") }),
+            // Case is kept, a differently cased name is not a suffix.
+            record("EXAMPLE.com", 60, RecordData::A(Ipv4Address::new(192, 0, 2, 1))),
+        ];
+        let bytes = repr.to_bytes().unwrap();
+
+        let question = b"\x07example\x03com\x00\x00\x01\x00\x01";
+        assert_eq!(&bytes[HEADER_LEN..HEADER_LEN + question.len()], &question[..]);
+        let answer = &bytes[HEADER_LEN + question.len()..];
+        // www + pointer to the question name, at 12
+        assert_eq!(&answer[..6], b"\x03www\xc0\x0c");
+        // web + the same pointer in the RDATA
+        assert_eq!(&answer[16..22], b"\x03web\xc0\x0c");
+        let srv = &answer[22..];
+        assert_eq!(&srv[..10], b"\x02_x\x04_tcp\xc0\x0c");
+        // The SRV target is written out in full.
+        assert_eq!(&srv[26..39], b"\x07example\x03com\x00");
+        // EXAMPLE + pointer to com, at 20
+        let a = &srv[39..];
+        assert_eq!(&a[..10], b"\x07EXAMPLE\xc0\x14");
+
+        assert_eq!(parse(&bytes).unwrap(), repr);
+    }
+
+    #[test]
+    fn edns() {
+        // A query with an OPT record: size 4096, extended rcode 1, version 0, DO, one cookie option.
+        let mut bytes = message([1, 0, 0, 1], b"\x00\x00\x01\x00\x01");
+        bytes.extend_from_slice(b"\x00\x00\x29\x10\x00\x01\x00\x80\x00\x00\x0c\x00\x0a\x00\x08\x01\x02\x03\x04\x05\x06\x07\x08");
+        let repr = parse(&bytes).unwrap();
+        assert!(repr.additionals.is_empty());
+        assert_eq!(repr.edns, Some(Edns {
+            udp_payload_size: 4096,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![(10, vec![1, 2, 3, 4, 5, 6, 7, 8])],
+        }));
+        assert_eq!(repr.to_bytes().unwrap(), bytes);
+
+        // Only one OPT record is allowed.
+        let opt = b"\x00\x00\x29\x04\xd0\x00\x00\x00\x00\x00\x00";
+        let mut twice = message([1, 0, 0, 2], b"\x00\x00\x01\x00\x01");
+        twice.extend_from_slice(opt);
+        twice.extend_from_slice(opt);
+        assert_eq!(parse(&twice), Err(Error::Malformed));
+
+        // Its name is the root.
+        let mut named = message([0, 0, 0, 1], b"\x01a\x00\x00\x29\x04\xd0\x00\x00\x00\x00\x00\x00");
+        assert_eq!(parse(&named), Err(Error::Malformed));
+
+        // An option longer than the RDATA.
+        named = message([0, 0, 0, 1], b"\x00\x00\x29\x04\xd0\x00\x00\x00\x00\x00\x05\x00\x0a\x00\x08\x01");
+        assert_eq!(parse(&named), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn pointers() {
+        // A question for `a` + pointer back to 12.
+        let good = message([2, 0, 0, 0], b"\x01a\x00\x00\x01\x00\x01\x01b\xc0\x0c\x00\x01\x00\x01");
+        let repr = parse(&good).unwrap();
+        assert_eq!(repr.questions[1].name, name("b.a"));
+
+        // A pointer to itself.
+        let own = message([1, 0, 0, 0], b"\xc0\x0c\x00\x01\x00\x01");
+        assert_eq!(parse(&own), Err(Error::Malformed));
+
+        // A pointer forward.
+        let forward = message([1, 0, 0, 0], b"\xc0\x0e\x01a\x00\x00\x01\x00\x01");
+        assert_eq!(parse(&forward), Err(Error::Malformed));
+
+        // `a` + a pointer back to `a`, reading on over the same pointer again.
+        let looped = message([1, 0, 0, 0], b"\x01a\xc0\x0c\x00\x01\x00\x01");
+        assert_eq!(parse(&looped), Err(Error::Malformed));
+
+        // The same through a pointer to a pointer.
+        let chained = message([2, 0, 0, 0], b"\x01a\xc0\x12\x00\x01\x00\x01\xc0\x0c\x00\x01\x00\x01");
+        assert_eq!(parse(&chained), Err(Error::Malformed));
+
+        // Reserved label types and pointers cut short.
+        assert_eq!(parse(&message([1, 0, 0, 0], b"\x40\x00\x00\x01\x00\x01")), Err(Error::Malformed));
+        assert_eq!(parse(&message([1, 0, 0, 0], b"\xc0")), Err(Error::Truncated));
+    }
+
+    #[test]
+    fn malformed() {
+        let question = b"\x00\x00\x01\x00\x01";
+        let answer = |kind: u16, data: &[u8]| {
+            let mut bytes = message([1, 1, 0, 0], question);
+            bytes.extend_from_slice(b"\x00");
+            bytes.extend_from_slice(&kind.to_be_bytes());
+            bytes.extend_from_slice(b"\x00\x01\x00\x00\x00\x3c");
+            bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(data);
+            bytes
+        };
+
+        assert!(parse(&answer(1, b"\xc0\x00\x02\x01")).is_ok());
+        assert_eq!(parse(&answer(1, b"\xc0\x00\x02")), Err(Error::Malformed));
+        assert_eq!(parse(&answer(28, &[0; 15])), Err(Error::Malformed));
+        // A name running past the RDATA, and one with bytes after it.
+        assert_eq!(parse(&answer(5, b"\x03www")), Err(Error::Truncated));
+        let mut cname = answer(5, b"\x03www\x00");
+        cname[HEADER_LEN + question.len() + 10] = 3;
+        assert_eq!(parse(&cname), Err(Error::Malformed));
+        assert_eq!(parse(&answer(5, b"\x03www\x00\x00")), Err(Error::Malformed));
+        assert_eq!(parse(&answer(15, b"\x00\x0a")), Err(Error::Malformed));
+        assert_eq!(parse(&answer(33, b"\x00\x00\x00\x00\x00\x35")), Err(Error::Malformed));
+        assert_eq!(parse(&answer(6, b"\x00\x00\x00\x00\x00\x01")), Err(Error::Malformed));
+        assert_eq!(parse(&answer(16, b"\x05abc")), Err(Error::Malformed));
+
+        // RDLENGTH past the end of the message.
+        let mut long = answer(1, b"\xc0\x00\x02\x01");
+        long.pop();
+        assert_eq!(parse(&long), Err(Error::Truncated));
+        // Fewer records than counted, and a short header.
+        assert_eq!(parse(&message([1, 1, 0, 0], question)), Err(Error::Truncated));
+        assert_eq!(parse(&[0; HEADER_LEN - 1]), Err(Error::Truncated));
+        // Labels and names too long.
+        let mut label = vec![64];
+        label.extend_from_slice(&[b'a'; 64]);
+        assert_eq!(parse(&message([1, 0, 0, 0], &label)), Err(Error::Malformed));
+        let mut long_name = Vec::new();
+        for _ in 0..5 {
+            long_name.push(63);
+            long_name.extend_from_slice(&[b'a'; 63]);
+        }
+        assert_eq!(parse(&message([1, 0, 0, 0], &long_name)), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn txt_chunks() {
+        let long = vec![b'x'; 300];
+        let mut repr = Repr::query(1, name("example.com"), Type::Txt).reply();
+        repr.answer(60, RecordData::Txt(vec![long.clone(), Vec::new()]));
+        let parsed = parse(&repr.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.answers[0].data, RecordData::Txt(vec![long[..255].to_vec(), long[255..].to_vec(), Vec::new()]));
+    }
+
+    #[test]
+    fn overflow() {
+        let mut repr = Repr::query(1, name("example.com"), Type::Any).reply();
+        repr.answer(60, RecordData::Unknown { kind: 99, data: vec![0; 0x10000] });
+        assert_eq!(repr.to_bytes(), Err(Error::Exhausted));
+        assert_eq!(repr.buffer_len(), Err(Error::Exhausted));
+
+        let mut repr = Repr::query(1, name("example.com"), Type::Any);
+        repr.edns = Some(Edns { options: vec![(65001, vec![0; 0x10000])], ..Edns::new(1232) });
+        assert_eq!(repr.to_bytes(), Err(Error::Exhausted));
+        repr.edns = Some(Edns { options: vec![(65001, vec![0; 0x8000]); 2], ..Edns::new(1232) });
+        assert_eq!(repr.to_bytes(), Err(Error::Exhausted));
+
+        let mut repr = Repr::query(1, Name::root(), Type::A);
+        repr.questions = vec![repr.questions[0].clone(); 0x10000];
+        assert_eq!(repr.to_bytes(), Err(Error::Exhausted));
+        repr.questions.pop();
+        assert!(repr.to_bytes().is_ok());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(name("WWW.Example.com."), name("www.example.com"));
+        assert!(name("a.example.com").is_subdomain_of(&name("Example.COM")));
+        assert!(!name("example.com").is_subdomain_of(&name("a.example.com")));
+        assert_eq!(name("a.example.com").parent(), Some(name("example.com")));
+        assert_eq!(Name::root().parent(), None);
+        assert_eq!(name(".").to_string(), ".");
+        assert_eq!(Name::from_labels(vec![b"a.b".to_vec(), vec![0]]).unwrap().to_string(), "a\\.b.\\000");
+        assert_eq!("a..b".parse::<Name>(), Err(Error::Malformed));
+        assert_eq!(Name::from_labels(vec![vec![b'a'; 64]]), Err(Error::Malformed));
+    }
+}
@@ -0,0 +1,95 @@
+//! A VPN kill switch: outside of the tunnel nothing leaves the host but the traffic
+//! to the VPN server and whatever is explicitly allowed.
+//!
+//! Linux programs nftables (one `inet` table of its own, replaced and removed in
+//! single transactions), macOS loads the rules into a pf anchor.
+//!
+//! ```no_run
+//! use znet::firewall::{self, KillSwitch};
+//! use smoltcp::wire::{IpAddress, IpEndpoint, IpProtocol};
+//!
+//! let server = IpEndpoint::new(IpAddress::v4(198, 51, 100, 7), 51820);
+//! KillSwitch::new("wg0").endpoint(server, IpProtocol::Udp).private_lan().dhcp().block_ipv6().apply().unwrap();
+//! // ... on disconnect ...
+//! firewall::remove().unwrap();
+//! ```
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, IpProtocol};
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        pub(crate) mod nft;
+        mod linux;
+        pub use self::linux::*;
+        pub mod nat;
+    } else if #[cfg(target_os = "macos")] {
+        mod macos;
+        pub use self::macos::*;
+    }
+}
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct KillSwitch {
+    tunnel: String,
+    endpoints: Vec<(IpEndpoint, IpProtocol)>,
+    lan: Vec<IpCidr>,
+    dhcp: bool,
+    block_ipv6: bool,
+    mark: Option<u32>,
+}
+
+impl KillSwitch {
+    /// Everything may go through the tunnel interface `tunnel`, and loopback stays open.
+    pub fn new(tunnel: &str) -> KillSwitch {
+        KillSwitch {
+            tunnel: tunnel.to_string(),
+            endpoints: Vec::new(),
+            lan: Vec::new(),
+            dhcp: false,
+            block_ipv6: false,
+            mark: None,
+        }
+    }
+
+    /// The VPN server, reachable from any interface.
+    pub fn endpoint(mut self, endpoint: IpEndpoint, protocol: IpProtocol) -> KillSwitch {
+        self.endpoints.push((endpoint, protocol));
+        self
+    }
+
+    pub fn lan(mut self, network: IpCidr) -> KillSwitch {
+        self.lan.push(network);
+        self
+    }
+
+    /// The RFC 1918, link-local and unique local networks.
+    pub fn private_lan(self) -> KillSwitch {
+        let networks = [
+            IpCidr::new(IpAddress::v4(10, 0, 0, 0), 8),
+            IpCidr::new(IpAddress::v4(172, 16, 0, 0), 12),
+            IpCidr::new(IpAddress::v4(192, 168, 0, 0), 16),
+            IpCidr::new(IpAddress::v4(169, 254, 0, 0), 16),
+            IpCidr::new(IpAddress::v6(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),
+            IpCidr::new(IpAddress::v6(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),
+        ];
+        networks.iter().fold(self, |killswitch, network| killswitch.lan(*network))
+    }
+
+    /// DHCP and DHCPv6 on every interface, so leases can be renewed.
+    pub fn dhcp(mut self) -> KillSwitch {
+        self.dhcp = true;
+        self
+    }
+
+    /// Drop all IPv6 but loopback, the tunnel included, for tunnels that only carry IPv4.
+    pub fn block_ipv6(mut self) -> KillSwitch {
+        self.block_ipv6 = true;
+        self
+    }
+
+    /// Let packets with this fwmark out anywhere, e.g. those of a WireGuard socket (Linux only).
+    pub fn mark(mut self, mark: u32) -> KillSwitch {
+        self.mark = Some(mark);
+        self
+    }
+}
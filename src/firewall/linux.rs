@@ -0,0 +1,209 @@
+use super::KillSwitch;
+use super::nft::{self, Batch, Hook, Rule};
+
+use crate::sys;
+
+use smoltcp::wire::{IpAddress, IpCidr, IpProtocol};
+
+use std::io;
+
+
+pub const TABLE: &str = "znet_killswitch";
+
+const DHCP_SERVER: u16 = 67;
+const DHCP_CLIENT: u16 = 68;
+const DHCPV6_CLIENT: u16 = 546;
+const DHCPV6_SERVER: u16 = 547;
+
+fn hook(hooknum: sys::c_int) -> Option<Hook> {
+    Some(Hook { hooknum, priority: 0, kind: "filter", policy: sys::NF_DROP })
+}
+
+fn host(addr: IpAddress) -> IpCidr {
+    let prefix_len = match addr {
+        IpAddress::Ipv6(_) => 128,
+        _ => 32,
+    };
+    IpCidr::new(addr, prefix_len)
+}
+
+impl KillSwitch {
+    /// Install the kill switch, replacing the one in place in the same transaction
+    /// so there is no moment without rules.
+    pub fn apply(&self) -> Result<(), io::Error> {
+        let mut batch = Batch::new(sys::NFPROTO_INET);
+        batch.flush_table(TABLE);
+        batch.add_table(TABLE);
+        batch.add_chain(TABLE, "input", hook(sys::NF_INET_LOCAL_IN));
+        batch.add_chain(TABLE, "output", hook(sys::NF_INET_LOCAL_OUT));
+        // Hosts routed through this one (containers, a hotspot) must not leak either.
+        batch.add_chain(TABLE, "forward", hook(sys::NF_INET_FORWARD));
+
+        let udp = u8::from(IpProtocol::Udp);
+
+        let mut output = vec![Rule::new().oifname("lo").accept()];
+        if self.block_ipv6 {
+            output.push(Rule::new().nfproto(sys::NFPROTO_IPV6).drop());
+        }
+        output.push(Rule::new().oifname(&self.tunnel).accept());
+        if let Some(mark) = self.mark {
+            output.push(Rule::new().mark(mark).accept());
+        }
+        for &(endpoint, protocol) in self.endpoints.iter() {
+            output.push(Rule::new().daddr(&host(endpoint.addr))
+                                   .l4proto(u8::from(protocol))
+                                   .dport(endpoint.port)
+                                   .accept());
+        }
+        if self.dhcp {
+            output.push(Rule::new().nfproto(sys::NFPROTO_IPV4).l4proto(udp)
+                                   .sport(DHCP_CLIENT).dport(DHCP_SERVER).accept());
+            output.push(Rule::new().nfproto(sys::NFPROTO_IPV6).l4proto(udp)
+                                   .sport(DHCPV6_CLIENT).dport(DHCPV6_SERVER).accept());
+        }
+        for network in self.lan.iter() {
+            output.push(Rule::new().daddr(network).accept());
+        }
+
+        let mut input = vec![Rule::new().iifname("lo").accept()];
+        if self.block_ipv6 {
+            input.push(Rule::new().nfproto(sys::NFPROTO_IPV6).drop());
+        }
+        input.push(Rule::new().ct_state(sys::netlink::NF_CT_STATE_ESTABLISHED | sys::netlink::NF_CT_STATE_RELATED).accept());
+        input.push(Rule::new().iifname(&self.tunnel).accept());
+        if self.dhcp {
+            input.push(Rule::new().nfproto(sys::NFPROTO_IPV4).l4proto(udp)
+                                  .sport(DHCP_SERVER).dport(DHCP_CLIENT).accept());
+            input.push(Rule::new().nfproto(sys::NFPROTO_IPV6).l4proto(udp)
+                                  .sport(DHCPV6_SERVER).dport(DHCPV6_CLIENT).accept());
+        }
+        for network in self.lan.iter() {
+            input.push(Rule::new().saddr(network).accept());
+        }
+
+        let mut forward = Vec::new();
+        if self.block_ipv6 {
+            forward.push(Rule::new().nfproto(sys::NFPROTO_IPV6).drop());
+        }
+        forward.push(Rule::new().ct_state(sys::netlink::NF_CT_STATE_ESTABLISHED | sys::netlink::NF_CT_STATE_RELATED).accept());
+        forward.push(Rule::new().oifname(&self.tunnel).accept());
+        for network in self.lan.iter() {
+            forward.push(Rule::new().daddr(network).accept());
+        }
+
+        for rule in output {
+            batch.add_rule(TABLE, "output", rule);
+        }
+        for rule in input {
+            batch.add_rule(TABLE, "input", rule);
+        }
+        for rule in forward {
+            batch.add_rule(TABLE, "forward", rule);
+        }
+
+        batch.commit()
+    }
+}
+
+/// Remove the kill switch, if there is one.
+pub fn remove() -> Result<(), io::Error> {
+    let mut batch = Batch::new(sys::NFPROTO_INET);
+    batch.flush_table(TABLE);
+    batch.commit()
+}
+
+pub fn is_active() -> Result<bool, io::Error> {
+    nft::table_exists(sys::NFPROTO_INET, TABLE)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::KillSwitch;
+    use crate::interface::link::{LinkBuilder, Namespace};
+    use crate::netns::scratch::Scratch;
+    use crate::sysctl::Sysctl;
+
+    use smoltcp::wire::IpEndpoint;
+
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    // `name` in `a` and `peer` in `b`, both up with the given addresses.
+    fn veth(a: &Scratch, name: &str, addr: &str, b: &Scratch, peer: &str, peer_addr: &str) {
+        let peer_netns = Namespace::from(&**b);
+        a.run(|| LinkBuilder::veth(name, peer).peer_netns(peer_netns).up().create().map(|_| ())).unwrap().unwrap();
+        a.ip(&["addr", "add", addr, "dev", name]);
+        b.ip(&["addr", "add", peer_addr, "dev", peer]);
+        b.ip(&["link", "set", peer, "up"]);
+    }
+
+    fn bind(netns: &Scratch, addr: &str) -> UdpSocket {
+        let socket = netns.run(|| UdpSocket::bind(addr)).unwrap().unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+        socket
+    }
+
+    // Whether a datagram from `from` arrives at `to`, `None` when sending fails.
+    fn delivered(from: &UdpSocket, to: &UdpSocket) -> Option<bool> {
+        from.send_to(b"ping", to.local_addr().unwrap()).ok()?;
+        let mut buf = [0; 16];
+        Some(to.recv(&mut buf).is_ok())
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn kill_switch() {
+        // host: wan0 10.77.0.1 and vpn0 10.78.0.1 to `remote`, lan0 10.80.0.1 to `client`
+        let (host, remote, client) = (Scratch::new(), Scratch::new(), Scratch::new());
+        veth(&host, "wan0", "10.77.0.1/24", &remote, "wan1", "10.77.0.2/24");
+        remote.ip(&["addr", "add", "10.77.0.130/24", "dev", "wan1"]);
+        veth(&host, "vpn0", "10.78.0.1/24", &remote, "vpn1", "10.78.0.2/24");
+        veth(&host, "lan0", "10.80.0.1/24", &client, "lan1", "10.80.0.2/24");
+        client.ip(&["route", "add", "default", "via", "10.80.0.1"]);
+        remote.ip(&["route", "add", "10.80.0.0/24", "via", "10.77.0.1"]);
+        host.run(|| Sysctl::IpForward.set(1)).unwrap().unwrap();
+
+        let local = bind(&host, "0.0.0.0:0");
+        let forwarded = bind(&client, "0.0.0.0:0");
+        let other = bind(&remote, "10.77.0.2:9999");
+        let server = bind(&remote, "10.77.0.2:51820");
+        let lan = bind(&remote, "10.77.0.130:9999");
+        let tunnel = bind(&remote, "10.78.0.2:9999");
+        assert_eq!(delivered(&local, &other), Some(true));
+        assert_eq!(delivered(&forwarded, &other), Some(true));
+
+        let killswitch = KillSwitch::new("vpn0").endpoint(IpEndpoint::new(IpAddress::v4(10, 77, 0, 2), 51820), IpProtocol::Udp)
+                                                .lan(IpCidr::new(IpAddress::v4(10, 77, 0, 128), 25));
+        host.run(|| killswitch.apply()).unwrap().unwrap();
+        assert!(host.run(is_active).unwrap().unwrap());
+        // Applying again replaces the rules.
+        host.run(|| killswitch.apply()).unwrap().unwrap();
+
+        // Dropped on output, sendto(2) fails with EPERM.
+        assert_eq!(delivered(&local, &other), None);
+        assert_eq!(delivered(&local, &server), Some(true));
+        assert_eq!(delivered(&local, &lan), Some(true));
+        assert_eq!(delivered(&local, &tunnel), Some(true));
+        // The router drops what it would forward outside of the tunnel.
+        assert_eq!(delivered(&forwarded, &other), Some(false));
+        assert_eq!(delivered(&forwarded, &lan), Some(true));
+        assert_eq!(delivered(&forwarded, &tunnel), Some(true));
+        // Only replies come in from outside of the tunnel.
+        let mut buf = [0; 16];
+        local.send_to(b"ping", server.local_addr().unwrap()).unwrap();
+        let (_, from) = server.recv_from(&mut buf).unwrap();
+        server.send_to(b"pong", from).unwrap();
+        assert!(local.recv(&mut buf).is_ok());
+        other.send_to(b"ping", from).unwrap();
+        assert!(local.recv(&mut buf).is_err());
+
+        host.run(remove).unwrap().unwrap();
+        assert!(!host.run(is_active).unwrap().unwrap());
+        assert_eq!(delivered(&local, &other), Some(true));
+        assert_eq!(delivered(&forwarded, &other), Some(true));
+        // Removing twice is fine.
+        host.run(remove).unwrap().unwrap();
+    }
+}
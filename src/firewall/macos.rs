@@ -0,0 +1,126 @@
+use super::KillSwitch;
+
+use pfctl::{PfCtl, FilterRule, FilterRuleBuilder, FilterRuleAction, AnchorChange, AnchorKind};
+use pfctl::{AddrFamily, Direction, Endpoint, Ip, Port, Proto, RulesetKind, StatePolicy};
+use pfctl::ipnetwork::IpNetwork;
+use pfctl::ErrorKind;
+
+use smoltcp::wire::{IpAddress, IpCidr, IpProtocol};
+
+use std::io;
+use std::fs;
+use std::fmt;
+use std::net::IpAddr;
+
+
+pub const ANCHOR: &str = "znet.killswitch";
+
+// Present while the kill switch is applied, "1" when pf was enabled by `apply`
+// and has to be disabled again by `remove`. /var/run is cleared on boot, like the anchor.
+const STATE_FILE: &str = "/var/run/znet.killswitch";
+
+fn pf_error<E: fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+fn ip(addr: &IpAddress, prefix_len: u8) -> Result<Ip, io::Error> {
+    let addr = match *addr {
+        IpAddress::Ipv4(ref v4) => IpAddr::from(v4.0),
+        IpAddress::Ipv6(ref v6) => IpAddr::from(v6.0),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "unspecified address")),
+    };
+    IpNetwork::new(addr, prefix_len).map(Ip::from).map_err(pf_error)
+}
+
+fn network(cidr: &IpCidr) -> Result<Ip, io::Error> {
+    ip(&cidr.address(), cidr.prefix_len())
+}
+
+fn pass() -> FilterRuleBuilder {
+    let mut builder = FilterRuleBuilder::default();
+    builder.action(FilterRuleAction::Pass).quick(true).keep_state(StatePolicy::Keep);
+    builder
+}
+
+fn build(builder: &mut FilterRuleBuilder) -> Result<FilterRule, io::Error> {
+    builder.build().map_err(pf_error)
+}
+
+impl KillSwitch {
+    /// Load the rules into the kill switch anchor (created on first use) and enable pf.
+    pub fn apply(&self) -> Result<(), io::Error> {
+        let mut rules = Vec::new();
+        rules.push(build(pass().interface("lo0"))?);
+        if self.block_ipv6 {
+            rules.push(build(FilterRuleBuilder::default()
+                                 .action(FilterRuleAction::Drop)
+                                 .quick(true)
+                                 .af(AddrFamily::Ipv6))?);
+        }
+        rules.push(build(pass().interface(self.tunnel.as_str()))?);
+        for &(endpoint, protocol) in self.endpoints.iter() {
+            let prefix_len = if let IpAddress::Ipv6(_) = endpoint.addr { 128 } else { 32 };
+            let proto = if protocol == IpProtocol::Tcp { Proto::Tcp } else { Proto::Udp };
+            rules.push(build(pass().direction(Direction::Out)
+                                   .proto(proto)
+                                   .to(Endpoint::new(ip(&endpoint.addr, prefix_len)?, Port::from(endpoint.port))))?);
+        }
+        if self.dhcp {
+            rules.push(build(pass().proto(Proto::Udp)
+                                   .from(Endpoint::new(Ip::Any, Port::from(68)))
+                                   .to(Endpoint::new(Ip::Any, Port::from(67))))?);
+            rules.push(build(pass().proto(Proto::Udp)
+                                   .from(Endpoint::new(Ip::Any, Port::from(546)))
+                                   .to(Endpoint::new(Ip::Any, Port::from(547))))?);
+        }
+        for cidr in self.lan.iter() {
+            rules.push(build(pass().direction(Direction::Out).to(network(cidr)?))?);
+            rules.push(build(pass().direction(Direction::In).from(network(cidr)?))?);
+        }
+        rules.push(build(FilterRuleBuilder::default().action(FilterRuleAction::Drop).quick(true))?);
+
+        let mut pf = PfCtl::new().map_err(pf_error)?;
+        pf.try_add_anchor(ANCHOR, AnchorKind::Filter).map_err(pf_error)?;
+        let mut change = AnchorChange::new();
+        change.set_filter_rules(rules);
+        pf.set_rules(ANCHOR, change).map_err(pf_error)?;
+
+        let enabled = match pf.enable() {
+            Ok(()) => true,
+            Err(ref e) if matches!(e.kind(), ErrorKind::StateAlreadyActive) => false,
+            Err(e) => return Err(pf_error(e)),
+        };
+        // Applying again must not forget that pf was off before the first time.
+        if enabled || fs::metadata(STATE_FILE).is_err() {
+            fs::write(STATE_FILE, if enabled { "1" } else { "0" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Flush and remove the kill switch anchor, and disable pf if `apply` enabled it.
+pub fn remove() -> Result<(), io::Error> {
+    let mut pf = PfCtl::new().map_err(pf_error)?;
+    // Flushing fails when there is no such anchor, which is fine.
+    let _ = pf.flush_rules(ANCHOR, RulesetKind::Filter);
+    pf.try_remove_anchor(ANCHOR, AnchorKind::Filter).map_err(pf_error)?;
+
+    match fs::read_to_string(STATE_FILE) {
+        Ok(state) => {
+            if state.trim() == "1" {
+                pf.try_disable().map_err(pf_error)?;
+            }
+            fs::remove_file(STATE_FILE)
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn is_active() -> Result<bool, io::Error> {
+    match fs::metadata(STATE_FILE) {
+        Ok(_) => Ok(true),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,291 @@
+//! Gateway mode: masquerade a LAN out through the tunnel and forward ports in.
+//!
+//! The rules live in an nftables `inet` table of their own, rebuilt in a single
+//! transaction on every change and removed once the `Nat` is dropped.
+//!
+//! ```no_run
+//! use znet::interface::Interface;
+//! use znet::firewall::nat::Nat;
+//! use smoltcp::wire::{IpAddress, IpEndpoint, IpProtocol};
+//!
+//! let lan = Interface::with_name("eth1").unwrap();
+//! let tunnel = Interface::with_name("wg0").unwrap();
+//!
+//! let mut nat = Nat::new();
+//! nat.enable_forwarding().unwrap();
+//! nat.masquerade(&lan, &tunnel).unwrap();
+//! nat.port_forward(&tunnel, IpProtocol::Tcp, 8080, IpEndpoint::new(IpAddress::v4(192, 168, 1, 10), 80)).unwrap();
+//! ```
+use super::nft::{Batch, Hook, Rule};
+
+use crate::sys;
+use crate::interface::{AddrScope, Interface};
+use crate::sysctl::{self, SysctlGuard};
+
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, IpProtocol};
+
+use std::io;
+use std::fmt;
+
+
+pub const TABLE: &str = "znet_nat";
+
+fn is_ipv6(addr: &IpAddress) -> bool {
+    matches!(*addr, IpAddress::Ipv6(_))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NatRule {
+    // Traffic from the networks of `lan` leaving through `out`. The networks are the
+    // ones `lan` had when the rule was added.
+    Masquerade { lan: String, networks: Vec<IpCidr>, out: String },
+    // `protocol` traffic to `port` arriving on `iface` goes to `to`.
+    PortForward { iface: String, protocol: IpProtocol, port: u16, to: IpEndpoint },
+}
+
+impl fmt::Display for NatRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NatRule::Masquerade { ref lan, ref out, .. } => write!(f, "masquerade {} -> {}", lan, out),
+            NatRule::PortForward { ref iface, protocol, port, to } => {
+                write!(f, "forward {} {}/{} -> {}", iface, port, protocol, to)
+            },
+        }
+    }
+}
+
+
+/// The NAT rules of this process, there should be only one `Nat` at a time.
+#[derive(Debug, Default)]
+pub struct Nat {
+    rules: Vec<NatRule>,
+    forwarding: Option<SysctlGuard>,
+}
+
+impl Nat {
+    pub fn new() -> Nat {
+        Nat { rules: Vec::new(), forwarding: None }
+    }
+
+    pub fn rules(&self) -> &[NatRule] {
+        &self.rules
+    }
+
+    /// Turn IPv4 and IPv6 forwarding on until `clear` or drop.
+    pub fn enable_forwarding(&mut self) -> Result<(), io::Error> {
+        if self.forwarding.is_none() {
+            self.forwarding = Some(sysctl::enable_forwarding()?);
+        }
+        Ok(())
+    }
+
+    pub fn masquerade(&mut self, lan: &Interface, out: &Interface) -> Result<(), io::Error> {
+        let networks = lan.addr_info()
+                          .iter()
+                          .filter(|addr| addr.scope == AddrScope::Global || addr.scope == AddrScope::Site)
+                          .map(|addr| addr.cidr)
+                          .collect::<Vec<IpCidr>>();
+        if networks.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{} has no address to masquerade", lan.name())));
+        }
+
+        self.add(NatRule::Masquerade { lan: lan.name(), networks, out: out.name() })
+    }
+
+    pub fn remove_masquerade(&mut self, lan: &Interface, out: &Interface) -> Result<(), io::Error> {
+        let (lan, out) = (lan.name(), out.name());
+        self.remove_where(|rule| match *rule {
+            NatRule::Masquerade { lan: ref l, out: ref o, .. } => *l == lan && *o == out,
+            _ => false,
+        })
+    }
+
+    pub fn port_forward(&mut self, iface: &Interface, protocol: IpProtocol, port: u16, to: IpEndpoint) -> Result<(), io::Error> {
+        match protocol {
+            IpProtocol::Tcp | IpProtocol::Udp => { },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "only TCP and UDP ports can be forwarded")),
+        }
+        if to.addr.is_unspecified() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot forward a port to an unspecified address"));
+        }
+        // The rule only matches packets of the family of `to`, which `iface` has to receive.
+        let ipv6 = is_ipv6(&to.addr);
+        if !iface.addr_info().is_empty() && !iface.addr_info().iter().any(|addr| is_ipv6(&addr.cidr.address()) == ipv6) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{} has no {} address to forward {} from", iface.name(),
+                                              if ipv6 { "IPv6" } else { "IPv4" }, port)));
+        }
+        self.add(NatRule::PortForward { iface: iface.name(), protocol, port, to })
+    }
+
+    pub fn remove_port_forward(&mut self, iface: &Interface, protocol: IpProtocol, port: u16) -> Result<(), io::Error> {
+        let name = iface.name();
+        self.remove_where(|rule| match *rule {
+            NatRule::PortForward { ref iface, protocol: p, port: n, .. } => *iface == name && p == protocol && n == port,
+            _ => false,
+        })
+    }
+
+    /// Remove every rule and restore forwarding.
+    pub fn clear(&mut self) -> Result<(), io::Error> {
+        self.rules.clear();
+        self.forwarding = None;
+        remove_all()
+    }
+
+    fn add(&mut self, rule: NatRule) -> Result<(), io::Error> {
+        if self.rules.contains(&rule) {
+            return Ok(());
+        }
+        self.rules.push(rule);
+        if let Err(e) = self.commit() {
+            self.rules.pop();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn remove_where<F: Fn(&NatRule) -> bool>(&mut self, predicate: F) -> Result<(), io::Error> {
+        let old = self.rules.clone();
+        self.rules.retain(|rule| !predicate(rule));
+        if let Err(e) = self.commit() {
+            self.rules = old;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn commit(&self) -> Result<(), io::Error> {
+        let mut batch = Batch::new(sys::NFPROTO_INET);
+        batch.flush_table(TABLE);
+        if self.rules.is_empty() {
+            return batch.commit();
+        }
+
+        batch.add_table(TABLE);
+        batch.add_chain(TABLE, "prerouting", Some(Hook {
+            hooknum: sys::NF_INET_PRE_ROUTING,
+            priority: sys::NF_IP_PRI_NAT_DST,
+            kind: "nat",
+            policy: sys::NF_ACCEPT,
+        }));
+        batch.add_chain(TABLE, "postrouting", Some(Hook {
+            hooknum: sys::NF_INET_POST_ROUTING,
+            priority: sys::NF_IP_PRI_NAT_SRC,
+            kind: "nat",
+            policy: sys::NF_ACCEPT,
+        }));
+
+        for rule in self.rules.iter() {
+            match *rule {
+                // The input interface is unknown after routing, so the source networks stand in for it.
+                NatRule::Masquerade { ref networks, ref out, .. } => {
+                    for network in networks {
+                        batch.add_rule(TABLE, "postrouting", Rule::new().saddr(network).oifname(out).masquerade());
+                    }
+                },
+                NatRule::PortForward { ref iface, protocol, port, to } => {
+                    let nfproto = if is_ipv6(&to.addr) { sys::NFPROTO_IPV6 } else { sys::NFPROTO_IPV4 };
+                    let rule = Rule::new().iifname(iface)
+                                          .nfproto(nfproto)
+                                          .l4proto(u8::from(protocol))
+                                          .dport(port)
+                                          .dnat(&to);
+                    batch.add_rule(TABLE, "prerouting", rule);
+                },
+            }
+        }
+
+        batch.commit()
+    }
+}
+
+impl Drop for Nat {
+    fn drop(&mut self) {
+        if self.rules.is_empty() {
+            return;
+        }
+        if let Err(e) = remove_all() {
+            error!("failed to remove the NAT rules: {}", e);
+        }
+    }
+}
+
+
+/// Remove the NAT table, e.g. one left behind by a process that crashed.
+pub fn remove_all() -> Result<(), io::Error> {
+    let mut batch = Batch::new(sys::NFPROTO_INET);
+    batch.flush_table(TABLE);
+    batch.commit()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::nft;
+    use crate::interface::link::LinkBuilder;
+    use crate::netns::scratch::Scratch;
+
+    fn table_exists() -> bool {
+        nft::table_exists(sys::NFPROTO_INET, TABLE).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn rules() {
+        let netns = Scratch::new();
+        netns.run(|| LinkBuilder::veth("lan0", "wan0").up().create().map(|_| ())).unwrap().unwrap();
+        netns.ip(&["addr", "add", "192.168.7.1/24", "dev", "lan0"]);
+        netns.ip(&["addr", "add", "10.9.0.2/24", "dev", "wan0"]);
+
+        netns.run(|| {
+            let lan = Interface::with_name("lan0").unwrap();
+            let wan = Interface::with_name("wan0").unwrap();
+            let web = IpEndpoint::new(IpAddress::v4(192, 168, 7, 10), 80);
+            let mut nat = Nat::new();
+
+            let err = nat.port_forward(&wan, IpProtocol::Tcp, 8080, IpEndpoint::new(IpAddress::v4(0, 0, 0, 0), 80)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let err = nat.port_forward(&wan, IpProtocol::Tcp, 8080, IpEndpoint::new(IpAddress::Unspecified, 80)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            // wan0 has no IPv6 address yet, the rule would never match.
+            let v6 = IpEndpoint::new(IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 10), 80);
+            let err = nat.port_forward(&wan, IpProtocol::Tcp, 8080, v6).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let err = nat.port_forward(&wan, IpProtocol::Icmp, 0, web).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(nat.rules().is_empty());
+            assert!(!table_exists());
+
+            nat.masquerade(&lan, &wan).unwrap();
+            nat.port_forward(&wan, IpProtocol::Tcp, 8080, web).unwrap();
+            // Adding a rule twice keeps one.
+            nat.port_forward(&wan, IpProtocol::Tcp, 8080, web).unwrap();
+            assert_eq!(nat.rules().len(), 2);
+            assert!(table_exists());
+
+            // A rule the kernel refuses is not kept.
+            let bad = NatRule::PortForward { iface: wan.name(), protocol: IpProtocol::Udp, port: 53, to: IpEndpoint::new(IpAddress::Unspecified, 53) };
+            assert!(nat.add(bad.clone()).is_err());
+            assert_eq!(nat.rules().len(), 2);
+
+            // A failed removal leaves the rules as they were.
+            nat.rules.push(bad);
+            assert!(nat.remove_masquerade(&lan, &wan).is_err());
+            assert_eq!(nat.rules().len(), 3);
+            nat.rules.pop();
+
+            nat.remove_masquerade(&lan, &wan).unwrap();
+            assert_eq!(nat.rules(), &[NatRule::PortForward { iface: "wan0".to_string(), protocol: IpProtocol::Tcp, port: 8080, to: web }]);
+            nat.remove_port_forward(&wan, IpProtocol::Tcp, 8080).unwrap();
+            assert!(nat.rules().is_empty());
+            assert!(!table_exists());
+
+            nat.port_forward(&wan, IpProtocol::Udp, 51820, web).unwrap();
+            drop(nat);
+            assert!(!table_exists());
+        }).unwrap();
+    }
+}
@@ -0,0 +1,357 @@
+// nf_tables over NETLINK_NETFILTER: tables, base chains and rules sent as one transaction.
+use crate::sys;
+use crate::netlink::{MessageBuilder, NetlinkSocket};
+
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint};
+
+use std::io;
+
+
+fn message(kind: sys::c_int, flags: sys::c_int, family: u8) -> MessageBuilder {
+    let nfgen = sys::netlink::nfgenmsg {
+        nfgen_family: family,
+        version: sys::NFNETLINK_V0 as u8,
+        res_id: 0,
+    };
+    let kind = ((sys::NFNL_SUBSYS_NFTABLES << 8) | kind) as u16;
+    MessageBuilder::new(kind, (flags | sys::NLM_F_ACK) as u16).header(&nfgen)
+}
+
+// The marker messages around a batch, they are not acknowledged.
+fn marker(kind: sys::c_int) -> Vec<u8> {
+    let nfgen = sys::netlink::nfgenmsg {
+        nfgen_family: sys::AF_UNSPEC as u8,
+        version: sys::NFNETLINK_V0 as u8,
+        res_id: (sys::NFNL_SUBSYS_NFTABLES as u16).to_be(),
+    };
+    MessageBuilder::new(kind as u16, 0).header(&nfgen).build()
+}
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hook {
+    pub hooknum: sys::c_int,
+    pub priority: i32,
+    // "filter", "nat" or "route".
+    pub kind: &'static str,
+    pub policy: sys::c_int,
+}
+
+/// Changes to the ruleset, applied all at once by `commit`.
+pub struct Batch {
+    family: u8,
+    msgs: Vec<Vec<u8>>,
+}
+
+impl Batch {
+    pub fn new(family: sys::c_int) -> Batch {
+        Batch { family: family as u8, msgs: vec![marker(sys::NFNL_MSG_BATCH_BEGIN)] }
+    }
+
+    pub fn add_table(&mut self, table: &str) {
+        let msg = message(sys::NFT_MSG_NEWTABLE, sys::NLM_F_CREATE, self.family)
+            .attr_str(sys::netlink::NFTA_TABLE_NAME, table)
+            .build();
+        self.msgs.push(msg);
+    }
+
+    pub fn delete_table(&mut self, table: &str) {
+        let msg = message(sys::NFT_MSG_DELTABLE, 0, self.family)
+            .attr_str(sys::netlink::NFTA_TABLE_NAME, table)
+            .build();
+        self.msgs.push(msg);
+    }
+
+    /// Delete the table with everything in it, whether it exists or not.
+    pub fn flush_table(&mut self, table: &str) {
+        // Creating it first makes the deletion succeed either way.
+        self.add_table(table);
+        self.delete_table(table);
+    }
+
+    pub fn add_chain(&mut self, table: &str, chain: &str, hook: Option<Hook>) {
+        let mut msg = message(sys::NFT_MSG_NEWCHAIN, sys::NLM_F_CREATE, self.family)
+            .attr_str(sys::netlink::NFTA_CHAIN_TABLE, table)
+            .attr_str(sys::netlink::NFTA_CHAIN_NAME, chain);
+        if let Some(hook) = hook {
+            msg = msg.begin_nested(sys::netlink::NFTA_CHAIN_HOOK)
+                     .attr_be32(sys::netlink::NFTA_HOOK_HOOKNUM, hook.hooknum as u32)
+                     .attr_be32(sys::netlink::NFTA_HOOK_PRIORITY, hook.priority as u32)
+                     .end_nested()
+                     .attr_be32(sys::netlink::NFTA_CHAIN_POLICY, hook.policy as u32)
+                     .attr_str(sys::netlink::NFTA_CHAIN_TYPE, hook.kind);
+        }
+        self.msgs.push(msg.build());
+    }
+
+    /// Append a rule to a chain.
+    pub fn add_rule(&mut self, table: &str, chain: &str, rule: Rule) {
+        let mut msg = message(sys::NFT_MSG_NEWRULE, sys::NLM_F_CREATE | sys::NLM_F_APPEND, self.family)
+            .attr_str(sys::netlink::NFTA_RULE_TABLE, table)
+            .attr_str(sys::netlink::NFTA_RULE_CHAIN, chain)
+            .begin_nested(sys::netlink::NFTA_RULE_EXPRESSIONS);
+        for expr in rule.exprs {
+            msg = expr.encode(msg);
+        }
+        self.msgs.push(msg.end_nested().build());
+    }
+
+    pub fn commit(mut self) -> Result<(), io::Error> {
+        self.msgs.push(marker(sys::NFNL_MSG_BATCH_END));
+        let mut socket = NetlinkSocket::new(sys::NETLINK_NETFILTER)?;
+        socket.batch(self.msgs)
+    }
+}
+
+pub fn table_exists(family: sys::c_int, table: &str) -> Result<bool, io::Error> {
+    let msg = message(sys::NFT_MSG_GETTABLE, 0, family as u8)
+        .attr_str(sys::netlink::NFTA_TABLE_NAME, table)
+        .build();
+    let mut socket = NetlinkSocket::new(sys::NETLINK_NETFILTER)?;
+    match socket.request(msg) {
+        Ok(_) => Ok(true),
+        Err(ref e) if e.raw_os_error() == Some(sys::ENOENT) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Meta(sys::c_int),
+    Ct(sys::c_int),
+    Payload { base: sys::c_int, offset: u32, len: u32 },
+    Bitwise { mask: Vec<u8>, xor: Vec<u8> },
+    Cmp { op: sys::c_int, data: Vec<u8> },
+    Verdict(sys::c_int),
+    Immediate { reg: sys::c_int, data: Vec<u8> },
+    // Address in NFT_REG_1, port (when there is one) in NFT_REG_2.
+    Nat { kind: sys::c_int, family: sys::c_int, port: bool },
+    Masq,
+}
+
+impl Expr {
+    fn name(&self) -> &'static str {
+        match *self {
+            Expr::Meta(_) => "meta",
+            Expr::Ct(_) => "ct",
+            Expr::Payload { .. } => "payload",
+            Expr::Bitwise { .. } => "bitwise",
+            Expr::Cmp { .. } => "cmp",
+            Expr::Verdict(_) | Expr::Immediate { .. } => "immediate",
+            Expr::Nat { .. } => "nat",
+            Expr::Masq => "masq",
+        }
+    }
+
+    fn encode(self, msg: MessageBuilder) -> MessageBuilder {
+        let reg = sys::NFT_REG_1 as u32;
+        let msg = msg.begin_nested(sys::netlink::NFTA_LIST_ELEM)
+                     .attr_str(sys::netlink::NFTA_EXPR_NAME, self.name())
+                     .begin_nested(sys::netlink::NFTA_EXPR_DATA);
+        let msg = match self {
+            Expr::Meta(key) => {
+                msg.attr_be32(sys::netlink::NFTA_META_KEY, key as u32)
+                   .attr_be32(sys::netlink::NFTA_META_DREG, reg)
+            },
+            Expr::Ct(key) => {
+                msg.attr_be32(sys::netlink::NFTA_CT_KEY, key as u32)
+                   .attr_be32(sys::netlink::NFTA_CT_DREG, reg)
+            },
+            Expr::Payload { base, offset, len } => {
+                msg.attr_be32(sys::netlink::NFTA_PAYLOAD_DREG, reg)
+                   .attr_be32(sys::netlink::NFTA_PAYLOAD_BASE, base as u32)
+                   .attr_be32(sys::netlink::NFTA_PAYLOAD_OFFSET, offset)
+                   .attr_be32(sys::netlink::NFTA_PAYLOAD_LEN, len)
+            },
+            Expr::Bitwise { mask, xor } => {
+                msg.attr_be32(sys::netlink::NFTA_BITWISE_SREG, reg)
+                   .attr_be32(sys::netlink::NFTA_BITWISE_DREG, reg)
+                   .attr_be32(sys::netlink::NFTA_BITWISE_LEN, mask.len() as u32)
+                   .begin_nested(sys::netlink::NFTA_BITWISE_MASK)
+                   .attr(sys::netlink::NFTA_DATA_VALUE, &mask)
+                   .end_nested()
+                   .begin_nested(sys::netlink::NFTA_BITWISE_XOR)
+                   .attr(sys::netlink::NFTA_DATA_VALUE, &xor)
+                   .end_nested()
+            },
+            Expr::Cmp { op, data } => {
+                msg.attr_be32(sys::netlink::NFTA_CMP_SREG, reg)
+                   .attr_be32(sys::netlink::NFTA_CMP_OP, op as u32)
+                   .begin_nested(sys::netlink::NFTA_CMP_DATA)
+                   .attr(sys::netlink::NFTA_DATA_VALUE, &data)
+                   .end_nested()
+            },
+            Expr::Verdict(code) => {
+                msg.attr_be32(sys::netlink::NFTA_IMMEDIATE_DREG, sys::NFT_REG_VERDICT as u32)
+                   .begin_nested(sys::netlink::NFTA_IMMEDIATE_DATA)
+                   .begin_nested(sys::netlink::NFTA_DATA_VERDICT)
+                   .attr_be32(sys::netlink::NFTA_VERDICT_CODE, code as u32)
+                   .end_nested()
+                   .end_nested()
+            },
+            Expr::Immediate { reg, data } => {
+                msg.attr_be32(sys::netlink::NFTA_IMMEDIATE_DREG, reg as u32)
+                   .begin_nested(sys::netlink::NFTA_IMMEDIATE_DATA)
+                   .attr(sys::netlink::NFTA_DATA_VALUE, &data)
+                   .end_nested()
+            },
+            Expr::Nat { kind, family, port } => {
+                let msg = msg.attr_be32(sys::netlink::NFTA_NAT_TYPE, kind as u32)
+                             .attr_be32(sys::netlink::NFTA_NAT_FAMILY, family as u32)
+                             .attr_be32(sys::netlink::NFTA_NAT_REG_ADDR_MIN, reg);
+                if port {
+                    msg.attr_be32(sys::netlink::NFTA_NAT_REG_PROTO_MIN, sys::NFT_REG_2 as u32)
+                } else {
+                    msg
+                }
+            },
+            Expr::Masq => msg,
+        };
+        msg.end_nested().end_nested()
+    }
+}
+
+
+// Header offsets, for `payload` loads.
+const IPV4_SADDR: u32 = 12;
+const IPV4_DADDR: u32 = 16;
+const IPV6_SADDR: u32 = 8;
+const IPV6_DADDR: u32 = 24;
+const TH_SPORT: u32 = 0;
+const TH_DPORT: u32 = 2;
+
+/// A rule: matches, all of which have to hold, followed by a verdict or statement.
+#[derive(Clone, Debug, Default)]
+pub struct Rule {
+    exprs: Vec<Expr>,
+}
+
+impl Rule {
+    pub fn new() -> Rule {
+        Rule { exprs: Vec::new() }
+    }
+
+    fn push(mut self, expr: Expr) -> Rule {
+        self.exprs.push(expr);
+        self
+    }
+
+    fn cmp(self, op: sys::c_int, data: &[u8]) -> Rule {
+        self.push(Expr::Cmp { op, data: data.to_vec() })
+    }
+
+    fn ifname(self, key: sys::c_int, ifname: &str) -> Rule {
+        let mut name = [0u8; sys::IFNAMSIZ];
+        let len = std::cmp::min(ifname.len(), sys::IFNAMSIZ - 1);
+        name[..len].copy_from_slice(&ifname.as_bytes()[..len]);
+        self.push(Expr::Meta(key)).cmp(sys::NFT_CMP_EQ, &name)
+    }
+
+    pub fn iifname(self, ifname: &str) -> Rule {
+        self.ifname(sys::NFT_META_IIFNAME, ifname)
+    }
+
+    pub fn oifname(self, ifname: &str) -> Rule {
+        self.ifname(sys::NFT_META_OIFNAME, ifname)
+    }
+
+    /// `meta nfproto ipv4` or `ipv6`.
+    pub fn nfproto(self, nfproto: sys::c_int) -> Rule {
+        self.push(Expr::Meta(sys::NFT_META_NFPROTO)).cmp(sys::NFT_CMP_EQ, &[nfproto as u8])
+    }
+
+    /// `meta l4proto udp` and so on.
+    pub fn l4proto(self, protocol: u8) -> Rule {
+        self.push(Expr::Meta(sys::NFT_META_L4PROTO)).cmp(sys::NFT_CMP_EQ, &[protocol])
+    }
+
+    pub fn mark(self, mark: u32) -> Rule {
+        self.push(Expr::Meta(sys::NFT_META_MARK)).cmp(sys::NFT_CMP_EQ, &mark.to_ne_bytes())
+    }
+
+    // The family match comes first so the payload is known to be the right header.
+    fn addr(self, cidr: &IpCidr, source: bool) -> Rule {
+        let (rule, offset, len) = match cidr.address() {
+            IpAddress::Ipv4(_) => {
+                (self.nfproto(sys::NFPROTO_IPV4), if source { IPV4_SADDR } else { IPV4_DADDR }, 4)
+            },
+            IpAddress::Ipv6(_) => {
+                (self.nfproto(sys::NFPROTO_IPV6), if source { IPV6_SADDR } else { IPV6_DADDR }, 16)
+            },
+            _ => return self,
+        };
+        let prefix_len = cidr.prefix_len() as usize;
+        if prefix_len == 0 {
+            return rule;
+        }
+
+        let mut rule = rule.push(Expr::Payload { base: sys::NFT_PAYLOAD_NETWORK_HEADER, offset, len });
+        let mut network = cidr.address().as_bytes().to_vec();
+        if prefix_len < len as usize * 8 {
+            let mut mask = vec![0u8; len as usize];
+            for (i, byte) in mask.iter_mut().enumerate() {
+                let bits = std::cmp::min(prefix_len.saturating_sub(i * 8), 8);
+                *byte = (0xff00u16 >> bits) as u8;
+            }
+            for (byte, mask) in network.iter_mut().zip(mask.iter()) {
+                *byte &= *mask;
+            }
+            rule = rule.push(Expr::Bitwise { xor: vec![0u8; mask.len()], mask });
+        }
+        rule.cmp(sys::NFT_CMP_EQ, &network)
+    }
+
+    pub fn saddr(self, cidr: &IpCidr) -> Rule {
+        self.addr(cidr, true)
+    }
+
+    pub fn daddr(self, cidr: &IpCidr) -> Rule {
+        self.addr(cidr, false)
+    }
+
+    /// Needs a preceding `l4proto` match.
+    pub fn sport(self, port: u16) -> Rule {
+        self.push(Expr::Payload { base: sys::NFT_PAYLOAD_TRANSPORT_HEADER, offset: TH_SPORT, len: 2 })
+            .cmp(sys::NFT_CMP_EQ, &port.to_be_bytes())
+    }
+
+    /// Needs a preceding `l4proto` match.
+    pub fn dport(self, port: u16) -> Rule {
+        self.push(Expr::Payload { base: sys::NFT_PAYLOAD_TRANSPORT_HEADER, offset: TH_DPORT, len: 2 })
+            .cmp(sys::NFT_CMP_EQ, &port.to_be_bytes())
+    }
+
+    /// `ct state <states>`, any of the NF_CT_STATE_* bits.
+    pub fn ct_state(self, states: u32) -> Rule {
+        self.push(Expr::Ct(sys::NFT_CT_STATE))
+            .push(Expr::Bitwise { mask: states.to_ne_bytes().to_vec(), xor: vec![0u8; 4] })
+            .cmp(sys::NFT_CMP_NEQ, &[0u8; 4])
+    }
+
+    pub fn masquerade(self) -> Rule {
+        self.push(Expr::Masq)
+    }
+
+    /// `dnat to <addr>:<port>`, port 0 keeps the destination port. Needs a preceding
+    /// match on the address family.
+    pub fn dnat(self, to: &IpEndpoint) -> Rule {
+        let family = match to.addr {
+            IpAddress::Ipv6(_) => sys::NFPROTO_IPV6,
+            _ => sys::NFPROTO_IPV4,
+        };
+        let mut rule = self.push(Expr::Immediate { reg: sys::NFT_REG_1, data: to.addr.as_bytes().to_vec() });
+        if to.port != 0 {
+            rule = rule.push(Expr::Immediate { reg: sys::NFT_REG_2, data: to.port.to_be_bytes().to_vec() });
+        }
+        rule.push(Expr::Nat { kind: sys::NFT_NAT_DNAT, family, port: to.port != 0 })
+    }
+
+    pub fn accept(self) -> Rule {
+        self.push(Expr::Verdict(sys::NF_ACCEPT))
+    }
+
+    pub fn drop(self) -> Rule {
+        self.push(Expr::Verdict(sys::NF_DROP))
+    }
+}
@@ -1,4 +1,6 @@
 use crate::sys;
+#[cfg(target_os = "linux")]
+use crate::netns::{self, NetNs};
 
 use smoltcp;
 use smoltcp::wire::{IpAddress, Ipv6Address, IpCidr, Ipv4Cidr, Ipv6Cidr, EthernetAddress};
@@ -7,10 +9,37 @@ use nix::net::if_::InterfaceFlags;
 use nix::sys::socket::SockAddr;
 
 use std::{io, fmt};
+#[cfg(not(target_os = "linux"))]
 use std::ffi::CString;
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 
+mod stats;
+pub use self::stats::{InterfaceStats, Rates, StatsSampler};
+
+mod addr;
+pub use self::addr::{AddrFlags, AddrScope, InterfaceAddr, Lifetime};
+
+mod info;
+pub use self::info::InterfaceKind;
+#[cfg(target_os = "linux")]
+pub use self::info::{LinkInfo, OperState};
+
+#[cfg(target_os = "linux")]
+mod rtnl;
+#[cfg(target_os = "linux")]
+mod monitor;
+#[cfg(target_os = "linux")]
+mod tunnel;
+#[cfg(target_os = "linux")]
+pub use self::tunnel::{TunnelKind, TunnelParams};
+#[cfg(target_os = "linux")]
+pub mod link;
+#[cfg(target_os = "linux")]
+pub use self::monitor::{InterfaceEvent, Monitor};
+
 pub type Flags = InterfaceFlags;
 
 
@@ -18,7 +47,7 @@ pub fn ipv6_cidr_from_ipv6_mask(address: Ipv6Addr, netmask: Ipv6Addr) -> Result<
     const IPV6_SEGMENT_BITS: u8 = 16;
 
     let mask = netmask.segments();
-    let mut mask_iter = mask.into_iter();
+    let mut mask_iter = mask.iter();
 
     let mut prefix_len = 0u8;
     for &segment in &mut mask_iter {
@@ -75,14 +104,30 @@ pub struct Interface {
     hwaddr: Option<EthernetAddress>,
     dstaddr: Option<IpCidr>,
     addrs: Vec<IpCidr>,
+    addr_info: Vec<InterfaceAddr>,
+    // Set by `interfaces_in`, the namespace the name and index belong to.
+    #[cfg(target_os = "linux")]
+    netns: Option<Arc<NetNs>>,
 }
 
 impl Interface {
+    #[cfg(target_os = "linux")]
+    pub fn with_index(ifindex: u32) -> Result<Interface, io::Error> {
+        rtnl::get(ifindex, None)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn with_name(ifname: &str) -> Result<Interface, io::Error> {
+        rtnl::get(0, Some(ifname))
+    }
+
+    #[cfg(not(target_os = "linux"))]
     pub fn with_index(ifindex: u32) -> Result<Interface, io::Error> {
         let ifname = sys::if_index_to_name(ifindex);
         Interface::with_name(&ifname)
     }
 
+    #[cfg(not(target_os = "linux"))]
     pub fn with_name(ifname: &str) -> Result<Interface, io::Error> {
         let index: u32 = unsafe { sys::if_nametoindex(CString::new(ifname).unwrap().as_ptr()) };
         let mtu: u32 = match sys::if_name_to_mtu(ifname){
@@ -99,6 +144,7 @@ impl Interface {
             hwaddr   : None,
             dstaddr  : None,
             addrs: vec![],
+            addr_info: vec![],
         };
         
         for ifaddr in getifaddrs().unwrap() {
@@ -116,13 +162,82 @@ impl Interface {
         self.flags.contains(Flags::IFF_LOOPBACK)
     }
 
+    /// The network namespace of an interface from `interfaces_in`, `None`
+    /// for the one of the calling thread.
+    #[cfg(target_os = "linux")]
+    pub fn netns(&self) -> Option<&NetNs> {
+        self.netns.as_deref()
+    }
+
+    // Lookups by name or index have to run where the interface lives.
+    #[cfg(target_os = "linux")]
+    fn run_in_netns<F, T>(&self, f: F) -> Result<T, io::Error>
+        where F: FnOnce() -> Result<T, io::Error> + Send,
+              T: Send
+    {
+        netns::run_in(self.netns(), f)?
+    }
+
+    #[cfg(target_os = "linux")]
     pub fn is_tap(&self) -> bool {
-        self.flags.contains(Flags::IFF_BROADCAST)
+        self.kind() == InterfaceKind::Tap
     }
 
+    #[cfg(target_os = "linux")]
     pub fn is_tun(&self) -> bool {
-        !self.is_loopback() 
-        && !self.is_tap()
+        self.kind() == InterfaceKind::Tun
+    }
+
+    // ARPHRD_* of the device, the ifi_type rtnetlink reports for it.
+    #[cfg(target_os = "linux")]
+    pub fn hwtype(&self) -> Result<u16, io::Error> {
+        self.link_info().map(|info| info.hwtype)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn link_info(&self) -> Result<LinkInfo, io::Error> {
+        self.run_in_netns(|| rtnl::get_info(self))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn kind(&self) -> InterfaceKind {
+        match self.link_info() {
+            Ok(info) => info.kind,
+            Err(_) => InterfaceKind::Unknown,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn kind(&self) -> InterfaceKind {
+        info::guess_kind(self)
+    }
+
+    /// The endpoints, keys and underlay of a GRE, IPIP, SIT, ip6tnl or VXLAN link.
+    #[cfg(target_os = "linux")]
+    pub fn tunnel_params(&self) -> Result<Option<TunnelParams>, io::Error> {
+        self.run_in_netns(|| rtnl::get_tunnel_params(self.index))
+    }
+
+    /// The ports of a bridge or bond.
+    #[cfg(target_os = "linux")]
+    pub fn slaves(&self) -> Result<Vec<Interface>, io::Error> {
+        let mut slaves = self.run_in_netns(|| rtnl::slaves(self.index))?;
+        for slave in &mut slaves {
+            slave.netns = self.netns.clone();
+        }
+        Ok(slaves)
+    }
+
+    // tuntaposx names its devices tapN, utun is the only TUN flavour on macOS.
+    #[cfg(not(target_os = "linux"))]
+    pub fn is_tap(&self) -> bool {
+        self.name.starts_with("tap")
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn is_tun(&self) -> bool {
+        !self.is_loopback()
+        && (self.name.starts_with("utun") || self.name.starts_with("tun"))
         && self.flags.contains(Flags::IFF_POINTOPOINT)
     }
 
@@ -153,6 +268,45 @@ impl Interface {
     pub fn addrs(&self) -> &Vec<IpCidr> {
         &self.addrs
     }
+
+    /// The addresses with scope, flags, lifetimes and labels.
+    pub fn addr_info(&self) -> &[InterfaceAddr] {
+        &self.addr_info
+    }
+
+    // Add or (when the kernel re-announces it) update an address.
+    fn add_addr(&mut self, addr: InterfaceAddr) {
+        if !self.addrs.contains(&addr.cidr) {
+            self.addrs.push(addr.cidr);
+        }
+        if addr.peer.is_some() {
+            self.dstaddr = addr.peer;
+        }
+        match self.addr_info.iter().position(|info| info.cidr == addr.cidr) {
+            Some(pos) => self.addr_info[pos] = addr,
+            None => self.addr_info.push(addr),
+        }
+    }
+
+    fn remove_addr(&mut self, cidr: &IpCidr) {
+        self.addrs.retain(|addr| addr != cidr);
+        if let Some(info) = self.addr_info.iter().find(|info| info.cidr == *cidr) {
+            if info.peer.is_some() && info.peer == self.dstaddr {
+                self.dstaddr = None;
+            }
+        }
+        self.addr_info.retain(|info| info.cidr != *cidr);
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn stats(&self) -> Result<InterfaceStats, io::Error> {
+        self.run_in_netns(|| stats::if_name_to_stats(&self.name))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn stats(&self) -> Result<InterfaceStats, io::Error> {
+        stats::if_name_to_stats(&self.name)
+    }
 }
 
 
@@ -164,29 +318,40 @@ impl fmt::Display for Interface {
                 format!("{:?}", self.flags).replace("IFF_", "").replace(" | ", ","),
                 self.mtu,
                 self.index);
-        if self.hwaddr.is_some(){
-            let _ = write!(f, "\n    ether {}", self.hwaddr.unwrap());
+        if let Some(hwaddr) = self.hwaddr {
+            let _ = write!(f, "\n    ether {}", hwaddr);
         }
-        for ip_cidr in self.addrs.iter() {
-            match ip_cidr {
-                &IpCidr::Ipv4(ipv4_cidr) => {
+        for addr in self.addr_info.iter() {
+            match addr.cidr {
+                IpCidr::Ipv4(ipv4_cidr) => {
                     write!(f, "\n    inet {}", ipv4_cidr)?;
-                    if self.flags.contains(Flags::IFF_POINTOPOINT) && self.dstaddr.is_some() {
-                        write!(f, " --> {}", self.dstaddr.unwrap())?;
+                    if let Some(peer) = addr.peer {
+                        write!(f, " --> {}", peer)?;
                     }
                     write!(f, " netmask {}", ipv4_cidr.netmask())?;
-                    if self.flags.contains(Flags::IFF_BROADCAST) {
-                        write!(f, " broadcast {}", ipv4_cidr.broadcast().unwrap())?;
+                    match addr.broadcast {
+                        Some(broadcast) => write!(f, " broadcast {}", broadcast)?,
+                        None if self.flags.contains(Flags::IFF_BROADCAST) => {
+                            if let Some(broadcast) = ipv4_cidr.broadcast() {
+                                write!(f, " broadcast {}", broadcast)?;
+                            }
+                        },
+                        None => { },
                     }
                 }
-                &IpCidr::Ipv6(ipv6_cidr) => {
+                IpCidr::Ipv6(ipv6_cidr) => {
                     write!(f, "\n    inet6 {}", ipv6_cidr)?;
-                    if self.flags.contains(Flags::IFF_POINTOPOINT) && self.dstaddr.is_some() {
-                        write!(f, " --> {}", self.dstaddr.unwrap())?;
+                    if let Some(peer) = addr.peer {
+                        write!(f, " --> {}", peer)?;
                     }
                 }
                 _ => { unreachable!() }
             }
+
+            addr.fmt_details(f)?;
+            if let (Some(valid), Some(preferred)) = (addr.valid_lft, addr.preferred_lft) {
+                write!(f, "\n        valid_lft {} preferred_lft {}", valid, preferred)?;
+            }
         }
 
         Ok(())
@@ -194,8 +359,7 @@ impl fmt::Display for Interface {
 }
 
 fn fill(ifaddr: &InterfaceAddress, iface: &mut Interface){
-    if ifaddr.address.is_some() {
-        let sock_addr = ifaddr.address.unwrap();
+    if let Some(sock_addr) = ifaddr.address {
         match sock_addr {
             SockAddr::Inet(inet_addr) => {
                 let std_ip = inet_addr.to_std().ip();
@@ -216,7 +380,12 @@ fn fill(ifaddr: &InterfaceAddress, iface: &mut Interface){
                     },
                 };
 
-                iface.addrs.push(ip_cidr_from_netmask(IpAddress::from(std_ip), IpAddress::from(netmask)).unwrap());
+                let cidr = ip_cidr_from_netmask(IpAddress::from(std_ip), IpAddress::from(netmask)).unwrap();
+                let mut addr = InterfaceAddr::new(cidr);
+                if let Some(SockAddr::Inet(broadcast)) = ifaddr.broadcast {
+                    addr.broadcast = Some(IpAddress::from(broadcast.to_std().ip()));
+                }
+                iface.add_addr(addr);
             },
             SockAddr::Unix(_) => { },
             #[cfg(any(target_os = "android", target_os = "linux"))]
@@ -239,38 +408,71 @@ fn fill(ifaddr: &InterfaceAddress, iface: &mut Interface){
         }
     }
 
-    if ifaddr.destination.is_some() {
-        let sock_addr = ifaddr.destination.unwrap();
-        match sock_addr {
-            SockAddr::Inet(inet_addr) => {
-                let std_ip = inet_addr.to_std().ip();
+    if let Some(SockAddr::Inet(inet_addr)) = ifaddr.destination {
+        let std_ip = inet_addr.to_std().ip();
 
-                let netmask = match ifaddr.netmask {
-                    Some(inet) => match inet {
-                        SockAddr::Inet(inet_addr) => inet_addr.to_std().ip(),
-                        _ => { unreachable!() }
-                    },
-                    None => {
-                        if std_ip.is_ipv4() {
-                            IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255))
-                        } else if std_ip.is_ipv6() {
-                            IpAddr::V6(Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff))
-                        } else {
-                            unreachable!()
-                        }
-                    },
-                };
+        let netmask = match ifaddr.netmask {
+            Some(inet) => match inet {
+                SockAddr::Inet(inet_addr) => inet_addr.to_std().ip(),
+                _ => { unreachable!() }
+            },
+            None => {
+                if std_ip.is_ipv4() {
+                    IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255))
+                } else if std_ip.is_ipv6() {
+                    IpAddr::V6(Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff, 0xffff))
+                } else {
+                    unreachable!()
+                }
+            },
+        };
 
-                let ip_cidr = ip_cidr_from_netmask(IpAddress::from(std_ip), IpAddress::from(netmask)).unwrap();
+        let ip_cidr = ip_cidr_from_netmask(IpAddress::from(std_ip), IpAddress::from(netmask)).unwrap();
 
-                iface.dstaddr = Some(ip_cidr);
-            },
-            _ => {}
+        iface.dstaddr = Some(ip_cidr);
+        if let Some(addr) = iface.addr_info.last_mut() {
+            addr.peer = Some(ip_cidr);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn interfaces() -> Vec<Interface> {
+    match rtnl::dump() {
+        Ok(ifaces) => ifaces,
+        Err(e) => {
+            warn!("RTM_GETLINK dump failed, falling back to getifaddrs: {}", e);
+            interfaces_from_getifaddrs()
         }
     }
 }
 
+#[cfg(not(target_os = "linux"))]
 pub fn interfaces() -> Vec<Interface> {
+    interfaces_from_getifaddrs()
+}
+
+/// `interfaces()` of another network namespace.
+#[cfg(target_os = "linux")]
+pub fn interfaces_in(netns: Option<&NetNs>) -> Result<Vec<Interface>, io::Error> {
+    let mut ifaces = netns::run_in(netns, rtnl::dump)??;
+    if let Some(netns) = netns {
+        let netns = Arc::new(netns.try_clone()?);
+        for iface in &mut ifaces {
+            iface.netns = Some(netns.clone());
+        }
+    }
+    Ok(ifaces)
+}
+
+pub fn interfaces_filtered<F>(mut predicate: F) -> Vec<Interface>
+    where F: FnMut(&Interface) -> bool
+{
+    interfaces().into_iter().filter(|iface| predicate(iface)).collect()
+}
+
+// getifaddrs(3) followed by an index and mtu lookup for every interface.
+pub fn interfaces_from_getifaddrs() -> Vec<Interface> {
     let mut ifaces: Vec<Interface> = vec![];
     for ifaddr in getifaddrs().unwrap() {
         let name: String = ifaddr.interface_name.clone();
@@ -289,12 +491,15 @@ pub fn interfaces() -> Vec<Interface> {
             let if_mtu   = sys::if_name_to_mtu(&name).unwrap();
             let mut iface = Interface {
                 name     : name.clone(),
-                index    : if_index as u32,
+                index    : if_index,
                 flags    : ifaddr.flags,
                 mtu      : if_mtu as u32,
                 hwaddr   : None,
                 dstaddr  : None,
                 addrs    : vec![],
+                addr_info: vec![],
+                #[cfg(target_os = "linux")]
+                netns    : None,
             };
             fill(&ifaddr, &mut iface);
             ifaces.push(iface);
@@ -0,0 +1,204 @@
+use smoltcp::wire::{IpAddress, IpCidr};
+
+use std::fmt;
+use std::time::Duration;
+
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/rtnetlink.h
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AddrScope {
+    Global,
+    Site,
+    Link,
+    Host,
+    Nowhere,
+    Other(u8),
+}
+
+impl AddrScope {
+    pub fn from_u8(scope: u8) -> AddrScope {
+        match scope {
+            0 => AddrScope::Global,
+            200 => AddrScope::Site,
+            253 => AddrScope::Link,
+            254 => AddrScope::Host,
+            255 => AddrScope::Nowhere,
+            _ => AddrScope::Other(scope),
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            AddrScope::Global => 0,
+            AddrScope::Site => 200,
+            AddrScope::Link => 253,
+            AddrScope::Host => 254,
+            AddrScope::Nowhere => 255,
+            AddrScope::Other(scope) => scope,
+        }
+    }
+
+    /// The scope the kernel would assign, for addresses that did not come from rtnetlink.
+    pub fn from_address(addr: &IpAddress) -> AddrScope {
+        match *addr {
+            IpAddress::Ipv4(ref v4) if v4.is_loopback() => AddrScope::Host,
+            IpAddress::Ipv6(ref v6) if v6.is_loopback() => AddrScope::Host,
+            IpAddress::Ipv6(ref v6) if v6.is_link_local() => AddrScope::Link,
+            _ => AddrScope::Global,
+        }
+    }
+}
+
+impl fmt::Display for AddrScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddrScope::Global => write!(f, "global"),
+            AddrScope::Site => write!(f, "site"),
+            AddrScope::Link => write!(f, "link"),
+            AddrScope::Host => write!(f, "host"),
+            AddrScope::Nowhere => write!(f, "nowhere"),
+            AddrScope::Other(scope) => write!(f, "{}", scope),
+        }
+    }
+}
+
+
+bitflags! {
+    /// IFA_F_* of an address, empty when it did not come from rtnetlink.
+    pub struct AddrFlags: u32 {
+        // IPv4 secondary and IPv6 temporary (privacy) addresses share a bit.
+        const SECONDARY      = 0x01;
+        const TEMPORARY      = 0x01;
+        const NODAD          = 0x02;
+        const OPTIMISTIC     = 0x04;
+        const DADFAILED      = 0x08;
+        const HOMEADDRESS    = 0x10;
+        const DEPRECATED     = 0x20;
+        const TENTATIVE      = 0x40;
+        const PERMANENT      = 0x80;
+        const MANAGETEMPADDR = 0x100;
+        const NOPREFIXROUTE  = 0x200;
+        const MCAUTOJOIN     = 0x400;
+        const STABLE_PRIVACY = 0x800;
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Lifetime {
+    Forever,
+    Remaining(Duration),
+}
+
+impl Lifetime {
+    // IFA_CACHEINFO uses all ones for "forever".
+    pub fn from_secs(secs: u32) -> Lifetime {
+        if secs == u32::MAX {
+            Lifetime::Forever
+        } else {
+            Lifetime::Remaining(Duration::from_secs(secs as u64))
+        }
+    }
+}
+
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Lifetime::Forever => write!(f, "forever"),
+            Lifetime::Remaining(duration) => write!(f, "{}sec", duration.as_secs()),
+        }
+    }
+}
+
+
+/// An address of an interface with everything `ip addr` shows about it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct InterfaceAddr {
+    pub cidr: IpCidr,
+    // The remote end of a point to point link.
+    pub peer: Option<IpCidr>,
+    pub broadcast: Option<IpAddress>,
+    pub label: Option<String>,
+    pub scope: AddrScope,
+    pub flags: AddrFlags,
+    // `None` when unknown (getifaddrs).
+    pub preferred_lft: Option<Lifetime>,
+    pub valid_lft: Option<Lifetime>,
+}
+
+impl InterfaceAddr {
+    pub fn new(cidr: IpCidr) -> InterfaceAddr {
+        InterfaceAddr {
+            cidr,
+            peer: None,
+            broadcast: None,
+            label: None,
+            scope: AddrScope::from_address(&cidr.address()),
+            flags: AddrFlags::empty(),
+            preferred_lft: None,
+            valid_lft: None,
+        }
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self.cidr, IpCidr::Ipv6(_))
+    }
+
+    pub fn is_temporary(&self) -> bool {
+        self.is_ipv6() && self.flags.contains(AddrFlags::TEMPORARY)
+    }
+
+    /// Neither tentative, failed duplicate address detection nor deprecated,
+    /// i.e. fit to be used as the source of new connections.
+    pub fn is_usable(&self) -> bool {
+        !self.flags.intersects(AddrFlags::TENTATIVE | AddrFlags::DADFAILED | AddrFlags::DEPRECATED)
+        && self.preferred_lft != Some(Lifetime::Remaining(Duration::from_secs(0)))
+    }
+
+    /// A usable global address that is not a privacy address, it stays the same across reconnects.
+    pub fn is_stable(&self) -> bool {
+        self.is_usable() && !self.is_temporary() && self.scope == AddrScope::Global
+    }
+}
+
+impl InterfaceAddr {
+    // Scope, flags and label, as `ip addr` prints them after the address.
+    pub(super) fn fmt_details(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, " scope {}", self.scope)?;
+
+        let names: &[(AddrFlags, &str)] = &[
+            (AddrFlags::PERMANENT, "permanent"),
+            (AddrFlags::DEPRECATED, "deprecated"),
+            (AddrFlags::TENTATIVE, "tentative"),
+            (AddrFlags::DADFAILED, "dadfailed"),
+            (AddrFlags::OPTIMISTIC, "optimistic"),
+            (AddrFlags::NODAD, "nodad"),
+            (AddrFlags::HOMEADDRESS, "home"),
+            (AddrFlags::MANAGETEMPADDR, "mngtmpaddr"),
+            (AddrFlags::NOPREFIXROUTE, "noprefixroute"),
+            (AddrFlags::MCAUTOJOIN, "autojoin"),
+            (AddrFlags::STABLE_PRIVACY, "stable-privacy"),
+        ];
+        if self.flags.contains(AddrFlags::SECONDARY) {
+            write!(f, " {}", if self.is_ipv6() { "temporary" } else { "secondary" })?;
+        }
+        for &(flag, name) in names {
+            if self.flags.contains(flag) {
+                write!(f, " {}", name)?;
+            }
+        }
+
+        if let Some(ref label) = self.label {
+            write!(f, " {}", label)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for InterfaceAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.cidr)?;
+        self.fmt_details(f)
+    }
+}
@@ -0,0 +1,224 @@
+use super::Interface;
+#[cfg(not(target_os = "linux"))]
+use super::Flags;
+
+#[cfg(target_os = "linux")]
+use crate::sys;
+
+#[cfg(target_os = "linux")]
+use smoltcp::wire::EthernetAddress;
+
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum InterfaceKind {
+    Ethernet,
+    Wireless,
+    Loopback,
+    Tun,
+    Tap,
+    Veth,
+    Bridge,
+    Bond,
+    Vlan,
+    Vxlan,
+    Wireguard,
+    Dummy,
+    Macvlan,
+    Ipip,
+    Gre,
+    // An IFLA_INFO_KIND this crate has no name for, e.g. "ifb".
+    Other(String),
+    Unknown,
+}
+
+impl InterfaceKind {
+    /// The value of IFLA_INFO_KIND (`ip link add ... type <kind>`).
+    pub fn from_info_kind(kind: &str) -> InterfaceKind {
+        match kind {
+            "veth" => InterfaceKind::Veth,
+            "bridge" => InterfaceKind::Bridge,
+            "bond" => InterfaceKind::Bond,
+            "vlan" => InterfaceKind::Vlan,
+            "vxlan" => InterfaceKind::Vxlan,
+            "wireguard" => InterfaceKind::Wireguard,
+            "dummy" => InterfaceKind::Dummy,
+            "tun" => InterfaceKind::Tun,
+            "macvlan" | "macvtap" => InterfaceKind::Macvlan,
+            "ipip" | "sit" | "ip6tnl" => InterfaceKind::Ipip,
+            "gre" | "gretap" | "ip6gre" | "ip6gretap" => InterfaceKind::Gre,
+            _ => InterfaceKind::Other(kind.to_string()),
+        }
+    }
+
+    /// Software devices, everything but ethernet and wireless NICs.
+    pub fn is_virtual(&self) -> bool {
+        !matches!(*self, InterfaceKind::Ethernet | InterfaceKind::Wireless)
+    }
+}
+
+impl fmt::Display for InterfaceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterfaceKind::Ethernet => write!(f, "ethernet"),
+            InterfaceKind::Wireless => write!(f, "wireless"),
+            InterfaceKind::Loopback => write!(f, "loopback"),
+            InterfaceKind::Tun => write!(f, "tun"),
+            InterfaceKind::Tap => write!(f, "tap"),
+            InterfaceKind::Veth => write!(f, "veth"),
+            InterfaceKind::Bridge => write!(f, "bridge"),
+            InterfaceKind::Bond => write!(f, "bond"),
+            InterfaceKind::Vlan => write!(f, "vlan"),
+            InterfaceKind::Vxlan => write!(f, "vxlan"),
+            InterfaceKind::Wireguard => write!(f, "wireguard"),
+            InterfaceKind::Dummy => write!(f, "dummy"),
+            InterfaceKind::Macvlan => write!(f, "macvlan"),
+            InterfaceKind::Ipip => write!(f, "ipip"),
+            InterfaceKind::Gre => write!(f, "gre"),
+            InterfaceKind::Other(ref kind) => write!(f, "{}", kind),
+            InterfaceKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OperState {
+    Unknown,
+    NotPresent,
+    Down,
+    LowerLayerDown,
+    Testing,
+    Dormant,
+    Up,
+}
+
+#[cfg(target_os = "linux")]
+impl OperState {
+    pub fn from_u8(state: u8) -> OperState {
+        match state {
+            sys::netlink::IF_OPER_NOTPRESENT => OperState::NotPresent,
+            sys::netlink::IF_OPER_DOWN => OperState::Down,
+            sys::netlink::IF_OPER_LOWERLAYERDOWN => OperState::LowerLayerDown,
+            sys::netlink::IF_OPER_TESTING => OperState::Testing,
+            sys::netlink::IF_OPER_DORMANT => OperState::Dormant,
+            sys::netlink::IF_OPER_UP => OperState::Up,
+            _ => OperState::Unknown,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl fmt::Display for OperState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OperState::Unknown => write!(f, "UNKNOWN"),
+            OperState::NotPresent => write!(f, "NOTPRESENT"),
+            OperState::Down => write!(f, "DOWN"),
+            OperState::LowerLayerDown => write!(f, "LOWERLAYERDOWN"),
+            OperState::Testing => write!(f, "TESTING"),
+            OperState::Dormant => write!(f, "DORMANT"),
+            OperState::Up => write!(f, "UP"),
+        }
+    }
+}
+
+
+/// What the kernel knows about a link beyond `Interface`.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LinkInfo {
+    pub kind: InterfaceKind,
+    // ARPHRD_*
+    pub hwtype: u16,
+    // The kernel module behind a physical device, from sysfs.
+    pub driver: Option<String>,
+    // Index of the bridge or bond this link is enslaved to.
+    pub master: Option<u32>,
+    // The lower device of a VLAN/macvlan or the peer of a veth, see `link_netnsid`.
+    pub link: Option<u32>,
+    // Set when `link` lives in another network namespace.
+    pub link_netnsid: Option<i32>,
+    pub vlan_id: Option<u16>,
+    pub operstate: OperState,
+    pub carrier: Option<bool>,
+    pub txqueuelen: Option<u32>,
+    pub alias: Option<String>,
+    pub perm_hwaddr: Option<EthernetAddress>,
+    // How many sockets (and `ip link set promisc on`) asked for promiscuous mode,
+    // IFF_PROMISC only reflects the latter.
+    pub promiscuity: u32,
+}
+
+#[cfg(target_os = "linux")]
+impl LinkInfo {
+    /// A NIC backed by a bus device, the candidates for a physical uplink.
+    pub fn is_physical(&self) -> bool {
+        !self.kind.is_virtual() && self.driver.is_some()
+    }
+
+    // Fill in what rtnetlink does not say, IFLA_INFO_KIND is absent for real hardware.
+    // /sys/class/net shows the namespace sysfs was mounted from, so the driver and
+    // wireless lookups are left out for an interface of another namespace.
+    pub(super) fn complete(&mut self, iface: &Interface) {
+        let sysfs = match iface.netns() {
+            None => Some(format!("/sys/class/net/{}", iface.name)),
+            Some(_) => None,
+        };
+
+        if let Some(sysfs) = &sysfs {
+            self.driver = fs::read_link(format!("{}/device/driver", sysfs))
+                .ok()
+                .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()));
+        }
+
+        if self.kind == InterfaceKind::Unknown {
+            self.kind = match self.hwtype {
+                sys::ARPHRD_LOOPBACK => InterfaceKind::Loopback,
+                sys::ARPHRD_IEEE80211 | sys::ARPHRD_IEEE80211_PRISM | sys::ARPHRD_IEEE80211_RADIOTAP => {
+                    InterfaceKind::Wireless
+                },
+                sys::ARPHRD_ETHER => match &sysfs {
+                    Some(sysfs) if Path::new(&format!("{}/wireless", sysfs)).exists()
+                                   || Path::new(&format!("{}/phy80211", sysfs)).exists() => {
+                        InterfaceKind::Wireless
+                    },
+                    _ => InterfaceKind::Ethernet,
+                },
+                sys::ARPHRD_TUNNEL | sys::ARPHRD_SIT | sys::ARPHRD_TUNNEL6 => InterfaceKind::Ipip,
+                sys::ARPHRD_IPGRE | sys::ARPHRD_IP6GRE => InterfaceKind::Gre,
+                _ => InterfaceKind::Unknown,
+            };
+        }
+    }
+}
+
+
+// Best effort without rtnetlink.
+#[cfg(not(target_os = "linux"))]
+pub fn guess_kind(iface: &Interface) -> InterfaceKind {
+    if iface.flags.contains(Flags::IFF_LOOPBACK) {
+        InterfaceKind::Loopback
+    } else if iface.is_tap() {
+        InterfaceKind::Tap
+    } else if iface.is_tun() {
+        InterfaceKind::Tun
+    } else if iface.name.starts_with("bridge") {
+        InterfaceKind::Bridge
+    } else if iface.name.starts_with("vlan") {
+        InterfaceKind::Vlan
+    } else if iface.name.starts_with("bond") {
+        InterfaceKind::Bond
+    } else if iface.hwaddr.is_some() && iface.name.starts_with("en") {
+        // Wi-Fi is an IFT_ETHER "en" device as well.
+        InterfaceKind::Ethernet
+    } else {
+        InterfaceKind::Unknown
+    }
+}
@@ -0,0 +1,385 @@
+//! Create, modify and delete links over rtnetlink, the `ip link` subset tests and
+//! container networking need.
+use super::{Interface, TunnelParams};
+
+use crate::sys;
+use crate::netlink::{MessageBuilder, NetlinkSocket};
+
+use smoltcp::wire::EthernetAddress;
+
+use std::io;
+use std::os::unix::io::RawFd;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MacvlanMode {
+    Private,
+    Vepa,
+    Bridge,
+    Passthru,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IpvlanMode {
+    L2,
+    L3,
+    L3s,
+}
+
+// A network namespace to move a link (or the peer of a veth) to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Namespace {
+    Fd(RawFd),
+    Pid(u32),
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Dummy,
+    Bridge,
+    Veth { peer: String, peer_netns: Option<Namespace> },
+    Vlan { parent: u32, id: u16 },
+    Macvlan { parent: u32, mode: MacvlanMode },
+    Ipvlan { parent: u32, mode: IpvlanMode },
+    Tunnel(TunnelParams),
+}
+
+/// RTM_NEWLINK with IFLA_LINKINFO.
+///
+/// ```no_run
+/// use znet::interface::link::{self, LinkBuilder};
+///
+/// let br = LinkBuilder::bridge("br0").up().create().unwrap();
+/// let veth = LinkBuilder::veth("veth0", "veth1").master(br.index()).up().create().unwrap();
+/// link::delete(veth.index()).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct LinkBuilder {
+    name: String,
+    kind: Kind,
+    mtu: Option<u32>,
+    hwaddr: Option<EthernetAddress>,
+    master: Option<u32>,
+    up: bool,
+}
+
+impl LinkBuilder {
+    fn new(name: &str, kind: Kind) -> LinkBuilder {
+        LinkBuilder {
+            name: name.to_string(),
+            kind,
+            mtu: None,
+            hwaddr: None,
+            master: None,
+            up: false,
+        }
+    }
+
+    pub fn dummy(name: &str) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Dummy)
+    }
+
+    pub fn bridge(name: &str) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Bridge)
+    }
+
+    pub fn veth(name: &str, peer: &str) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Veth { peer: peer.to_string(), peer_netns: None })
+    }
+
+    /// An 802.1Q VLAN on top of the link with index `parent`.
+    pub fn vlan(name: &str, parent: u32, id: u16) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Vlan { parent, id })
+    }
+
+    pub fn macvlan(name: &str, parent: u32, mode: MacvlanMode) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Macvlan { parent, mode })
+    }
+
+    pub fn ipvlan(name: &str, parent: u32, mode: IpvlanMode) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Ipvlan { parent, mode })
+    }
+
+    /// GRE, IPIP, SIT, ip6tnl or VXLAN, see `TunnelParams`.
+    pub fn tunnel(name: &str, params: TunnelParams) -> LinkBuilder {
+        LinkBuilder::new(name, Kind::Tunnel(params))
+    }
+
+    /// Create the peer of a veth in another network namespace, ignored for other kinds.
+    pub fn peer_netns(mut self, netns: Namespace) -> LinkBuilder {
+        if let Kind::Veth { ref mut peer_netns, .. } = self.kind {
+            *peer_netns = Some(netns);
+        }
+        self
+    }
+
+    pub fn mtu(mut self, mtu: u32) -> LinkBuilder {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    pub fn hwaddr(mut self, hwaddr: EthernetAddress) -> LinkBuilder {
+        self.hwaddr = Some(hwaddr);
+        self
+    }
+
+    /// Enslave the new link to a bridge or bond.
+    pub fn master(mut self, master: u32) -> LinkBuilder {
+        self.master = Some(master);
+        self
+    }
+
+    pub fn up(mut self) -> LinkBuilder {
+        self.up = true;
+        self
+    }
+
+    fn message(&self) -> Vec<u8> {
+        let ifinfo = sys::netlink::ifinfomsg {
+            ifi_family: sys::AF_UNSPEC as u8,
+            ifi_flags: if self.up { sys::IFF_UP as u32 } else { 0 },
+            ifi_change: if self.up { sys::IFF_UP as u32 } else { 0 },
+            ..Default::default()
+        };
+
+        let mut msg = MessageBuilder::new(sys::netlink::RTM_NEWLINK,
+                                          (sys::NLM_F_CREATE | sys::NLM_F_EXCL) as u16)
+            .header(&ifinfo)
+            .attr_str(sys::netlink::IFLA_IFNAME, &self.name);
+        if let Some(mtu) = self.mtu {
+            msg = msg.attr_u32(sys::netlink::IFLA_MTU, mtu);
+        }
+        if let Some(hwaddr) = self.hwaddr {
+            msg = msg.attr(sys::netlink::IFLA_ADDRESS, hwaddr.as_bytes());
+        }
+        if let Some(master) = self.master {
+            msg = msg.attr_u32(sys::netlink::IFLA_MASTER, master);
+        }
+        match self.kind {
+            Kind::Vlan { parent, .. } | Kind::Macvlan { parent, .. } | Kind::Ipvlan { parent, .. } => {
+                msg = msg.attr_u32(sys::netlink::IFLA_LINK, parent);
+            },
+            _ => { },
+        }
+
+        msg = msg.begin_nested(sys::netlink::IFLA_LINKINFO);
+        msg = match self.kind {
+            Kind::Dummy => msg.attr_str(sys::netlink::IFLA_INFO_KIND, "dummy"),
+            Kind::Bridge => msg.attr_str(sys::netlink::IFLA_INFO_KIND, "bridge"),
+            Kind::Veth { ref peer, peer_netns } => {
+                // The peer is described by a nested ifinfomsg with its own attributes.
+                let peer_info = sys::netlink::ifinfomsg { ifi_family: sys::AF_UNSPEC as u8, ..Default::default() };
+                let mut msg = msg.attr_str(sys::netlink::IFLA_INFO_KIND, "veth")
+                                 .begin_nested(sys::netlink::IFLA_INFO_DATA)
+                                 .begin_nested(sys::netlink::VETH_INFO_PEER)
+                                 .header(&peer_info)
+                                 .attr_str(sys::netlink::IFLA_IFNAME, peer);
+                if let Some(mtu) = self.mtu {
+                    msg = msg.attr_u32(sys::netlink::IFLA_MTU, mtu);
+                }
+                if let Some(netns) = peer_netns {
+                    let (kind, value) = namespace(netns);
+                    msg = msg.attr_u32(kind, value);
+                }
+                msg.end_nested().end_nested()
+            },
+            Kind::Vlan { id, .. } => {
+                msg.attr_str(sys::netlink::IFLA_INFO_KIND, "vlan")
+                   .begin_nested(sys::netlink::IFLA_INFO_DATA)
+                   .attr_u16(sys::netlink::IFLA_VLAN_ID, id)
+                   .end_nested()
+            },
+            Kind::Macvlan { mode, .. } => {
+                let mode = match mode {
+                    MacvlanMode::Private => sys::netlink::MACVLAN_MODE_PRIVATE,
+                    MacvlanMode::Vepa => sys::netlink::MACVLAN_MODE_VEPA,
+                    MacvlanMode::Bridge => sys::netlink::MACVLAN_MODE_BRIDGE,
+                    MacvlanMode::Passthru => sys::netlink::MACVLAN_MODE_PASSTHRU,
+                };
+                msg.attr_str(sys::netlink::IFLA_INFO_KIND, "macvlan")
+                   .begin_nested(sys::netlink::IFLA_INFO_DATA)
+                   .attr_u32(sys::netlink::IFLA_MACVLAN_MODE, mode)
+                   .end_nested()
+            },
+            Kind::Ipvlan { mode, .. } => {
+                let mode = match mode {
+                    IpvlanMode::L2 => sys::netlink::IPVLAN_MODE_L2,
+                    IpvlanMode::L3 => sys::netlink::IPVLAN_MODE_L3,
+                    IpvlanMode::L3s => sys::netlink::IPVLAN_MODE_L3S,
+                };
+                msg.attr_str(sys::netlink::IFLA_INFO_KIND, "ipvlan")
+                   .begin_nested(sys::netlink::IFLA_INFO_DATA)
+                   .attr_u16(sys::netlink::IFLA_IPVLAN_MODE, mode)
+                   .end_nested()
+            },
+            Kind::Tunnel(ref params) => params.encode(msg),
+        };
+
+        msg.end_nested().build()
+    }
+
+    pub fn create(self) -> Result<Interface, io::Error> {
+        let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+        socket.request(self.message())?;
+
+        Interface::with_name(&self.name)
+    }
+}
+
+fn namespace(netns: Namespace) -> (u16, u32) {
+    match netns {
+        Namespace::Fd(fd) => (sys::netlink::IFLA_NET_NS_FD, fd as u32),
+        Namespace::Pid(pid) => (sys::netlink::IFLA_NET_NS_PID, pid),
+    }
+}
+
+
+// RTM_NEWLINK without NLM_F_CREATE changes an existing link.
+fn change(index: u32, flags: u32, change: u32, attrs: &[(u16, u32)]) -> Result<(), io::Error> {
+    let ifinfo = sys::netlink::ifinfomsg {
+        ifi_family: sys::AF_UNSPEC as u8,
+        ifi_index: index as sys::c_int,
+        ifi_flags: flags,
+        ifi_change: change,
+        ..Default::default()
+    };
+    let mut msg = MessageBuilder::new(sys::netlink::RTM_NEWLINK, 0).header(&ifinfo);
+    for &(kind, value) in attrs {
+        msg = msg.attr_u32(kind, value);
+    }
+
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+    socket.request(msg.build())?;
+    Ok(())
+}
+
+/// Delete a link, deleting one end of a veth removes both.
+pub fn delete(index: u32) -> Result<(), io::Error> {
+    let ifinfo = sys::netlink::ifinfomsg {
+        ifi_family: sys::AF_UNSPEC as u8,
+        ifi_index: index as sys::c_int,
+        ..Default::default()
+    };
+    let msg = MessageBuilder::new(sys::netlink::RTM_DELLINK, 0).header(&ifinfo).build();
+
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+    socket.request(msg)?;
+    Ok(())
+}
+
+pub fn set_up(index: u32, up: bool) -> Result<(), io::Error> {
+    let flags = if up { sys::IFF_UP as u32 } else { 0 };
+    change(index, flags, sys::IFF_UP as u32, &[])
+}
+
+pub fn set_mtu(index: u32, mtu: u32) -> Result<(), io::Error> {
+    change(index, 0, 0, &[(sys::netlink::IFLA_MTU, mtu)])
+}
+
+/// Add a link to a bridge or bond.
+pub fn enslave(index: u32, master: u32) -> Result<(), io::Error> {
+    change(index, 0, 0, &[(sys::netlink::IFLA_MASTER, master)])
+}
+
+/// Remove a link from its bridge or bond.
+pub fn release(index: u32) -> Result<(), io::Error> {
+    change(index, 0, 0, &[(sys::netlink::IFLA_MASTER, 0)])
+}
+
+/// Move a link to another network namespace.
+pub fn set_netns(index: u32, netns: Namespace) -> Result<(), io::Error> {
+    change(index, 0, 0, &[namespace(netns)])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::InterfaceKind;
+    use crate::interface::rtnl;
+    use crate::netns::scratch::Scratch;
+
+    // Create the link in a scratch namespace, check it and delete it again.
+    fn check<F>(create: fn() -> LinkBuilder, check: F)
+        where F: FnOnce(&Interface) + Send
+    {
+        let netns = Scratch::new();
+        netns.run(move || {
+            let link = create().create().unwrap();
+            check(&link);
+            delete(link.index()).unwrap();
+            assert!(Interface::with_index(link.index()).is_err());
+        }).unwrap();
+    }
+
+    // A veth end for the links stacked on top of one.
+    fn parent() -> u32 {
+        LinkBuilder::veth("parent0", "parent1").create().unwrap().index()
+    }
+
+    #[test]
+    #[ignore = "needs root and the dummy module"]
+    fn dummy() {
+        check(|| LinkBuilder::dummy("dummy0").mtu(1400).up(), |link| {
+            assert_eq!(link.kind(), InterfaceKind::Dummy);
+            assert_eq!(link.mtu(), 1400);
+            assert_eq!(link.link_info().unwrap().master, None);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn bridge() {
+        check(|| LinkBuilder::bridge("br0").up(), |br| {
+            assert_eq!(br.kind(), InterfaceKind::Bridge);
+
+            let port = LinkBuilder::veth("port0", "port1").create().unwrap();
+            assert_eq!(port.link_info().unwrap().master, None);
+            enslave(port.index(), br.index()).unwrap();
+            assert_eq!(port.link_info().unwrap().master, Some(br.index()));
+            let slaves = rtnl::slaves(br.index()).unwrap();
+            assert_eq!(slaves.iter().map(|iface| iface.name()).collect::<Vec<_>>(), ["port0"]);
+
+            release(port.index()).unwrap();
+            assert_eq!(port.link_info().unwrap().master, None);
+            assert!(rtnl::slaves(br.index()).unwrap().is_empty());
+
+            // Enslaved right away.
+            let other = LinkBuilder::veth("port2", "port3").master(br.index()).create().unwrap();
+            assert_eq!(other.link_info().unwrap().master, Some(br.index()));
+            delete(other.index()).unwrap();
+            delete(port.index()).unwrap();
+        });
+    }
+
+    #[test]
+    #[ignore = "needs root and the 8021q module"]
+    fn vlan() {
+        check(|| LinkBuilder::vlan("vlan5", parent(), 5), |vlan| {
+            let info = vlan.link_info().unwrap();
+            assert_eq!(info.kind, InterfaceKind::Vlan);
+            assert_eq!(info.vlan_id, Some(5));
+            assert_eq!(info.link, Some(Interface::with_name("parent0").unwrap().index()));
+        });
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn macvlan() {
+        check(|| LinkBuilder::macvlan("macvlan0", parent(), MacvlanMode::Bridge), |macvlan| {
+            let info = macvlan.link_info().unwrap();
+            assert_eq!(info.kind, InterfaceKind::Macvlan);
+            assert_eq!(info.link, Some(Interface::with_name("parent0").unwrap().index()));
+        });
+    }
+
+    #[test]
+    #[ignore = "needs root and the ipvlan module"]
+    fn ipvlan() {
+        check(|| LinkBuilder::ipvlan("ipvlan0", parent(), IpvlanMode::L2), |ipvlan| {
+            let info = ipvlan.link_info().unwrap();
+            assert_eq!(info.kind, InterfaceKind::Other("ipvlan".to_string()));
+            assert_eq!(info.link, Some(Interface::with_name("parent0").unwrap().index()));
+        });
+    }
+}
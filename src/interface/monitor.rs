@@ -0,0 +1,307 @@
+use super::{Flags, Interface, interfaces, rtnl};
+
+use crate::sys;
+use crate::netlink::NetlinkSocket;
+
+use smoltcp::wire::IpCidr;
+
+use std::{io, fmt};
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InterfaceEvent {
+    Added(Interface),
+    // The last known state of the interface.
+    Removed(Interface),
+    Up(Interface),
+    Down(Interface),
+    AddrAdded(Interface, IpCidr),
+    AddrRemoved(Interface, IpCidr),
+    MtuChanged(Interface),
+}
+
+impl InterfaceEvent {
+    pub fn interface(&self) -> &Interface {
+        match *self {
+            InterfaceEvent::Added(ref iface)
+            | InterfaceEvent::Removed(ref iface)
+            | InterfaceEvent::Up(ref iface)
+            | InterfaceEvent::Down(ref iface)
+            | InterfaceEvent::AddrAdded(ref iface, _)
+            | InterfaceEvent::AddrRemoved(ref iface, _)
+            | InterfaceEvent::MtuChanged(ref iface) => iface,
+        }
+    }
+}
+
+impl fmt::Display for InterfaceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InterfaceEvent::Added(ref iface) => write!(f, "{}: added", iface.name),
+            InterfaceEvent::Removed(ref iface) => write!(f, "{}: removed", iface.name),
+            InterfaceEvent::Up(ref iface) => write!(f, "{}: up", iface.name),
+            InterfaceEvent::Down(ref iface) => write!(f, "{}: down", iface.name),
+            InterfaceEvent::AddrAdded(ref iface, cidr) => write!(f, "{}: address {} added", iface.name, cidr),
+            InterfaceEvent::AddrRemoved(ref iface, cidr) => write!(f, "{}: address {} removed", iface.name, cidr),
+            InterfaceEvent::MtuChanged(ref iface) => write!(f, "{}: mtu {}", iface.name, iface.mtu),
+        }
+    }
+}
+
+// Administratively up and with a carrier, a Wi-Fi link that lost its
+// association stays IFF_UP but drops IFF_RUNNING.
+fn is_up(iface: &Interface) -> bool {
+    iface.flags.contains(Flags::IFF_UP) && iface.flags.contains(Flags::IFF_RUNNING)
+}
+
+fn diff(old: Option<&Interface>, new: Option<&Interface>, events: &mut VecDeque<InterfaceEvent>) {
+    match (old, new) {
+        (None, Some(new)) => {
+            events.push_back(InterfaceEvent::Added(new.clone()));
+            for cidr in new.addrs.iter() {
+                events.push_back(InterfaceEvent::AddrAdded(new.clone(), *cidr));
+            }
+        },
+        (Some(old), None) => {
+            events.push_back(InterfaceEvent::Removed(old.clone()));
+        },
+        (Some(old), Some(new)) => {
+            if is_up(old) != is_up(new) {
+                if is_up(new) {
+                    events.push_back(InterfaceEvent::Up(new.clone()));
+                } else {
+                    events.push_back(InterfaceEvent::Down(new.clone()));
+                }
+            }
+            if old.mtu != new.mtu {
+                events.push_back(InterfaceEvent::MtuChanged(new.clone()));
+            }
+            for cidr in old.addrs.iter().filter(|cidr| !new.addrs.contains(cidr)) {
+                events.push_back(InterfaceEvent::AddrRemoved(new.clone(), *cidr));
+            }
+            for cidr in new.addrs.iter().filter(|cidr| !old.addrs.contains(cidr)) {
+                events.push_back(InterfaceEvent::AddrAdded(new.clone(), *cidr));
+            }
+        },
+        (None, None) => { },
+    }
+}
+
+
+/// Link and address changes reported by the kernel (RTNLGRP_LINK, RTNLGRP_IPV4_IFADDR
+/// and RTNLGRP_IPV6_IFADDR).
+///
+/// Iterating blocks until the next event. After `set_nonblocking(true)` the fd can be
+/// registered with a `Poller` (or mio/tokio), the iterator then ends once the socket
+/// is drained.
+pub struct Monitor {
+    socket: NetlinkSocket,
+    known: HashMap<u32, Interface>,
+    pending: VecDeque<InterfaceEvent>,
+}
+
+impl Monitor {
+    pub fn new() -> Result<Monitor, io::Error> {
+        let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+        socket.add_membership(sys::netlink::RTNLGRP_LINK)?;
+        socket.add_membership(sys::netlink::RTNLGRP_IPV4_IFADDR)?;
+        socket.add_membership(sys::netlink::RTNLGRP_IPV6_IFADDR)?;
+
+        // Subscribe first so nothing happening in between is missed.
+        let known = Monitor::snapshot();
+
+        Ok(Monitor { socket, known, pending: VecDeque::new() })
+    }
+
+    fn snapshot() -> HashMap<u32, Interface> {
+        interfaces().into_iter().map(|iface| (iface.index, iface)).collect()
+    }
+
+    /// The interfaces as last seen by the monitor.
+    pub fn interfaces(&self) -> Vec<Interface> {
+        let mut ifaces = self.known.values().cloned().collect::<Vec<Interface>>();
+        ifaces.sort_by_key(|iface| iface.index);
+        ifaces
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error> {
+        self.socket.set_nonblocking(nonblocking)
+    }
+
+    /// Wait for the next event, or fail with `WouldBlock` in nonblocking mode.
+    pub fn next_event(&mut self) -> Result<InterfaceEvent, io::Error> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+
+            match self.socket.recv() {
+                Ok(messages) => {
+                    for msg in messages {
+                        self.handle(msg.kind, &msg.payload);
+                    }
+                },
+                // The socket buffer overflowed and notifications were lost.
+                Err(ref e) if e.raw_os_error() == Some(sys::ENOBUFS) => self.resync(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn resync(&mut self) {
+        warn!("netlink notifications were dropped, rescanning interfaces");
+        let known = Monitor::snapshot();
+        for (index, old) in self.known.iter() {
+            if !known.contains_key(index) {
+                diff(Some(old), None, &mut self.pending);
+            }
+        }
+        for (index, new) in known.iter() {
+            diff(self.known.get(index), Some(new), &mut self.pending);
+        }
+        self.known = known;
+    }
+
+    fn handle(&mut self, kind: u16, payload: &[u8]) {
+        match kind {
+            sys::netlink::RTM_NEWLINK => {
+                if let Some(mut iface) = rtnl::parse_link(payload) {
+                    let old = self.known.remove(&iface.index);
+                    if let Some(ref old) = old {
+                        iface.addrs = old.addrs.clone();
+                        iface.addr_info = old.addr_info.clone();
+                        iface.dstaddr = old.dstaddr;
+                    }
+                    diff(old.as_ref(), Some(&iface), &mut self.pending);
+                    self.known.insert(iface.index, iface);
+                }
+            },
+            sys::netlink::RTM_DELLINK => {
+                if let Some(iface) = rtnl::parse_link(payload) {
+                    let old = self.known.remove(&iface.index).unwrap_or(iface);
+                    diff(Some(&old), None, &mut self.pending);
+                }
+            },
+            sys::netlink::RTM_NEWADDR | sys::netlink::RTM_DELADDR => {
+                let addr = match rtnl::parse_addr(payload) {
+                    Some(addr) => addr,
+                    None => return,
+                };
+                let old = match self.known.get_mut(&addr.index) {
+                    Some(old) => old,
+                    None => return,
+                };
+
+                let mut new = old.clone();
+                if kind == sys::netlink::RTM_NEWADDR {
+                    // IPv6 addresses are re-announced whenever their lifetimes are refreshed.
+                    new.add_addr(addr.addr);
+                } else {
+                    new.remove_addr(&addr.addr.cidr);
+                }
+
+                diff(Some(old), Some(&new), &mut self.pending);
+                *old = new;
+            },
+            _ => { },
+        }
+    }
+}
+
+impl Iterator for Monitor {
+    type Item = Result<InterfaceEvent, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_event() {
+            Ok(event) => Some(Ok(event)),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl AsRawFd for Monitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::link::{self, LinkBuilder, Namespace};
+    use crate::netns::scratch::Scratch;
+    use crate::poller::{Interest, Poller};
+    use crate::sysctl::{Conf, Sysctl};
+
+    use std::time::Duration;
+
+    const EXPECTED: &[&str] = &[
+        "mon0: added",
+        "mon0: up",
+        "mon0: mtu 1400",
+        "mon0: address 10.83.0.1/24 added",
+        "mon0: address 10.83.0.1/24 removed",
+        "mon0: down",
+        "mon0: removed",
+    ];
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn events() {
+        // The peer end lives in `remote` so only mon0 shows up.
+        let (netns, remote) = (Scratch::new(), Scratch::new());
+        // No IPv6 link-local addresses coming and going.
+        netns.run(|| {
+            Sysctl::DisableIpv6(Conf::All).set(1)?;
+            Sysctl::DisableIpv6(Conf::Default).set(1)
+        }).unwrap().unwrap();
+
+        let mut blocking = netns.run(Monitor::new).unwrap().unwrap();
+        let mut nonblocking = netns.run(Monitor::new).unwrap().unwrap();
+        nonblocking.set_nonblocking(true).unwrap();
+        let mut next = || blocking.next().unwrap().unwrap().to_string();
+
+        // Wait for the event of every change before the next one, IFF_RUNNING
+        // follows IFF_UP asynchronously.
+        let mut events = Vec::new();
+        let index = netns.run(|| LinkBuilder::veth("mon0", "mon1").peer_netns(Namespace::from(&*remote)).create())
+                         .unwrap()
+                         .unwrap()
+                         .index();
+        events.push(next());
+        remote.ip(&["link", "set", "mon1", "up"]);
+        netns.run(|| link::set_up(index, true)).unwrap().unwrap();
+        events.push(next());
+        netns.run(|| link::set_mtu(index, 1400)).unwrap().unwrap();
+        events.push(next());
+        netns.ip(&["addr", "add", "10.83.0.1/24", "dev", "mon0"]);
+        events.push(next());
+        netns.ip(&["addr", "del", "10.83.0.1/24", "dev", "mon0"]);
+        events.push(next());
+        netns.run(|| link::set_up(index, false)).unwrap().unwrap();
+        events.push(next());
+        netns.run(|| link::delete(index)).unwrap().unwrap();
+        events.push(next());
+        assert_eq!(events, EXPECTED);
+
+        blocking.set_nonblocking(true).unwrap();
+        assert!(blocking.next().is_none());
+
+        // The same sequence through a poller, the iterator ends once drained.
+        let mut poller = Poller::new().unwrap();
+        let mut ready = Vec::new();
+        poller.register(&nonblocking, 0, Interest::READABLE).unwrap();
+        let mut events = Vec::new();
+        while events.len() < EXPECTED.len() {
+            poller.poll(&mut ready, Some(Duration::from_secs(5))).unwrap();
+            assert!(!ready.is_empty(), "timed out after {:?}", events);
+            events.extend(nonblocking.by_ref().map(|event| event.unwrap().to_string()));
+        }
+        assert_eq!(events, EXPECTED);
+        assert!(nonblocking.next().is_none());
+    }
+}
@@ -0,0 +1,502 @@
+// Decoding of RTM_NEWLINK / RTM_NEWADDR payloads.
+use super::{Flags, Interface, InterfaceKind, LinkInfo, OperState};
+use super::{AddrFlags, AddrScope, InterfaceAddr, Lifetime, TunnelParams};
+
+use crate::sys;
+use crate::netlink::{self, Attrs, MessageBuilder, NetlinkSocket};
+
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv6Address, EthernetAddress};
+
+use std::io;
+use std::mem;
+use std::collections::HashMap;
+
+
+pub fn parse_link(payload: &[u8]) -> Option<Interface> {
+    let ifinfo: sys::netlink::ifinfomsg = netlink::read(payload)?;
+    let offset = netlink::align(mem::size_of::<sys::netlink::ifinfomsg>());
+    if payload.len() < offset {
+        return None;
+    }
+
+    let mut iface = Interface {
+        name: String::new(),
+        index: ifinfo.ifi_index as u32,
+        flags: Flags::from_bits_truncate(ifinfo.ifi_flags as sys::c_int),
+        mtu: 0,
+        hwaddr: None,
+        dstaddr: None,
+        addrs: vec![],
+        addr_info: vec![],
+        netns: None,
+    };
+
+    for (kind, value) in Attrs::new(&payload[offset..]) {
+        match kind {
+            sys::netlink::IFLA_IFNAME => iface.name = netlink::attr_str(value)?,
+            sys::netlink::IFLA_MTU => iface.mtu = netlink::attr_u32(value).unwrap_or(0),
+            sys::netlink::IFLA_ADDRESS
+                if value.len() == 6 => {
+                    iface.hwaddr = Some(EthernetAddress::from_bytes(value));
+                },
+            _ => { },
+        }
+    }
+
+    if iface.name.is_empty() {
+        return None;
+    }
+
+    Some(iface)
+}
+
+pub struct Addr {
+    pub index: u32,
+    pub addr: InterfaceAddr,
+}
+
+fn ip_address(family: u8, value: &[u8]) -> Option<IpAddress> {
+    match family as sys::c_int {
+        sys::AF_INET if value.len() == 4 => Some(IpAddress::Ipv4(Ipv4Address::from_bytes(value))),
+        sys::AF_INET6 if value.len() == 16 => Some(IpAddress::Ipv6(Ipv6Address::from_bytes(value))),
+        _ => None,
+    }
+}
+
+pub fn parse_addr(payload: &[u8]) -> Option<Addr> {
+    let ifaddr: sys::netlink::ifaddrmsg = netlink::read(payload)?;
+    let offset = netlink::align(mem::size_of::<sys::netlink::ifaddrmsg>());
+    if payload.len() < offset {
+        return None;
+    }
+
+    let mut address = None;
+    let mut local = None;
+    let mut broadcast = None;
+    let mut label = None;
+    let mut cacheinfo = None;
+    // IFA_FLAGS carries the flags that do not fit in `ifa_flags`.
+    let mut flags = ifaddr.ifa_flags as u32;
+    for (kind, value) in Attrs::new(&payload[offset..]) {
+        match kind {
+            sys::netlink::IFA_ADDRESS => address = ip_address(ifaddr.ifa_family, value),
+            sys::netlink::IFA_LOCAL => local = ip_address(ifaddr.ifa_family, value),
+            sys::netlink::IFA_BROADCAST => broadcast = ip_address(ifaddr.ifa_family, value),
+            sys::netlink::IFA_LABEL => label = netlink::attr_str(value),
+            sys::netlink::IFA_CACHEINFO => cacheinfo = netlink::read::<sys::netlink::ifa_cacheinfo>(value),
+            sys::netlink::IFA_FLAGS => flags = netlink::attr_u32(value).unwrap_or(flags),
+            _ => { },
+        }
+    }
+
+    // IFA_LOCAL is the address of the interface, IFA_ADDRESS the peer (or the same address).
+    let prefix_len = ifaddr.ifa_prefixlen;
+    let (local, peer) = match (local, address) {
+        (Some(local), Some(address)) if local != address => (local, Some(address)),
+        (Some(local), _) => (local, None),
+        (None, Some(address)) => (address, None),
+        (None, None) => return None,
+    };
+
+    let mut addr = InterfaceAddr::new(IpCidr::new(local, prefix_len));
+    addr.peer = peer.map(|peer| IpCidr::new(peer, prefix_len));
+    addr.broadcast = broadcast;
+    addr.label = label;
+    addr.scope = AddrScope::from_u8(ifaddr.ifa_scope);
+    addr.flags = AddrFlags::from_bits_truncate(flags);
+    if let Some(cacheinfo) = cacheinfo {
+        addr.preferred_lft = Some(Lifetime::from_secs(cacheinfo.ifa_prefered));
+        addr.valid_lft = Some(Lifetime::from_secs(cacheinfo.ifa_valid));
+    }
+
+    Some(Addr { index: ifaddr.ifa_index, addr })
+}
+
+fn attach(ifaces: &mut [Interface], addrs: Vec<Addr>) {
+    let positions = ifaces.iter()
+                          .enumerate()
+                          .map(|(pos, iface)| (iface.index, pos))
+                          .collect::<HashMap<u32, usize>>();
+    for addr in addrs {
+        if let Some(pos) = positions.get(&addr.index) {
+            ifaces[*pos].add_addr(addr.addr);
+        }
+    }
+}
+
+fn dump_addrs(socket: &mut NetlinkSocket) -> Result<Vec<Addr>, io::Error> {
+    let ifaddr = sys::netlink::ifaddrmsg { ifa_family: sys::AF_UNSPEC as u8, ..Default::default() };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETADDR, sys::NLM_F_DUMP as u16)
+        .header(&ifaddr)
+        .build();
+
+    Ok(socket.request(msg)?
+             .iter()
+             .filter(|reply| reply.kind == sys::netlink::RTM_NEWADDR)
+             .filter_map(|reply| parse_addr(&reply.payload))
+             .collect())
+}
+
+/// All links and their addresses, from one RTM_GETLINK and one RTM_GETADDR dump.
+pub fn dump() -> Result<Vec<Interface>, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+
+    let ifinfo = sys::netlink::ifinfomsg { ifi_family: sys::AF_UNSPEC as u8, ..Default::default() };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETLINK, sys::NLM_F_DUMP as u16)
+        .header(&ifinfo)
+        .build();
+    let mut ifaces = socket.request(msg)?
+                           .iter()
+                           .filter(|reply| reply.kind == sys::netlink::RTM_NEWLINK)
+                           .filter_map(|reply| parse_link(&reply.payload))
+                           .collect::<Vec<Interface>>();
+    ifaces.sort_by_key(|iface| iface.index);
+
+    let addrs = dump_addrs(&mut socket)?;
+    attach(&mut ifaces, addrs);
+
+    Ok(ifaces)
+}
+
+/// A single link, looked up by index or (when `index` is 0) by name.
+pub fn get(index: u32, ifname: Option<&str>) -> Result<Interface, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+
+    let ifinfo = sys::netlink::ifinfomsg {
+        ifi_family: sys::AF_UNSPEC as u8,
+        ifi_index: index as sys::c_int,
+        ..Default::default()
+    };
+    let mut msg = MessageBuilder::new(sys::netlink::RTM_GETLINK, 0).header(&ifinfo);
+    if let Some(ifname) = ifname {
+        msg = msg.attr_str(sys::netlink::IFLA_IFNAME, ifname);
+    }
+
+    let iface = socket.request(msg.build())?
+                      .iter()
+                      .filter(|reply| reply.kind == sys::netlink::RTM_NEWLINK)
+                      .filter_map(|reply| parse_link(&reply.payload))
+                      .next()
+                      .ok_or_else(|| io::Error::from_raw_os_error(sys::ENODEV))?;
+
+    // An address dump is only narrowed by the kernel with NETLINK_GET_STRICT_CHK, filter here.
+    let addrs = dump_addrs(&mut socket)?
+        .into_iter()
+        .filter(|addr| addr.index == iface.index)
+        .collect();
+    let mut ifaces = vec![iface];
+    attach(&mut ifaces, addrs);
+
+    Ok(ifaces.remove(0))
+}
+
+
+pub fn parse_link_info(payload: &[u8]) -> Option<LinkInfo> {
+    let ifinfo: sys::netlink::ifinfomsg = netlink::read(payload)?;
+    let offset = netlink::align(mem::size_of::<sys::netlink::ifinfomsg>());
+    if payload.len() < offset {
+        return None;
+    }
+
+    let mut info = LinkInfo {
+        kind: InterfaceKind::Unknown,
+        hwtype: ifinfo.ifi_type,
+        driver: None,
+        master: None,
+        link: None,
+        link_netnsid: None,
+        vlan_id: None,
+        operstate: OperState::Unknown,
+        carrier: None,
+        txqueuelen: None,
+        alias: None,
+        perm_hwaddr: None,
+        promiscuity: 0,
+    };
+
+    for (kind, value) in Attrs::new(&payload[offset..]) {
+        match kind {
+            sys::netlink::IFLA_MASTER => info.master = netlink::attr_u32(value),
+            // A link without a lower device points to itself.
+            sys::netlink::IFLA_LINK => {
+                info.link = netlink::attr_u32(value).filter(|link| *link != ifinfo.ifi_index as u32);
+            },
+            sys::netlink::IFLA_LINK_NETNSID => info.link_netnsid = netlink::attr_u32(value).map(|id| id as i32),
+            sys::netlink::IFLA_OPERSTATE => {
+                info.operstate = OperState::from_u8(netlink::attr_u8(value).unwrap_or(0));
+            },
+            sys::netlink::IFLA_CARRIER => info.carrier = netlink::attr_u8(value).map(|carrier| carrier != 0),
+            sys::netlink::IFLA_TXQLEN => info.txqueuelen = netlink::attr_u32(value),
+            sys::netlink::IFLA_PROMISCUITY => info.promiscuity = netlink::attr_u32(value).unwrap_or(0),
+            sys::netlink::IFLA_IFALIAS => info.alias = netlink::attr_str(value).filter(|alias| !alias.is_empty()),
+            sys::netlink::IFLA_PERM_ADDRESS
+                if value.len() == 6 => {
+                    info.perm_hwaddr = Some(EthernetAddress::from_bytes(value));
+                },
+            sys::netlink::IFLA_LINKINFO => {
+                let mut info_kind = None;
+                let mut tun_type = None;
+                for (kind, value) in Attrs::new(value) {
+                    match kind {
+                        sys::netlink::IFLA_INFO_KIND => info_kind = netlink::attr_str(value),
+                        sys::netlink::IFLA_INFO_DATA if info_kind.as_ref().map(|k| k == "vlan").unwrap_or(false) => {
+                            for (kind, value) in Attrs::new(value) {
+                                if kind == sys::netlink::IFLA_VLAN_ID {
+                                    info.vlan_id = netlink::attr_u16(value);
+                                }
+                            }
+                        },
+                        // IFF_TUN or IFF_TAP, the kind is "tun" for both.
+                        sys::netlink::IFLA_INFO_DATA if info_kind.as_ref().map(|k| k == "tun").unwrap_or(false) => {
+                            for (kind, value) in Attrs::new(value) {
+                                if kind == sys::netlink::IFLA_TUN_TYPE {
+                                    tun_type = netlink::attr_u8(value);
+                                }
+                            }
+                        },
+                        _ => { },
+                    }
+                }
+                if let Some(info_kind) = info_kind {
+                    info.kind = match tun_type {
+                        Some(tun_type) if tun_type as i32 & sys::IFF_TAP != 0 => InterfaceKind::Tap,
+                        _ => InterfaceKind::from_info_kind(&info_kind),
+                    };
+                }
+            },
+            _ => { },
+        }
+    }
+
+    Some(info)
+}
+
+// The RTM_NEWLINK payload of one link.
+fn get_link(index: u32) -> Result<Vec<u8>, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+
+    let ifinfo = sys::netlink::ifinfomsg {
+        ifi_family: sys::AF_UNSPEC as u8,
+        ifi_index: index as sys::c_int,
+        ..Default::default()
+    };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETLINK, 0).header(&ifinfo).build();
+
+    socket.request(msg)?
+          .into_iter()
+          .find(|reply| reply.kind == sys::netlink::RTM_NEWLINK)
+          .map(|reply| reply.payload)
+          .ok_or_else(|| io::Error::from_raw_os_error(sys::ENODEV))
+}
+
+/// The `LinkInfo` of one link, looked up by index.
+pub fn get_info(iface: &Interface) -> Result<LinkInfo, io::Error> {
+    let payload = get_link(iface.index)?;
+    let mut info = parse_link_info(&payload).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+    info.complete(iface);
+
+    Ok(info)
+}
+
+/// The configuration of a kernel tunnel, `None` for other links.
+pub fn get_tunnel_params(index: u32) -> Result<Option<TunnelParams>, io::Error> {
+    let payload = get_link(index)?;
+    let offset = netlink::align(mem::size_of::<sys::netlink::ifinfomsg>());
+    if payload.len() < offset {
+        return Err(io::Error::from(io::ErrorKind::InvalidData));
+    }
+
+    Ok(Attrs::new(&payload[offset..])
+             .find(|&(kind, _)| kind == sys::netlink::IFLA_LINKINFO)
+             .and_then(|(_, linkinfo)| TunnelParams::decode(linkinfo)))
+}
+
+/// The links enslaved to `master`, i.e. the ports of a bridge or bond.
+pub fn slaves(master: u32) -> Result<Vec<Interface>, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+
+    let ifinfo = sys::netlink::ifinfomsg { ifi_family: sys::AF_UNSPEC as u8, ..Default::default() };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETLINK, sys::NLM_F_DUMP as u16)
+        .header(&ifinfo)
+        .build();
+    let mut ifaces = socket.request(msg)?
+                           .iter()
+                           .filter(|reply| reply.kind == sys::netlink::RTM_NEWLINK)
+                           .filter(|reply| {
+                               parse_link_info(&reply.payload).and_then(|info| info.master) == Some(master)
+                           })
+                           .filter_map(|reply| parse_link(&reply.payload))
+                           .collect::<Vec<Interface>>();
+    ifaces.sort_by_key(|iface| iface.index);
+
+    let addrs = dump_addrs(&mut socket)?;
+    attach(&mut ifaces, addrs);
+
+    Ok(ifaces)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::{self, link::{self, LinkBuilder}};
+    use crate::netns::scratch::Scratch;
+
+    use std::time::Duration;
+
+    // RTM_NEWLINK payloads from `ip link show`, trimmed to the attributes parsed here.
+    // v0: veth, index 3, mtu 1400, 02:00:00:00:00:01, up.
+    const LINK_VETH: &[u8] = &[
+        0x00, 0x00, 0x01, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x07, 0x00, 0x03, 0x00, 0x76, 0x30, 0x00, 0x00, 0x08, 0x00, 0x0d, 0x00, 0xe8, 0x03, 0x00, 0x00,
+        0x05, 0x00, 0x10, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x78, 0x05, 0x00, 0x00,
+        0x0a, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x10, 0x00, 0x12, 0x00,
+        0x09, 0x00, 0x01, 0x00, 0x76, 0x65, 0x74, 0x68, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x05, 0x00,
+        0x02, 0x00, 0x00, 0x00,
+    ];
+
+    // vx0: vxlan id 42 over v0, down.
+    const LINK_VXLAN: &[u8] = &[
+        0x00, 0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x03, 0x00, 0x76, 0x78, 0x30, 0x00, 0x08, 0x00, 0x0d, 0x00, 0xe8, 0x03, 0x00, 0x00,
+        0x05, 0x00, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x46, 0x05, 0x00, 0x00,
+        0x0a, 0x00, 0x01, 0x00, 0xe2, 0xa0, 0xdc, 0x85, 0x6d, 0xd9, 0x00, 0x00, 0xf0, 0x00, 0x12, 0x00,
+        0x0a, 0x00, 0x01, 0x00, 0x76, 0x78, 0x6c, 0x61, 0x6e, 0x00, 0x00, 0x00, 0xe0, 0x00, 0x02, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x02, 0x00, 0x0a, 0x01, 0x02, 0x09,
+        0x08, 0x00, 0x03, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x0a, 0x01, 0x02, 0x03,
+        0x05, 0x00, 0x05, 0x00, 0x40, 0x00, 0x00, 0x00, 0x05, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x1a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x07, 0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0d, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x08, 0x00, 0x2c, 0x01, 0x00, 0x00, 0x08, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x06, 0x00, 0x0f, 0x00, 0x12, 0xb5, 0x00, 0x00, 0x05, 0x00, 0x12, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x1f, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x0c, 0x00, 0x21, 0x00, 0xf7, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0xff,
+    ];
+
+    // RTM_NEWADDR payloads from `ip addr show`.
+    // 10.1.2.3/24 brd 10.1.2.255 label v0:lan, permanent.
+    const ADDR_V4: &[u8] = &[
+        0x02, 0x18, 0x80, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x01, 0x00, 0x0a, 0x01, 0x02, 0x03,
+        0x08, 0x00, 0x02, 0x00, 0x0a, 0x01, 0x02, 0x03, 0x08, 0x00, 0x04, 0x00, 0x0a, 0x01, 0x02, 0xff,
+        0x0b, 0x00, 0x03, 0x00, 0x76, 0x30, 0x3a, 0x6c, 0x61, 0x6e, 0x00, 0x00, 0x08, 0x00, 0x08, 0x00,
+        0x80, 0x00, 0x00, 0x00, 0x14, 0x00, 0x06, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xda, 0x68, 0x06, 0x00, 0xda, 0x68, 0x06, 0x00,
+    ];
+
+    // fd00::1/64 nodad valid_lft 3600 preferred_lft 1800.
+    const ADDR_V6: &[u8] = &[
+        0x0a, 0x40, 0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x14, 0x00, 0x01, 0x00, 0xfd, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x14, 0x00, 0x06, 0x00,
+        0x08, 0x07, 0x00, 0x00, 0x10, 0x0e, 0x00, 0x00, 0xda, 0x68, 0x06, 0x00, 0xda, 0x68, 0x06, 0x00,
+        0x08, 0x00, 0x08, 0x00, 0x02, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_link_veth() {
+        let iface = parse_link(LINK_VETH).unwrap();
+        assert_eq!(iface.name(), "v0");
+        assert_eq!(iface.index(), 3);
+        assert_eq!(iface.mtu(), 1400);
+        assert_eq!(iface.hwaddr(), Some(EthernetAddress([0x02, 0, 0, 0, 0, 0x01])));
+        assert_eq!(iface.flags(), Flags::IFF_UP | Flags::IFF_BROADCAST | Flags::IFF_MULTICAST);
+    }
+
+    #[test]
+    fn parse_link_truncated() {
+        assert!(parse_link(&LINK_VETH[..8]).is_none());
+        // The header alone has no IFLA_IFNAME.
+        assert!(parse_link(&LINK_VETH[..16]).is_none());
+    }
+
+    #[test]
+    fn parse_link_info_vxlan() {
+        let info = parse_link_info(LINK_VXLAN).unwrap();
+        assert_eq!(info.kind, InterfaceKind::Vxlan);
+        assert_eq!(info.hwtype, sys::ARPHRD_ETHER);
+        assert_eq!(info.operstate, OperState::Down);
+        assert_eq!(info.txqueuelen, Some(1000));
+        assert_eq!(info.vlan_id, None);
+
+        let info = parse_link_info(LINK_VETH).unwrap();
+        assert_eq!(info.kind, InterfaceKind::Veth);
+        assert_eq!(info.operstate, OperState::LowerLayerDown);
+        // The peer.
+        assert_eq!(info.link, Some(2));
+    }
+
+    #[test]
+    fn parse_addr_ipv4() {
+        let Addr { index, addr } = parse_addr(ADDR_V4).unwrap();
+        assert_eq!(index, 3);
+        assert_eq!(addr.cidr, IpCidr::new(IpAddress::v4(10, 1, 2, 3), 24));
+        assert_eq!(addr.peer, None);
+        assert_eq!(addr.broadcast, Some(IpAddress::v4(10, 1, 2, 255)));
+        assert_eq!(addr.label, Some("v0:lan".to_string()));
+        assert_eq!(addr.scope, AddrScope::Global);
+        assert_eq!(addr.flags, AddrFlags::PERMANENT);
+        assert_eq!(addr.valid_lft, Some(Lifetime::Forever));
+        assert_eq!(addr.preferred_lft, Some(Lifetime::Forever));
+    }
+
+    #[test]
+    fn parse_addr_ipv6() {
+        let Addr { index, addr } = parse_addr(ADDR_V6).unwrap();
+        assert_eq!(index, 3);
+        assert_eq!(addr.cidr, IpCidr::new(IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 1), 64));
+        assert_eq!(addr.broadcast, None);
+        assert_eq!(addr.label, None);
+        assert_eq!(addr.flags, AddrFlags::NODAD);
+        assert_eq!(addr.preferred_lft, Some(Lifetime::Remaining(Duration::from_secs(1800))));
+        assert_eq!(addr.valid_lft, Some(Lifetime::Remaining(Duration::from_secs(3600))));
+    }
+
+    #[test]
+    fn parse_addr_peer() {
+        // IFA_LOCAL differs from IFA_ADDRESS on a point to point link.
+        let mut payload = ADDR_V4[..24].to_vec();
+        payload[15] = 0x01;
+        let Addr { addr, .. } = parse_addr(&payload).unwrap();
+        assert_eq!(addr.cidr, IpCidr::new(IpAddress::v4(10, 1, 2, 3), 24));
+        assert_eq!(addr.peer, Some(IpCidr::new(IpAddress::v4(10, 1, 2, 1), 24)));
+    }
+
+    // A link of the calling thread's namespace, deleted on drop.
+    struct Local(u32);
+
+    impl Drop for Local {
+        fn drop(&mut self) {
+            let _ = link::delete(self.0);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn tun_in_netns() {
+        let netns = Scratch::new();
+        netns.ip(&["tuntap", "add", "zntun0", "mode", "tun"]);
+        netns.ip(&["tuntap", "add", "zntap0", "mode", "tap"]);
+        // Same named links where sysfs was mounted, nothing of them may show up below.
+        let _tun = Local(LinkBuilder::bridge("zntun0").create().unwrap().index());
+        let _tap = Local(LinkBuilder::bridge("zntap0").create().unwrap().index());
+
+        let ifaces = interface::interfaces_in(Some(&netns)).unwrap();
+        let find = |name: &str| ifaces.iter().find(|iface| iface.name() == name).unwrap();
+
+        let tun = find("zntun0");
+        assert_eq!(tun.kind(), InterfaceKind::Tun);
+        assert!(tun.is_tun() && !tun.is_tap());
+        assert_eq!(tun.hwtype().unwrap(), sys::ARPHRD_NONE);
+        assert_eq!(tun.link_info().unwrap().driver, None);
+        assert_eq!(tun.stats().unwrap().tx_packets, 0);
+
+        let tap = find("zntap0");
+        assert_eq!(tap.kind(), InterfaceKind::Tap);
+        assert!(tap.is_tap() && !tap.is_tun());
+        assert_eq!(tap.hwtype().unwrap(), sys::ARPHRD_ETHER);
+    }
+}
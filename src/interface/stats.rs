@@ -0,0 +1,308 @@
+use crate::sys;
+#[cfg(target_os = "linux")]
+use crate::netlink::{self, Attrs, MessageBuilder, NetlinkSocket};
+
+use std::{io, fmt};
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::ffi::CStr;
+
+
+/// Traffic counters of an interface since it came up.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct InterfaceStats {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_errors: u64,
+    pub tx_dropped: u64,
+    pub multicast: u64,
+    pub collisions: u64,
+}
+
+impl fmt::Display for InterfaceStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "RX packets {} bytes {} errors {} dropped {} multicast {}",
+               self.rx_packets, self.rx_bytes, self.rx_errors, self.rx_dropped, self.multicast)?;
+        write!(f, "TX packets {} bytes {} errors {} dropped {} collisions {}",
+               self.tx_packets, self.tx_bytes, self.tx_errors, self.tx_dropped, self.collisions)
+    }
+}
+
+
+/// Per second rates between two `InterfaceStats` samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rates {
+    pub interval: Duration,
+    pub rx_bytes: f64,
+    pub rx_packets: f64,
+    pub tx_bytes: f64,
+    pub tx_packets: f64,
+}
+
+impl Rates {
+    pub fn between(prev: &InterfaceStats, next: &InterfaceStats, interval: Duration) -> Rates {
+        let secs = interval.as_secs() as f64 + interval.subsec_nanos() as f64 / 1_000_000_000.0;
+        // Counters go backwards when the device is recreated (or a 32 bit counter wraps).
+        let rate = |prev: u64, next: u64| {
+            if secs > 0.0 { next.saturating_sub(prev) as f64 / secs } else { 0.0 }
+        };
+
+        Rates {
+            interval,
+            rx_bytes: rate(prev.rx_bytes, next.rx_bytes),
+            rx_packets: rate(prev.rx_packets, next.rx_packets),
+            tx_bytes: rate(prev.tx_bytes, next.tx_bytes),
+            tx_packets: rate(prev.tx_packets, next.tx_packets),
+        }
+    }
+}
+
+impl fmt::Display for Rates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RX {:.0} B/s {:.0} pkt/s TX {:.0} B/s {:.0} pkt/s",
+               self.rx_bytes, self.rx_packets, self.tx_bytes, self.tx_packets)
+    }
+}
+
+
+/// Samples the counters of one interface and reports the rates since the previous sample.
+pub struct StatsSampler {
+    ifname: String,
+    last: Option<(Instant, InterfaceStats)>,
+}
+
+impl StatsSampler {
+    pub fn new(ifname: &str) -> StatsSampler {
+        StatsSampler { ifname: ifname.to_string(), last: None }
+    }
+
+    pub fn ifname(&self) -> &str {
+        &self.ifname
+    }
+
+    pub fn last(&self) -> Option<InterfaceStats> {
+        self.last.map(|(_, stats)| stats)
+    }
+
+    /// Take a sample, the first one only primes the sampler and returns `None`.
+    pub fn sample(&mut self) -> Result<Option<Rates>, io::Error> {
+        let stats = if_name_to_stats(&self.ifname)?;
+        let now = Instant::now();
+        let rates = self.last.map(|(then, prev)| Rates::between(&prev, &stats, now.duration_since(then)));
+        self.last = Some((now, stats));
+
+        Ok(rates)
+    }
+}
+
+
+#[cfg(target_os = "linux")]
+pub fn if_name_to_stats(ifname: &str) -> Result<InterfaceStats, io::Error> {
+    match netlink_stats(ifname) {
+        Ok(stats) => Ok(stats),
+        Err(e) => {
+            debug!("IFLA_STATS64 of {} unavailable: {}", ifname, e);
+            // Not sysfs, it shows the namespace it was mounted from.
+            procfs_stats(ifname)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn netlink_stats(ifname: &str) -> Result<InterfaceStats, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+    let ifinfo = sys::netlink::ifinfomsg { ifi_family: sys::AF_UNSPEC as u8, ..Default::default() };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETLINK, 0)
+        .header(&ifinfo)
+        .attr_str(sys::netlink::IFLA_IFNAME, ifname)
+        .build();
+
+    for reply in socket.request(msg)? {
+        if reply.kind != sys::netlink::RTM_NEWLINK {
+            continue;
+        }
+
+        let offset = netlink::align(std::mem::size_of::<sys::netlink::ifinfomsg>());
+        if reply.payload.len() < offset {
+            continue;
+        }
+        for (kind, value) in Attrs::new(&reply.payload[offset..]) {
+            if kind != sys::netlink::IFLA_STATS64 {
+                continue;
+            }
+            if let Some(stats) = netlink::read::<sys::netlink::rtnl_link_stats64>(value) {
+                return Ok(InterfaceStats::from(stats));
+            }
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "no IFLA_STATS64 attribute"))
+}
+
+#[cfg(target_os = "linux")]
+impl From<sys::netlink::rtnl_link_stats64> for InterfaceStats {
+    fn from(stats: sys::netlink::rtnl_link_stats64) -> InterfaceStats {
+        InterfaceStats {
+            rx_bytes: stats.rx_bytes,
+            rx_packets: stats.rx_packets,
+            rx_errors: stats.rx_errors,
+            rx_dropped: stats.rx_dropped,
+            tx_bytes: stats.tx_bytes,
+            tx_packets: stats.tx_packets,
+            tx_errors: stats.tx_errors,
+            tx_dropped: stats.tx_dropped,
+            multicast: stats.multicast,
+            collisions: stats.collisions,
+        }
+    }
+}
+
+// Inter-|   Receive                                                |  Transmit
+//  face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+#[cfg(target_os = "linux")]
+fn procfs_stats(ifname: &str) -> Result<InterfaceStats, io::Error> {
+    // /proc/net follows the main thread, not a thread switched to another namespace.
+    let content = fs::read_to_string("/proc/thread-self/net/dev")?;
+    parse_proc_net_dev(&content, ifname)
+}
+
+#[cfg(target_os = "linux")]
+fn parse_proc_net_dev(content: &str, ifname: &str) -> Result<InterfaceStats, io::Error> {
+    for line in content.lines().skip(2) {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        if name != ifname {
+            continue;
+        }
+
+        let fields = parts.next()
+                          .unwrap_or("")
+                          .split_whitespace()
+                          .map(|field| field.parse::<u64>().unwrap_or(0))
+                          .collect::<Vec<u64>>();
+        if fields.len() < 16 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/net/dev line"));
+        }
+
+        return Ok(InterfaceStats {
+            rx_bytes: fields[0],
+            rx_packets: fields[1],
+            rx_errors: fields[2],
+            rx_dropped: fields[3],
+            multicast: fields[7],
+            tx_bytes: fields[8],
+            tx_packets: fields[9],
+            tx_errors: fields[10],
+            tx_dropped: fields[11],
+            collisions: fields[13],
+        });
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in /proc/net/dev", ifname)))
+}
+
+#[cfg(target_os = "macos")]
+pub fn if_name_to_stats(ifname: &str) -> Result<InterfaceStats, io::Error> {
+    let mut ifap: *mut sys::ifaddrs = std::ptr::null_mut();
+    if unsafe { sys::getifaddrs(&mut ifap) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut stats = None;
+    let mut cur = ifap;
+    while !cur.is_null() {
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+
+        if ifa.ifa_addr.is_null() || ifa.ifa_data.is_null() {
+            continue;
+        }
+        if unsafe { (*ifa.ifa_addr).sa_family } as i32 != sys::AF_LINK {
+            continue;
+        }
+        if unsafe { CStr::from_ptr(ifa.ifa_name) }.to_bytes() != ifname.as_bytes() {
+            continue;
+        }
+
+        let data = unsafe { &*(ifa.ifa_data as *const sys::if_data) };
+        stats = Some(InterfaceStats {
+            rx_bytes: data.ifi_ibytes as u64,
+            rx_packets: data.ifi_ipackets as u64,
+            rx_errors: data.ifi_ierrors as u64,
+            rx_dropped: data.ifi_iqdrops as u64,
+            tx_bytes: data.ifi_obytes as u64,
+            tx_packets: data.ifi_opackets as u64,
+            tx_errors: data.ifi_oerrors as u64,
+            tx_dropped: 0,
+            multicast: data.ifi_imcasts as u64,
+            collisions: data.ifi_collisions as u64,
+        });
+        break;
+    }
+
+    unsafe { sys::freeifaddrs(ifap) };
+
+    stats.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no link statistics for {}", ifname)))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn if_name_to_stats(_ifname: &str) -> Result<InterfaceStats, io::Error> {
+    Err(io::Error::new(io::ErrorKind::Other, "interface statistics are not supported on this platform"))
+}
+
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    const PROC_NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  148250    1723    0    0    0     0          0         0   148250    1723    0    0    0     0       0          0
+  eth0: 918273645 702154    3   17    0     0          0      4821 51234567  301876    1    2    0     5       0          0
+   wg0:       0       0    0    0    0     0          0         0     1480      12    0    9    0     0       0          0
+";
+
+    #[test]
+    fn parse_proc_net_dev_fields() {
+        let stats = parse_proc_net_dev(PROC_NET_DEV, "eth0").unwrap();
+        assert_eq!(stats, InterfaceStats {
+            rx_bytes: 918273645,
+            rx_packets: 702154,
+            rx_errors: 3,
+            rx_dropped: 17,
+            multicast: 4821,
+            tx_bytes: 51234567,
+            tx_packets: 301876,
+            tx_errors: 1,
+            tx_dropped: 2,
+            collisions: 5,
+        });
+
+        let stats = parse_proc_net_dev(PROC_NET_DEV, "wg0").unwrap();
+        assert_eq!(stats.tx_packets, 12);
+        assert_eq!(stats.tx_dropped, 9);
+    }
+
+    #[test]
+    fn parse_proc_net_dev_missing() {
+        // The header lines are not interfaces.
+        let err = parse_proc_net_dev(PROC_NET_DEV, "Inter-").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = parse_proc_net_dev(PROC_NET_DEV, "eth").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn parse_proc_net_dev_truncated() {
+        let content = "header\nheader\n  eth0: 1 2 3 4 5 6 7 8\n";
+        let err = parse_proc_net_dev(content, "eth0").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,537 @@
+use crate::sys;
+use crate::netlink::{self, Attrs, MessageBuilder};
+
+use smoltcp::wire::{IpAddress, Ipv4Address, Ipv6Address};
+
+use std::fmt;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TunnelKind {
+    Gre,
+    Gretap,
+    Ip6Gre,
+    Ip6Gretap,
+    Ipip,
+    Sit,
+    Ip6tnl,
+    Vxlan,
+}
+
+impl TunnelKind {
+    pub fn from_info_kind(kind: &str) -> Option<TunnelKind> {
+        match kind {
+            "gre" => Some(TunnelKind::Gre),
+            "gretap" => Some(TunnelKind::Gretap),
+            "ip6gre" => Some(TunnelKind::Ip6Gre),
+            "ip6gretap" => Some(TunnelKind::Ip6Gretap),
+            "ipip" => Some(TunnelKind::Ipip),
+            "sit" => Some(TunnelKind::Sit),
+            "ip6tnl" => Some(TunnelKind::Ip6tnl),
+            "vxlan" => Some(TunnelKind::Vxlan),
+            _ => None,
+        }
+    }
+
+    pub fn info_kind(&self) -> &'static str {
+        match *self {
+            TunnelKind::Gre => "gre",
+            TunnelKind::Gretap => "gretap",
+            TunnelKind::Ip6Gre => "ip6gre",
+            TunnelKind::Ip6Gretap => "ip6gretap",
+            TunnelKind::Ipip => "ipip",
+            TunnelKind::Sit => "sit",
+            TunnelKind::Ip6tnl => "ip6tnl",
+            TunnelKind::Vxlan => "vxlan",
+        }
+    }
+
+    fn is_gre(&self) -> bool {
+        matches!(*self, TunnelKind::Gre | TunnelKind::Gretap | TunnelKind::Ip6Gre | TunnelKind::Ip6Gretap)
+    }
+}
+
+impl fmt::Display for TunnelKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.info_kind())
+    }
+}
+
+
+/// The configuration of a kernel tunnel, see `LinkBuilder::tunnel` and `Interface::tunnel_params`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TunnelParams {
+    pub kind: TunnelKind,
+    pub local: Option<IpAddress>,
+    // The remote endpoint, for VXLAN the unicast destination or the multicast group.
+    pub remote: Option<IpAddress>,
+    // GRE input and output keys.
+    pub ikey: Option<u32>,
+    pub okey: Option<u32>,
+    // 0 inherits the TTL of the inner packet.
+    pub ttl: Option<u8>,
+    pub vni: Option<u32>,
+    // VXLAN destination UDP port.
+    pub port: Option<u16>,
+    // Index of the device the encapsulated packets are bound to.
+    pub underlay: Option<u32>,
+}
+
+impl TunnelParams {
+    fn new(kind: TunnelKind, local: Option<IpAddress>, remote: Option<IpAddress>) -> TunnelParams {
+        TunnelParams {
+            kind,
+            local,
+            remote,
+            ikey: None,
+            okey: None,
+            ttl: None,
+            vni: None,
+            port: None,
+            underlay: None,
+        }
+    }
+
+    /// GRE over IPv4 or IPv6, depending on the addresses.
+    pub fn gre(local: IpAddress, remote: IpAddress) -> TunnelParams {
+        let kind = if is_ipv6(&remote) { TunnelKind::Ip6Gre } else { TunnelKind::Gre };
+        TunnelParams::new(kind, Some(local), Some(remote))
+    }
+
+    /// GRE carrying ethernet frames.
+    pub fn gretap(local: IpAddress, remote: IpAddress) -> TunnelParams {
+        let kind = if is_ipv6(&remote) { TunnelKind::Ip6Gretap } else { TunnelKind::Gretap };
+        TunnelParams::new(kind, Some(local), Some(remote))
+    }
+
+    /// IPv4 in IPv4.
+    pub fn ipip(local: Ipv4Address, remote: Ipv4Address) -> TunnelParams {
+        TunnelParams::new(TunnelKind::Ipip, Some(IpAddress::Ipv4(local)), Some(IpAddress::Ipv4(remote)))
+    }
+
+    /// IPv6 in IPv4.
+    pub fn sit(local: Ipv4Address, remote: Ipv4Address) -> TunnelParams {
+        TunnelParams::new(TunnelKind::Sit, Some(IpAddress::Ipv4(local)), Some(IpAddress::Ipv4(remote)))
+    }
+
+    /// IPv6 in IPv6.
+    pub fn ip6tnl(local: Ipv6Address, remote: Ipv6Address) -> TunnelParams {
+        TunnelParams::new(TunnelKind::Ip6tnl, Some(IpAddress::Ipv6(local)), Some(IpAddress::Ipv6(remote)))
+    }
+
+    /// VXLAN on the IANA port 4789, set `remote` (and usually `local`/`underlay`) before creating it.
+    pub fn vxlan(vni: u32) -> TunnelParams {
+        let mut params = TunnelParams::new(TunnelKind::Vxlan, None, None);
+        params.vni = Some(vni);
+        params.port = Some(4789);
+        params
+    }
+
+    pub fn local(mut self, local: IpAddress) -> TunnelParams {
+        self.local = Some(local);
+        self
+    }
+
+    pub fn remote(mut self, remote: IpAddress) -> TunnelParams {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Sets both GRE keys.
+    pub fn key(mut self, key: u32) -> TunnelParams {
+        self.ikey = Some(key);
+        self.okey = Some(key);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: u8) -> TunnelParams {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> TunnelParams {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn underlay(mut self, index: u32) -> TunnelParams {
+        self.underlay = Some(index);
+        self
+    }
+
+    // IFLA_INFO_KIND and IFLA_INFO_DATA, inside IFLA_LINKINFO.
+    pub(super) fn encode(&self, msg: MessageBuilder) -> MessageBuilder {
+        let mut msg = msg.attr_str(sys::netlink::IFLA_INFO_KIND, self.kind.info_kind())
+                         .begin_nested(sys::netlink::IFLA_INFO_DATA);
+
+        if self.kind.is_gre() {
+            if let Some(link) = self.underlay {
+                msg = msg.attr_u32(sys::netlink::IFLA_GRE_LINK, link);
+            }
+            if let Some(ikey) = self.ikey {
+                msg = msg.attr_be16(sys::netlink::IFLA_GRE_IFLAGS, sys::netlink::GRE_KEY)
+                         .attr_be32(sys::netlink::IFLA_GRE_IKEY, ikey);
+            }
+            if let Some(okey) = self.okey {
+                msg = msg.attr_be16(sys::netlink::IFLA_GRE_OFLAGS, sys::netlink::GRE_KEY)
+                         .attr_be32(sys::netlink::IFLA_GRE_OKEY, okey);
+            }
+            if let Some(ref local) = self.local {
+                msg = msg.attr(sys::netlink::IFLA_GRE_LOCAL, address_bytes(local));
+            }
+            if let Some(ref remote) = self.remote {
+                msg = msg.attr(sys::netlink::IFLA_GRE_REMOTE, address_bytes(remote));
+            }
+            if let Some(ttl) = self.ttl {
+                msg = msg.attr_u8(sys::netlink::IFLA_GRE_TTL, ttl);
+            }
+        } else if self.kind == TunnelKind::Vxlan {
+            if let Some(vni) = self.vni {
+                msg = msg.attr_u32(sys::netlink::IFLA_VXLAN_ID, vni);
+            }
+            if let Some(ref remote) = self.remote {
+                let kind = if is_ipv6(remote) { sys::netlink::IFLA_VXLAN_GROUP6 } else { sys::netlink::IFLA_VXLAN_GROUP };
+                msg = msg.attr(kind, address_bytes(remote));
+            }
+            if let Some(ref local) = self.local {
+                let kind = if is_ipv6(local) { sys::netlink::IFLA_VXLAN_LOCAL6 } else { sys::netlink::IFLA_VXLAN_LOCAL };
+                msg = msg.attr(kind, address_bytes(local));
+            }
+            if let Some(link) = self.underlay {
+                msg = msg.attr_u32(sys::netlink::IFLA_VXLAN_LINK, link);
+            }
+            if let Some(ttl) = self.ttl {
+                msg = msg.attr_u8(sys::netlink::IFLA_VXLAN_TTL, ttl);
+            }
+            if let Some(port) = self.port {
+                msg = msg.attr_be16(sys::netlink::IFLA_VXLAN_PORT, port);
+            }
+        } else {
+            if let Some(link) = self.underlay {
+                msg = msg.attr_u32(sys::netlink::IFLA_IPTUN_LINK, link);
+            }
+            if let Some(ref local) = self.local {
+                msg = msg.attr(sys::netlink::IFLA_IPTUN_LOCAL, address_bytes(local));
+            }
+            if let Some(ref remote) = self.remote {
+                msg = msg.attr(sys::netlink::IFLA_IPTUN_REMOTE, address_bytes(remote));
+            }
+            if let Some(ttl) = self.ttl {
+                msg = msg.attr_u8(sys::netlink::IFLA_IPTUN_TTL, ttl);
+            }
+            if self.kind == TunnelKind::Ip6tnl {
+                // ip6ip6, the kernel default of 0 accepts IPv4 as well.
+                msg = msg.attr_u8(sys::netlink::IFLA_IPTUN_PROTO, sys::IPPROTO_IPV6 as u8);
+            }
+        }
+
+        msg.end_nested()
+    }
+
+    // The payload of IFLA_LINKINFO.
+    pub(super) fn decode(linkinfo: &[u8]) -> Option<TunnelParams> {
+        let mut kind = None;
+        let mut data = None;
+        for (attr, value) in Attrs::new(linkinfo) {
+            match attr {
+                sys::netlink::IFLA_INFO_KIND => kind = netlink::attr_str(value).and_then(|k| TunnelKind::from_info_kind(&k)),
+                sys::netlink::IFLA_INFO_DATA => data = Some(value),
+                _ => { },
+            }
+        }
+
+        let mut params = TunnelParams::new(kind?, None, None);
+        let data = match data {
+            Some(data) => data,
+            None => return Some(params),
+        };
+
+        let mut iflags = 0;
+        let mut oflags = 0;
+        let mut ikey = None;
+        let mut okey = None;
+        for (attr, value) in Attrs::new(data) {
+            if params.kind.is_gre() {
+                match attr {
+                    sys::netlink::IFLA_GRE_LINK => params.underlay = netlink::attr_u32(value),
+                    sys::netlink::IFLA_GRE_IFLAGS => iflags = netlink::attr_be16(value).unwrap_or(0),
+                    sys::netlink::IFLA_GRE_OFLAGS => oflags = netlink::attr_be16(value).unwrap_or(0),
+                    sys::netlink::IFLA_GRE_IKEY => ikey = netlink::attr_be32(value),
+                    sys::netlink::IFLA_GRE_OKEY => okey = netlink::attr_be32(value),
+                    sys::netlink::IFLA_GRE_LOCAL => params.local = address(value),
+                    sys::netlink::IFLA_GRE_REMOTE => params.remote = address(value),
+                    sys::netlink::IFLA_GRE_TTL => params.ttl = netlink::attr_u8(value),
+                    _ => { },
+                }
+            } else if params.kind == TunnelKind::Vxlan {
+                match attr {
+                    sys::netlink::IFLA_VXLAN_ID => params.vni = netlink::attr_u32(value),
+                    sys::netlink::IFLA_VXLAN_GROUP | sys::netlink::IFLA_VXLAN_GROUP6 => params.remote = address(value),
+                    sys::netlink::IFLA_VXLAN_LOCAL | sys::netlink::IFLA_VXLAN_LOCAL6 => params.local = address(value),
+                    sys::netlink::IFLA_VXLAN_LINK => params.underlay = netlink::attr_u32(value),
+                    sys::netlink::IFLA_VXLAN_TTL => params.ttl = netlink::attr_u8(value),
+                    sys::netlink::IFLA_VXLAN_PORT => params.port = netlink::attr_be16(value),
+                    _ => { },
+                }
+            } else {
+                match attr {
+                    sys::netlink::IFLA_IPTUN_LINK => params.underlay = netlink::attr_u32(value),
+                    sys::netlink::IFLA_IPTUN_LOCAL => params.local = address(value),
+                    sys::netlink::IFLA_IPTUN_REMOTE => params.remote = address(value),
+                    sys::netlink::IFLA_IPTUN_TTL => params.ttl = netlink::attr_u8(value),
+                    _ => { },
+                }
+            }
+        }
+
+        // Keys are only in effect with the GRE_KEY flag.
+        if iflags & sys::netlink::GRE_KEY != 0 {
+            params.ikey = ikey;
+        }
+        if oflags & sys::netlink::GRE_KEY != 0 {
+            params.okey = okey;
+        }
+        // The kernel reports 0 for "not bound" and unspecified endpoints.
+        params.underlay = params.underlay.filter(|link| *link != 0);
+        params.local = params.local.filter(|addr| !addr.is_unspecified());
+        params.remote = params.remote.filter(|addr| !addr.is_unspecified());
+
+        Some(params)
+    }
+}
+
+impl fmt::Display for TunnelParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(vni) = self.vni {
+            write!(f, " id {}", vni)?;
+        }
+        if let Some(remote) = self.remote {
+            write!(f, " remote {}", remote)?;
+        }
+        if let Some(local) = self.local {
+            write!(f, " local {}", local)?;
+        }
+        if let Some(underlay) = self.underlay {
+            write!(f, " dev {}", underlay)?;
+        }
+        if let Some(ttl) = self.ttl {
+            if ttl == 0 { write!(f, " ttl inherit")? } else { write!(f, " ttl {}", ttl)? }
+        }
+        if let Some(port) = self.port {
+            write!(f, " dstport {}", port)?;
+        }
+        if let Some(ikey) = self.ikey {
+            write!(f, " ikey {}", ikey)?;
+        }
+        if let Some(okey) = self.okey {
+            write!(f, " okey {}", okey)?;
+        }
+        Ok(())
+    }
+}
+
+
+fn is_ipv6(addr: &IpAddress) -> bool {
+    matches!(*addr, IpAddress::Ipv6(_))
+}
+
+fn address_bytes(addr: &IpAddress) -> &[u8] {
+    match *addr {
+        IpAddress::Ipv4(ref v4) => v4.as_bytes(),
+        IpAddress::Ipv6(ref v6) => v6.as_bytes(),
+        _ => &[],
+    }
+}
+
+fn address(value: &[u8]) -> Option<IpAddress> {
+    match value.len() {
+        4 => Some(IpAddress::Ipv4(Ipv4Address::from_bytes(value))),
+        16 => Some(IpAddress::Ipv6(Ipv6Address::from_bytes(value))),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::link::{self, LinkBuilder};
+    use crate::netns::scratch::Scratch;
+
+    // IFLA_LINKINFO of `ip link add vx0 type vxlan id 42 remote 10.1.2.9 local 10.1.2.3 dstport 4789 ttl 64 dev v0`.
+    const LINKINFO_VXLAN: &[u8] = &[
+        0x0a, 0x00, 0x01, 0x00, 0x76, 0x78, 0x6c, 0x61, 0x6e, 0x00, 0x00, 0x00, 0xe0, 0x00, 0x02, 0x00,
+        0x08, 0x00, 0x01, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08, 0x00, 0x02, 0x00, 0x0a, 0x01, 0x02, 0x09,
+        0x08, 0x00, 0x03, 0x00, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00, 0x0a, 0x01, 0x02, 0x03,
+        0x05, 0x00, 0x05, 0x00, 0x40, 0x00, 0x00, 0x00, 0x05, 0x00, 0x1c, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x1a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x07, 0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x0d, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x19, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x08, 0x00, 0x08, 0x00, 0x2c, 0x01, 0x00, 0x00, 0x08, 0x00, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x06, 0x00, 0x0f, 0x00, 0x12, 0xb5, 0x00, 0x00, 0x05, 0x00, 0x12, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x05, 0x00, 0x1f, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x0c, 0x00, 0x21, 0x00, 0xf7, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0xff,
+    ];
+
+    fn roundtrip(params: &TunnelParams) -> Option<TunnelParams> {
+        // Skip the nlmsghdr added by `build`.
+        let msg = params.encode(MessageBuilder::new(0, 0)).build();
+        TunnelParams::decode(&msg[16..])
+    }
+
+    #[test]
+    fn decode_vxlan() {
+        let params = TunnelParams::decode(LINKINFO_VXLAN).unwrap();
+        let expected = TunnelParams::vxlan(42).remote(IpAddress::v4(10, 1, 2, 9))
+                                              .local(IpAddress::v4(10, 1, 2, 3))
+                                              .ttl(64)
+                                              .underlay(3);
+        assert_eq!(params, expected);
+    }
+
+    #[test]
+    fn decode_without_data() {
+        // IFLA_INFO_KIND alone.
+        let params = TunnelParams::decode(&LINKINFO_VXLAN[..12]).unwrap();
+        assert_eq!(params, TunnelParams::new(TunnelKind::Vxlan, None, None));
+        // Not a tunnel.
+        let veth = [0x09, 0x00, 0x01, 0x00, 0x76, 0x65, 0x74, 0x68, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(TunnelParams::decode(&veth), None);
+    }
+
+    #[test]
+    fn roundtrip_gre() {
+        let local = IpAddress::v4(192, 0, 2, 1);
+        let remote = IpAddress::v4(198, 51, 100, 7);
+        let params = TunnelParams::gre(local, remote).key(0x01020304).ttl(32).underlay(2);
+        assert_eq!(params.kind, TunnelKind::Gre);
+        assert_eq!(roundtrip(&params), Some(params));
+
+        let params = TunnelParams::gretap(local, remote);
+        assert_eq!(params.kind, TunnelKind::Gretap);
+        assert_eq!(roundtrip(&params), Some(params));
+    }
+
+    #[test]
+    fn roundtrip_gre_ipv6() {
+        let local = IpAddress::v6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let remote = IpAddress::v6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        let mut params = TunnelParams::gre(local, remote);
+        params.okey = Some(7);
+        assert_eq!(params.kind, TunnelKind::Ip6Gre);
+        assert_eq!(roundtrip(&params), Some(params));
+
+        let params = TunnelParams::gretap(local, remote).key(9);
+        assert_eq!(params.kind, TunnelKind::Ip6Gretap);
+        assert_eq!(roundtrip(&params), Some(params));
+    }
+
+    #[test]
+    fn roundtrip_iptun() {
+        let local = Ipv4Address::new(192, 0, 2, 1);
+        let remote = Ipv4Address::new(198, 51, 100, 7);
+        let params = TunnelParams::ipip(local, remote).ttl(64);
+        assert_eq!(roundtrip(&params), Some(params));
+
+        let params = TunnelParams::sit(local, remote).underlay(4);
+        assert_eq!(roundtrip(&params), Some(params));
+
+        let params = TunnelParams::ip6tnl(Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 1),
+                                          Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+        assert_eq!(roundtrip(&params), Some(params));
+    }
+
+    #[test]
+    fn roundtrip_vxlan() {
+        let params = TunnelParams::vxlan(4096).remote(IpAddress::v6(0xff05, 0, 0, 0, 0, 0, 0, 0x42))
+                                              .local(IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 1))
+                                              .port(8472);
+        assert_eq!(roundtrip(&params), Some(params));
+    }
+
+    #[test]
+    fn unspecified_endpoints() {
+        // The kernel reports "any" as zeroes.
+        let params = TunnelParams::ipip(Ipv4Address::UNSPECIFIED, Ipv4Address::new(198, 51, 100, 7));
+        let decoded = roundtrip(&params).unwrap();
+        assert_eq!(decoded.local, None);
+        assert_eq!(decoded.remote, params.remote);
+    }
+
+    // Create the tunnel in a scratch namespace and read it back.
+    fn create(params: TunnelParams) -> TunnelParams {
+        let netns = Scratch::new();
+        netns.run(move || {
+            let underlay = LinkBuilder::veth("under0", "under1").up().create().unwrap();
+            let params = match params.kind {
+                TunnelKind::Vxlan => params.underlay(underlay.index()),
+                _ => params,
+            };
+            let kind = params.kind;
+            let tunnel = LinkBuilder::tunnel("tun0", params).create()
+                                                            .unwrap_or_else(|e| panic!("failed to create {}: {}", kind, e));
+            let created = tunnel.tunnel_params().unwrap().expect("no tunnel params");
+            link::delete(tunnel.index()).unwrap();
+            assert!(tunnel.tunnel_params().is_err());
+            created
+        }).unwrap()
+    }
+
+    fn check(params: TunnelParams) {
+        let expected = params.clone();
+        let created = create(params);
+        assert_eq!(created.kind, expected.kind);
+        assert_eq!(created.local, expected.local);
+        assert_eq!(created.remote, expected.remote);
+        assert_eq!(created.ikey, expected.ikey);
+        assert_eq!(created.okey, expected.okey);
+        if expected.ttl.is_some() {
+            assert_eq!(created.ttl, expected.ttl);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs root and the ip_gre module"]
+    fn create_gre() {
+        let local = IpAddress::v4(192, 0, 2, 1);
+        let remote = IpAddress::v4(198, 51, 100, 7);
+        check(TunnelParams::gre(local, remote).key(42).ttl(64));
+        check(TunnelParams::gretap(local, remote));
+    }
+
+    #[test]
+    #[ignore = "needs root and the ip6_gre module"]
+    fn create_gre_ipv6() {
+        let local = IpAddress::v6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let remote = IpAddress::v6(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2);
+        check(TunnelParams::gre(local, remote).ttl(16));
+        check(TunnelParams::gretap(local, remote).key(7));
+    }
+
+    #[test]
+    #[ignore = "needs root and the ipip, sit and ip6_tunnel modules"]
+    fn create_iptun() {
+        let local = Ipv4Address::new(192, 0, 2, 1);
+        let remote = Ipv4Address::new(198, 51, 100, 7);
+        check(TunnelParams::ipip(local, remote).ttl(64));
+        check(TunnelParams::sit(local, remote).ttl(64));
+        check(TunnelParams::ip6tnl(Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 1),
+                                   Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 2)).ttl(64));
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn create_vxlan() {
+        let params = TunnelParams::vxlan(42).remote(IpAddress::v4(10, 1, 2, 9))
+                                            .local(IpAddress::v4(10, 1, 2, 3))
+                                            .ttl(64);
+        let created = create(params.clone());
+        assert!(created.underlay.is_some());
+        assert_eq!(created, params.underlay(created.underlay.unwrap()));
+    }
+}
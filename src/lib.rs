@@ -2,6 +2,8 @@
 extern crate log;
 #[macro_use]
 extern crate cfg_if;
+#[macro_use]
+extern crate bitflags;
 
 #[cfg(unix)]
 extern crate libc;
@@ -10,16 +12,27 @@ extern crate nix;
 
 extern crate smoltcp;
 
+#[cfg(feature = "mio")]
+extern crate mio;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+
 cfg_if! {
     if #[cfg(target_os = "macos")] {
         extern crate core_foundation;
         extern crate system_configuration;
+        extern crate pfctl;
     }
 }
 
 
 mod sys;
 
+#[cfg(target_os = "linux")]
+mod netlink;
+
 
 #[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "linux"))]
 pub mod interface;
@@ -27,8 +40,20 @@ pub mod interface;
 #[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "linux"))]
 pub mod raw_socket;
 
-#[cfg(any(target_os = "macos"))]
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "linux"))]
+pub mod poller;
+
+#[cfg(target_os = "linux")]
+pub mod netns;
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub mod sysctl;
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub mod firewall;
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
 pub mod dns;
 
-#[cfg(any(target_os = "macos"))]
+#[cfg(any(target_os = "macos", target_os = "linux"))]
 pub mod route;
@@ -0,0 +1,380 @@
+// A small netlink(7) transport shared by the rtnetlink and nfnetlink users of this crate.
+#![allow(dead_code)]
+use crate::sys;
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+
+pub const NLMSG_HDRLEN: usize = 16;
+pub const NLA_HDRLEN: usize = 4;
+
+pub fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+// View a `#[repr(C)]` header as bytes.
+pub fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+// Read a `#[repr(C)]` header from the front of a buffer.
+pub fn read<T: Copy + Default>(buf: &[u8]) -> Option<T> {
+    if buf.len() < mem::size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) })
+}
+
+
+/// A netlink request under construction.
+pub struct MessageBuilder {
+    buf: Vec<u8>,
+    nests: Vec<usize>,
+}
+
+impl MessageBuilder {
+    pub fn new(kind: u16, flags: u16) -> MessageBuilder {
+        let hdr = sys::nlmsghdr {
+            nlmsg_len: NLMSG_HDRLEN as u32,
+            nlmsg_type: kind,
+            nlmsg_flags: flags | sys::NLM_F_REQUEST as u16,
+            nlmsg_seq: 0,
+            nlmsg_pid: 0,
+        };
+        let mut buf = Vec::with_capacity(256);
+        buf.extend_from_slice(as_bytes(&hdr));
+        MessageBuilder { buf, nests: Vec::new() }
+    }
+
+    pub fn header<T: Copy>(mut self, header: &T) -> MessageBuilder {
+        self.buf.extend_from_slice(as_bytes(header));
+        self.pad();
+        self
+    }
+
+    fn pad(&mut self) {
+        let len = align(self.buf.len());
+        self.buf.resize(len, 0);
+    }
+
+    pub fn attr(mut self, kind: u16, value: &[u8]) -> MessageBuilder {
+        let rta = sys::netlink::rtattr { rta_len: (NLA_HDRLEN + value.len()) as u16, rta_type: kind };
+        self.buf.extend_from_slice(as_bytes(&rta));
+        self.buf.extend_from_slice(value);
+        self.pad();
+        self
+    }
+
+    pub fn attr_u8(self, kind: u16, value: u8) -> MessageBuilder {
+        self.attr(kind, &[value])
+    }
+
+    pub fn attr_u16(self, kind: u16, value: u16) -> MessageBuilder {
+        self.attr(kind, &value.to_ne_bytes())
+    }
+
+    pub fn attr_u32(self, kind: u16, value: u32) -> MessageBuilder {
+        self.attr(kind, &value.to_ne_bytes())
+    }
+
+    pub fn attr_be16(self, kind: u16, value: u16) -> MessageBuilder {
+        self.attr(kind, &value.to_be_bytes())
+    }
+
+    pub fn attr_be32(self, kind: u16, value: u32) -> MessageBuilder {
+        self.attr(kind, &value.to_be_bytes())
+    }
+
+    // NUL terminated string.
+    pub fn attr_str(self, kind: u16, value: &str) -> MessageBuilder {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.attr(kind, &bytes)
+    }
+
+    pub fn begin_nested(mut self, kind: u16) -> MessageBuilder {
+        self.nests.push(self.buf.len());
+        let rta = sys::netlink::rtattr { rta_len: 0, rta_type: kind | sys::NLA_F_NESTED as u16 };
+        self.buf.extend_from_slice(as_bytes(&rta));
+        self
+    }
+
+    pub fn end_nested(mut self) -> MessageBuilder {
+        let start = self.nests.pop().expect("end_nested without begin_nested");
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        self
+    }
+
+    pub fn build(mut self) -> Vec<u8> {
+        debug_assert!(self.nests.is_empty());
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+}
+
+
+/// A message received from the kernel, `payload` excludes the `nlmsghdr`.
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub kind: u16,
+    pub flags: u16,
+    pub seq: u32,
+    pub payload: Vec<u8>,
+}
+
+/// Split a datagram into the netlink messages it carries.
+pub fn messages(buf: &[u8]) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while offset + NLMSG_HDRLEN <= buf.len() {
+        let hdr: sys::nlmsghdr = unsafe { ptr::read_unaligned(buf[offset..].as_ptr() as *const sys::nlmsghdr) };
+        let len = hdr.nlmsg_len as usize;
+        if len < NLMSG_HDRLEN || offset + len > buf.len() {
+            break;
+        }
+
+        messages.push(Message {
+            kind: hdr.nlmsg_type,
+            flags: hdr.nlmsg_flags,
+            seq: hdr.nlmsg_seq,
+            payload: buf[offset + NLMSG_HDRLEN..offset + len].to_vec(),
+        });
+        offset += align(len);
+    }
+
+    messages
+}
+
+/// Iterate over the attributes in `buf`, yielding `(type, value)`.
+pub struct Attrs<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Attrs<'a> {
+    pub fn new(buf: &'a [u8]) -> Attrs<'a> {
+        Attrs { buf }
+    }
+}
+
+impl<'a> Iterator for Attrs<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rta: sys::netlink::rtattr = read(self.buf)?;
+        let len = rta.rta_len as usize;
+        if len < NLA_HDRLEN || len > self.buf.len() {
+            return None;
+        }
+
+        let value = &self.buf[NLA_HDRLEN..len];
+        let next = std::cmp::min(align(len), self.buf.len());
+        self.buf = &self.buf[next..];
+        Some((rta.rta_type & sys::NLA_TYPE_MASK as u16, value))
+    }
+}
+
+pub fn attr_u8(value: &[u8]) -> Option<u8> {
+    value.first().cloned()
+}
+
+pub fn attr_u16(value: &[u8]) -> Option<u16> {
+    read::<u16>(value)
+}
+
+pub fn attr_u32(value: &[u8]) -> Option<u32> {
+    read::<u32>(value)
+}
+
+pub fn attr_be16(value: &[u8]) -> Option<u16> {
+    read::<u16>(value).map(u16::from_be)
+}
+
+pub fn attr_be32(value: &[u8]) -> Option<u32> {
+    read::<u32>(value).map(u32::from_be)
+}
+
+pub fn attr_str(value: &[u8]) -> Option<String> {
+    let end = value.iter().position(|b| *b == 0).unwrap_or(value.len());
+    String::from_utf8(value[..end].to_vec()).ok()
+}
+
+
+pub struct NetlinkSocket {
+    fd: RawFd,
+    seq: u32,
+}
+
+impl NetlinkSocket {
+    pub fn new(protocol: sys::c_int) -> Result<NetlinkSocket, io::Error> {
+        NetlinkSocket::with_groups(protocol, 0)
+    }
+
+    /// Subscribe to the given multicast groups (RTMGRP_* bits).
+    pub fn with_groups(protocol: sys::c_int, groups: u32) -> Result<NetlinkSocket, io::Error> {
+        let fd = unsafe { sys::socket(sys::AF_NETLINK, sys::SOCK_RAW | sys::SOCK_CLOEXEC, protocol) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut addr: sys::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = sys::AF_NETLINK as sys::sa_family_t;
+        addr.nl_groups = groups;
+        let ret = unsafe {
+            sys::bind(fd,
+                      &addr as *const sys::sockaddr_nl as *const sys::sockaddr,
+                      mem::size_of::<sys::sockaddr_nl>() as sys::socklen_t)
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            unsafe { sys::close(fd) };
+            return Err(err);
+        }
+
+        Ok(NetlinkSocket { fd, seq: 0 })
+    }
+
+    /// Join a multicast group by number (RTNLGRP_*).
+    pub fn add_membership(&mut self, group: u32) -> Result<(), io::Error> {
+        let ret = unsafe {
+            sys::setsockopt(self.fd, sys::SOL_NETLINK, sys::NETLINK_ADD_MEMBERSHIP,
+                            &group as *const u32 as *const sys::c_void,
+                            mem::size_of::<u32>() as sys::socklen_t)
+        };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), io::Error> {
+        let flags = unsafe { sys::fcntl(self.fd, sys::F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let flags = if nonblocking { flags | sys::O_NONBLOCK } else { flags & !sys::O_NONBLOCK };
+        if unsafe { sys::fcntl(self.fd, sys::F_SETFL, flags) } < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn next_seq(&mut self, msg: &mut [u8]) -> u32 {
+        self.seq = self.seq.wrapping_add(1);
+        msg[8..12].copy_from_slice(&self.seq.to_ne_bytes());
+        self.seq
+    }
+
+    fn send_raw(&self, buf: &[u8]) -> Result<(), io::Error> {
+        let ret = unsafe { sys::send(self.fd, buf.as_ptr() as *const sys::c_void, buf.len(), 0) };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn send(&mut self, mut msg: Vec<u8>) -> Result<u32, io::Error> {
+        let seq = self.next_seq(&mut msg);
+        self.send_raw(&msg)?;
+        Ok(seq)
+    }
+
+    pub fn recv(&mut self) -> Result<Vec<Message>, io::Error> {
+        let mut buf = vec![0u8; 65536];
+        let len = unsafe { sys::recv(self.fd, buf.as_mut_ptr() as *mut sys::c_void, buf.len(), 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(messages(&buf[..len as usize]))
+    }
+
+    /// Send a request and collect its replies. Dumps end with NLMSG_DONE,
+    /// everything else is sent with NLM_F_ACK and ends with the acknowledgement.
+    pub fn request(&mut self, msg: Vec<u8>) -> Result<Vec<Message>, io::Error> {
+        let mut msg = msg;
+        let flags = u16::from_ne_bytes([msg[6], msg[7]]);
+        let dump = flags & sys::NLM_F_DUMP as u16 == sys::NLM_F_DUMP as u16;
+        if !dump {
+            let flags = flags | sys::NLM_F_ACK as u16;
+            msg[6..8].copy_from_slice(&flags.to_ne_bytes());
+        }
+
+        let seq = self.send(msg)?;
+        let mut replies = Vec::new();
+        loop {
+            for msg in self.recv()? {
+                if msg.seq != seq {
+                    continue;
+                }
+
+                match msg.kind as sys::c_int {
+                    sys::NLMSG_ERROR | sys::NLMSG_DONE => {
+                        let code = read::<i32>(&msg.payload).unwrap_or(0);
+                        if code < 0 {
+                            return Err(io::Error::from_raw_os_error(-code));
+                        }
+                        return Ok(replies);
+                    },
+                    sys::NLMSG_NOOP => { },
+                    _ => replies.push(msg),
+                }
+            }
+        }
+    }
+
+    /// Send several messages in one datagram and wait for the acknowledgements of
+    /// those sent with NLM_F_ACK. Framed by NFNL_MSG_BATCH_BEGIN and NFNL_MSG_BATCH_END,
+    /// nfnetlink applies them as a single transaction: all or nothing.
+    pub fn batch(&mut self, msgs: Vec<Vec<u8>>) -> Result<(), io::Error> {
+        let mut buf = Vec::new();
+        let mut pending = Vec::new();
+        for mut msg in msgs {
+            let seq = self.next_seq(&mut msg);
+            let flags = u16::from_ne_bytes([msg[6], msg[7]]);
+            if flags & sys::NLM_F_ACK as u16 != 0 {
+                pending.push(seq);
+            }
+            buf.extend_from_slice(&msg);
+        }
+
+        self.send_raw(&buf)?;
+        while !pending.is_empty() {
+            for msg in self.recv()? {
+                if msg.kind as sys::c_int != sys::NLMSG_ERROR || !pending.contains(&msg.seq) {
+                    continue;
+                }
+                pending.retain(|seq| *seq != msg.seq);
+
+                // The first error aborts the whole transaction.
+                let code = read::<i32>(&msg.payload).unwrap_or(0);
+                if code < 0 {
+                    return Err(io::Error::from_raw_os_error(-code));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRawFd for NetlinkSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        unsafe { sys::close(self.fd) };
+    }
+}
@@ -0,0 +1,343 @@
+//! Network namespaces, named the way iproute2 names them (bind mounts under `/run/netns`).
+use crate::sys;
+use crate::interface::link::{self, Namespace};
+
+use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+
+pub const NETNS_RUN_DIR: &str = "/run/netns";
+
+// The namespace of the calling thread, which may differ from the one of the process.
+fn thread_ns_path() -> PathBuf {
+    let tid = unsafe { sys::syscall(sys::SYS_gettid) };
+    PathBuf::from(format!("/proc/self/task/{}/ns/net", tid))
+}
+
+fn cstring(path: &Path) -> Result<CString, io::Error> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn setns(fd: RawFd) -> Result<(), io::Error> {
+    if unsafe { sys::setns(fd, sys::CLONE_NEWNET) } < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn mount(source: &Path, target: &Path, flags: sys::c_ulong) -> Result<(), io::Error> {
+    let source = cstring(source)?;
+    let target = cstring(target)?;
+    let fstype = CString::new("none").unwrap();
+    let ret = unsafe {
+        sys::mount(source.as_ptr(), target.as_ptr(), fstype.as_ptr(), flags, std::ptr::null())
+    };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+// Like `ip netns add`, /run/netns has to be a shared mount so that the
+// namespaces mounted below it show up in other mount namespaces.
+fn prepare_run_dir() -> Result<(), io::Error> {
+    let run_dir = Path::new(NETNS_RUN_DIR);
+    fs::create_dir_all(run_dir)?;
+
+    match mount(Path::new(""), run_dir, sys::MS_SHARED | sys::MS_REC) {
+        Ok(()) => Ok(()),
+        Err(ref e) if e.raw_os_error() == Some(sys::EINVAL) => {
+            mount(run_dir, run_dir, sys::MS_BIND | sys::MS_REC)?;
+            mount(Path::new(""), run_dir, sys::MS_SHARED | sys::MS_REC)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+
+/// A handle to a network namespace, it stays alive as long as the handle is open.
+#[derive(Debug)]
+pub struct NetNs {
+    file: File,
+    name: Option<String>,
+}
+
+impl NetNs {
+    /// The namespace of the calling thread.
+    pub fn current() -> Result<NetNs, io::Error> {
+        NetNs::open_path(thread_ns_path())
+    }
+
+    /// The namespace of a process.
+    pub fn from_pid(pid: u32) -> Result<NetNs, io::Error> {
+        NetNs::open_path(format!("/proc/{}/ns/net", pid))
+    }
+
+    /// A namespace created by `ip netns add` or `NetNs::create`.
+    pub fn open(name: &str) -> Result<NetNs, io::Error> {
+        let mut netns = NetNs::open_path(Path::new(NETNS_RUN_DIR).join(name))?;
+        netns.name = Some(name.to_string());
+        Ok(netns)
+    }
+
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<NetNs, io::Error> {
+        let file = OpenOptions::new().read(true).custom_flags(sys::O_CLOEXEC).open(path)?;
+        Ok(NetNs { file, name: None })
+    }
+
+    /// Create a named namespace, like `ip netns add <name>`.
+    pub fn create(name: &str) -> Result<NetNs, io::Error> {
+        if name.is_empty() || name.contains('/') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid network namespace name"));
+        }
+
+        prepare_run_dir()?;
+
+        let path = Path::new(NETNS_RUN_DIR).join(name);
+        OpenOptions::new().write(true).create_new(true).mode(0o000).open(&path)?;
+
+        // unshare(2) on a thread of its own, the namespace of the caller stays as it is.
+        let target = path.clone();
+        let result = thread::spawn(move || -> Result<(), io::Error> {
+            if unsafe { sys::unshare(sys::CLONE_NEWNET) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            mount(&thread_ns_path(), &target, sys::MS_BIND)
+        }).join().unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "netns thread panicked")));
+
+        if let Err(e) = result {
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+
+        NetNs::open(name)
+    }
+
+    /// Remove a named namespace, like `ip netns delete <name>`. It lives on
+    /// until every process and handle using it is gone.
+    pub fn delete(name: &str) -> Result<(), io::Error> {
+        let path = Path::new(NETNS_RUN_DIR).join(name);
+        let target = cstring(&path)?;
+        if unsafe { sys::umount2(target.as_ptr(), sys::MNT_DETACH) } < 0 {
+            let err = io::Error::last_os_error();
+            // Not a mount point (EINVAL), remove the stale file anyway.
+            if err.raw_os_error() != Some(sys::EINVAL) {
+                return Err(err);
+            }
+        }
+        fs::remove_file(path)
+    }
+
+    /// The named namespaces.
+    pub fn list() -> Result<Vec<String>, io::Error> {
+        let entries = match fs::read_dir(NETNS_RUN_DIR) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Another handle to the same namespace.
+    pub fn try_clone(&self) -> Result<NetNs, io::Error> {
+        Ok(NetNs { file: self.file.try_clone()?, name: self.name.clone() })
+    }
+
+    // The nsfs inode identifies a namespace, whichever path it was opened by.
+    fn id(&self) -> (u64, u64) {
+        self.file.metadata().map(|meta| (meta.dev(), meta.ino())).unwrap_or((0, 0))
+    }
+
+    /// Switch the calling thread into the namespace until the guard is dropped.
+    ///
+    /// Sockets opened meanwhile stay in the namespace after the switch back.
+    pub fn enter(&self) -> Result<NetNsGuard, io::Error> {
+        let previous = NetNs::current()?;
+        setns(self.as_raw_fd())?;
+        Ok(NetNsGuard { previous, _not_send: PhantomData })
+    }
+
+    /// Run `f` on a dedicated thread inside the namespace and return its result.
+    pub fn run<F, T>(&self, f: F) -> Result<T, io::Error>
+        where F: FnOnce() -> T + Send,
+              T: Send
+    {
+        let fd = self.as_raw_fd();
+        thread::scope(|scope| {
+            scope.spawn(move || -> Result<T, io::Error> {
+                setns(fd)?;
+                Ok(f())
+            }).join().unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "netns thread panicked")))
+        })
+    }
+
+    /// Move a link (by index, in the caller's namespace) into this namespace.
+    pub fn move_interface(&self, index: u32) -> Result<(), io::Error> {
+        link::set_netns(index, Namespace::from(self))
+    }
+}
+
+impl PartialEq for NetNs {
+    fn eq(&self, other: &NetNs) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for NetNs { }
+
+impl Hash for NetNs {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl AsRawFd for NetNs {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl<'a> From<&'a NetNs> for Namespace {
+    fn from(netns: &'a NetNs) -> Namespace {
+        Namespace::Fd(netns.as_raw_fd())
+    }
+}
+
+
+/// Returned by `NetNs::enter`, switches the thread back on drop.
+pub struct NetNsGuard {
+    previous: NetNs,
+    // setns(2) affects the calling thread only.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for NetNsGuard {
+    fn drop(&mut self) {
+        if let Err(e) = setns(self.previous.as_raw_fd()) {
+            error!("failed to return to the previous network namespace: {}", e);
+        }
+    }
+}
+
+
+/// Run `f` in `netns`, or right here when there is none.
+pub fn run_in<F, T>(netns: Option<&NetNs>, f: F) -> Result<T, io::Error>
+    where F: FnOnce() -> T + Send,
+          T: Send
+{
+    match netns {
+        Some(netns) => netns.run(f),
+        None => Ok(f()),
+    }
+}
+
+
+/// Throwaway namespaces for tests that change links, addresses, routes or
+/// firewall rules.
+#[cfg(test)]
+pub(crate) mod scratch {
+    use super::NetNs;
+
+    use std::ops::Deref;
+    use std::process::{self, Command};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    /// A named namespace with `lo` up, deleted on drop.
+    pub struct Scratch {
+        netns: NetNs,
+        name: String,
+    }
+
+    impl Scratch {
+        /// Needs CAP_SYS_ADMIN, the tests using it are `#[ignore]`d and run
+        /// as root with `cargo test -- --ignored`.
+        pub fn new() -> Scratch {
+            let name = format!("znet-test-{}-{}", process::id(), NEXT.fetch_add(1, Ordering::SeqCst));
+            let netns = NetNs::create(&name).unwrap_or_else(|e| panic!("failed to create {}: {}", name, e));
+            let scratch = Scratch { netns, name };
+            scratch.ip(&["link", "set", "lo", "up"]);
+            scratch
+        }
+
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+
+        /// Run `ip -n <name> <args>`, for what the crate has no API for (addresses).
+        pub fn ip(&self, args: &[&str]) {
+            let status = Command::new("ip").arg("-n").arg(&self.name).args(args)
+                                           .status()
+                                           .expect("failed to run ip");
+            assert!(status.success(), "ip {:?} failed", args);
+        }
+    }
+
+    impl Deref for Scratch {
+        type Target = NetNs;
+
+        fn deref(&self) -> &NetNs {
+            &self.netns
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = NetNs::delete(&self.name);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::scratch::Scratch;
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn create_run_delete() {
+        let netns = Scratch::new();
+        assert!(NetNs::list().unwrap().iter().any(|name| name == netns.name()));
+        assert_eq!(NetNs::open(netns.name()).unwrap().name(), Some(netns.name()));
+
+        let outside = fs::read_link(thread_ns_path()).unwrap();
+        let inside = netns.run(|| fs::read_link(thread_ns_path()).unwrap()).unwrap();
+        assert_ne!(inside, outside);
+        // `run` leaves the caller where it was.
+        assert_eq!(fs::read_link(thread_ns_path()).unwrap(), outside);
+
+        {
+            let _guard = netns.enter().unwrap();
+            assert_eq!(fs::read_link(thread_ns_path()).unwrap(), inside);
+        }
+        assert_eq!(fs::read_link(thread_ns_path()).unwrap(), outside);
+
+        let name = netns.name().to_string();
+        drop(netns);
+        assert!(!NetNs::list().unwrap().contains(&name));
+        assert!(NetNs::create("a/b").is_err());
+    }
+}
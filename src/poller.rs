@@ -0,0 +1,321 @@
+use crate::sys;
+
+use std::io;
+use std::time::Duration;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+
+bitflags! {
+    pub struct Interest: u8 {
+        const READABLE = 0b01;
+        const WRITABLE = 0b10;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct Event {
+    pub token: usize,
+    pub readable: bool,
+    pub writable: bool,
+    pub error: bool,
+    pub hangup: bool,
+}
+
+// poll(2) and epoll_wait(2) take milliseconds, round up so a short timeout never becomes a busy loop.
+fn timeout_millis(timeout: Option<Duration>) -> sys::c_int {
+    match timeout {
+        Some(duration) => {
+            let millis = duration.as_secs()
+                                 .saturating_mul(1_000)
+                                 .saturating_add((duration.subsec_nanos() as u64 + 999_999) / 1_000_000);
+            if millis > sys::c_int::MAX as u64 {
+                sys::c_int::MAX
+            } else {
+                millis as sys::c_int
+            }
+        },
+        None => -1,
+    }
+}
+
+
+/// Level triggered readiness for several `RawSocket`s (or any other descriptor).
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub struct Poller {
+    epfd: RawFd,
+    events: Vec<sys::epoll_event>,
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl Poller {
+    pub fn new() -> Result<Poller, io::Error> {
+        let epfd = unsafe { sys::epoll_create1(sys::EPOLL_CLOEXEC) };
+        if epfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Poller { epfd, events: Vec::new() })
+    }
+
+    fn ctl(&mut self, op: sys::c_int, fd: RawFd, token: usize, interest: Interest) -> Result<(), io::Error> {
+        let mut flags = 0;
+        if interest.contains(Interest::READABLE) {
+            flags |= sys::EPOLLIN;
+        }
+        if interest.contains(Interest::WRITABLE) {
+            flags |= sys::EPOLLOUT;
+        }
+
+        let mut event = sys::epoll_event { events: flags as u32, u64: token as u64 };
+        if unsafe { sys::epoll_ctl(self.epfd, op, fd, &mut event) } < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn register<S: AsRawFd>(&mut self, source: &S, token: usize, interest: Interest) -> Result<(), io::Error> {
+        self.ctl(sys::EPOLL_CTL_ADD, source.as_raw_fd(), token, interest)?;
+        self.events.push(sys::epoll_event { events: 0, u64: 0 });
+        Ok(())
+    }
+
+    pub fn reregister<S: AsRawFd>(&mut self, source: &S, token: usize, interest: Interest) -> Result<(), io::Error> {
+        self.ctl(sys::EPOLL_CTL_MOD, source.as_raw_fd(), token, interest)
+    }
+
+    pub fn deregister<S: AsRawFd>(&mut self, source: &S) -> Result<(), io::Error> {
+        self.ctl(sys::EPOLL_CTL_DEL, source.as_raw_fd(), 0, Interest::empty())?;
+        self.events.pop();
+        Ok(())
+    }
+
+    /// Wait until at least one registered source is ready or the timeout expires,
+    /// `events` is cleared and filled with the ready sources.
+    pub fn poll(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> Result<usize, io::Error> {
+        events.clear();
+        if self.events.is_empty() {
+            self.events.push(sys::epoll_event { events: 0, u64: 0 });
+        }
+
+        let n = unsafe {
+            sys::epoll_wait(self.epfd,
+                            self.events.as_mut_ptr(),
+                            self.events.len() as sys::c_int,
+                            timeout_millis(timeout))
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for event in self.events.iter().take(n as usize) {
+            let flags = event.events as sys::c_int;
+            events.push(Event {
+                token: event.u64 as usize,
+                readable: flags & sys::EPOLLIN != 0,
+                writable: flags & sys::EPOLLOUT != 0,
+                error: flags & sys::EPOLLERR != 0,
+                hangup: flags & sys::EPOLLHUP != 0,
+            });
+        }
+
+        Ok(events.len())
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl AsRawFd for Poller {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epfd
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl Drop for Poller {
+    fn drop(&mut self) {
+        unsafe { sys::close(self.epfd) };
+    }
+}
+
+
+/// Level triggered readiness for several `RawSocket`s (or any other descriptor).
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub struct Poller {
+    fds: Vec<sys::pollfd>,
+    tokens: Vec<usize>,
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+impl Poller {
+    pub fn new() -> Result<Poller, io::Error> {
+        Ok(Poller { fds: Vec::new(), tokens: Vec::new() })
+    }
+
+    fn position(&self, fd: RawFd) -> Option<usize> {
+        self.fds.iter().position(|pollfd| pollfd.fd == fd)
+    }
+
+    fn events(interest: Interest) -> sys::c_short {
+        let mut events = 0;
+        if interest.contains(Interest::READABLE) {
+            events |= sys::POLLIN;
+        }
+        if interest.contains(Interest::WRITABLE) {
+            events |= sys::POLLOUT;
+        }
+        events
+    }
+
+    pub fn register<S: AsRawFd>(&mut self, source: &S, token: usize, interest: Interest) -> Result<(), io::Error> {
+        let fd = source.as_raw_fd();
+        if self.position(fd).is_some() {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+
+        self.fds.push(sys::pollfd { fd: fd, events: Poller::events(interest), revents: 0 });
+        self.tokens.push(token);
+        Ok(())
+    }
+
+    pub fn reregister<S: AsRawFd>(&mut self, source: &S, token: usize, interest: Interest) -> Result<(), io::Error> {
+        match self.position(source.as_raw_fd()) {
+            Some(idx) => {
+                self.fds[idx].events = Poller::events(interest);
+                self.tokens[idx] = token;
+                Ok(())
+            },
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    pub fn deregister<S: AsRawFd>(&mut self, source: &S) -> Result<(), io::Error> {
+        match self.position(source.as_raw_fd()) {
+            Some(idx) => {
+                self.fds.remove(idx);
+                self.tokens.remove(idx);
+                Ok(())
+            },
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    /// Wait until at least one registered source is ready or the timeout expires,
+    /// `events` is cleared and filled with the ready sources.
+    pub fn poll(&mut self, events: &mut Vec<Event>, timeout: Option<Duration>) -> Result<usize, io::Error> {
+        events.clear();
+
+        let n = unsafe {
+            sys::poll(self.fds.as_mut_ptr(), self.fds.len() as sys::nfds_t, timeout_millis(timeout))
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for (pollfd, token) in self.fds.iter().zip(self.tokens.iter()) {
+            if pollfd.revents == 0 {
+                continue;
+            }
+
+            events.push(Event {
+                token: *token,
+                readable: pollfd.revents & sys::POLLIN != 0,
+                writable: pollfd.revents & sys::POLLOUT != 0,
+                error: pollfd.revents & (sys::POLLERR | sys::POLLNVAL) != 0,
+                hangup: pollfd.revents & sys::POLLHUP != 0,
+            });
+        }
+
+        Ok(events.len())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::UdpSocket;
+
+    fn bound() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        socket
+    }
+
+    fn poll_sorted(poller: &mut Poller, events: &mut Vec<Event>) -> Vec<Event> {
+        poller.poll(events, Some(Duration::from_secs(1))).unwrap();
+        let mut ready = events.clone();
+        ready.sort_by_key(|event| event.token);
+        ready
+    }
+
+    #[test]
+    fn timeout_rounding() {
+        assert_eq!(timeout_millis(None), -1);
+        assert_eq!(timeout_millis(Some(Duration::from_secs(0))), 0);
+        // Rounded up, not down to a busy loop.
+        assert_eq!(timeout_millis(Some(Duration::from_nanos(1))), 1);
+        assert_eq!(timeout_millis(Some(Duration::from_micros(1500))), 2);
+        assert_eq!(timeout_millis(Some(Duration::from_millis(1500))), 1500);
+        assert_eq!(timeout_millis(Some(Duration::new(2, 1))), 2001);
+        assert_eq!(timeout_millis(Some(Duration::from_secs(u64::MAX))), sys::c_int::MAX);
+    }
+
+    #[test]
+    fn readable() {
+        let (a, b, sender) = (bound(), bound(), bound());
+        let mut poller = Poller::new().unwrap();
+        let mut events = Vec::new();
+        poller.register(&a, 1, Interest::READABLE).unwrap();
+        poller.register(&b, 2, Interest::READABLE).unwrap();
+
+        assert_eq!(poller.poll(&mut events, Some(Duration::from_millis(10))).unwrap(), 0);
+        assert!(events.is_empty());
+
+        sender.send_to(b"a", a.local_addr().unwrap()).unwrap();
+        sender.send_to(b"b", b.local_addr().unwrap()).unwrap();
+        let ready = poll_sorted(&mut poller, &mut events);
+        assert_eq!(ready.len(), 2);
+        assert_eq!((ready[0].token, ready[1].token), (1, 2));
+        assert!(ready.iter().all(|event| event.readable && !event.writable && !event.error && !event.hangup));
+
+        // Level triggered, ready until read.
+        let mut buf = [0u8; 4];
+        a.recv(&mut buf).unwrap();
+        let ready = poll_sorted(&mut poller, &mut events);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].token, 2);
+    }
+
+    #[test]
+    fn writable() {
+        let socket = bound();
+        let mut poller = Poller::new().unwrap();
+        let mut events = Vec::new();
+        poller.register(&socket, 7, Interest::READABLE | Interest::WRITABLE).unwrap();
+        let ready = poll_sorted(&mut poller, &mut events);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].token, 7);
+        assert!(ready[0].writable && !ready[0].readable);
+
+        poller.reregister(&socket, 8, Interest::READABLE).unwrap();
+        assert_eq!(poller.poll(&mut events, Some(Duration::from_millis(10))).unwrap(), 0);
+    }
+
+    #[test]
+    fn deregister() {
+        let (a, b, sender) = (bound(), bound(), bound());
+        let mut poller = Poller::new().unwrap();
+        let mut events = Vec::new();
+        poller.register(&a, 1, Interest::READABLE).unwrap();
+        poller.register(&b, 2, Interest::READABLE).unwrap();
+        poller.deregister(&a).unwrap();
+        assert!(poller.deregister(&a).is_err());
+
+        sender.send_to(b"a", a.local_addr().unwrap()).unwrap();
+        sender.send_to(b"b", b.local_addr().unwrap()).unwrap();
+        let ready = poll_sorted(&mut poller, &mut events);
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].token, 2);
+    }
+}
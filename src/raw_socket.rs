@@ -1,5 +1,16 @@
 use crate::sys;
+#[cfg(target_os = "linux")]
+use crate::netns::{self, NetNs};
+#[cfg(target_os = "linux")]
+use crate::interface::Interface;
 
+use smoltcp::wire::EthernetAddress;
+#[cfg(target_os = "linux")]
+use byteorder::{ByteOrder, NetworkEndian};
+#[cfg(feature = "mio")]
+use mio::{Interest, Registry, Token};
+#[cfg(feature = "mio")]
+use mio::unix::SourceFd;
 
 use std::fmt;
 use std::io;
@@ -8,6 +19,8 @@ use std::os::unix::io::RawFd;
 use std::os::unix::io::AsRawFd;
 use std::iter::Iterator;
 use std::ptr;
+#[cfg(target_os = "linux")]
+use std::sync::Arc;
 cfg_if! {
     if #[cfg(any(target_os = "macos", target_os = "freebsd"))] {
         use std::ffi::CString;
@@ -15,12 +28,65 @@ cfg_if! {
     }
 }
 
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "tokio")]
+pub use self::async_io::{AsyncRawSocket, Frames};
+
+
+// Large enough for Ethernet + 802.1Q tags as well as 802.11 + radiotap headers.
+#[cfg(target_os = "linux")]
+const MAX_LINK_HEADER_LEN: usize = 256;
+
 
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 pub enum LinkLayer {
     Eth,
     Ip,
     IpWithPI(usize),
+    /// Linux cooked capture v1 header (16 bytes), `DLT_LINUX_SLL`.
+    LinuxSll,
+    /// Linux cooked capture v2 header (20 bytes), `DLT_LINUX_SLL2`.
+    LinuxSll2,
+    /// IEEE 802.11 frames.
+    Ieee80211,
+    /// IEEE 802.11 frames behind a radiotap header.
+    Ieee80211Radiotap,
+    /// IPv4 over IPv4 tunnel, frames are IP packets.
+    Ipip,
+    /// IPv6 over IPv4 tunnel, frames are IP packets.
+    Sit,
+    /// IP over IPv6 tunnel, frames are IP packets.
+    Ip6Tnl,
+    /// GRE tunnel, frames are IP packets.
+    Gre,
+    /// No link layer header at all (WireGuard, TUN), frames are IP packets.
+    Headerless,
+}
+
+impl LinkLayer {
+    /// Map an `ARPHRD_*` device type to the link layer of its frames.
+    #[cfg(target_os = "linux")]
+    pub fn from_hwtype(hwtype: u16) -> Option<LinkLayer> {
+        match hwtype {
+            sys::ARPHRD_ETHER | sys::ARPHRD_LOOPBACK | sys::ARPHRD_IEEE802 => Some(LinkLayer::Eth),
+            sys::ARPHRD_PPP | sys::ARPHRD_RAWIP => Some(LinkLayer::Ip),
+            sys::ARPHRD_IEEE80211 => Some(LinkLayer::Ieee80211),
+            sys::ARPHRD_IEEE80211_RADIOTAP => Some(LinkLayer::Ieee80211Radiotap),
+            sys::ARPHRD_TUNNEL => Some(LinkLayer::Ipip),
+            sys::ARPHRD_SIT => Some(LinkLayer::Sit),
+            sys::ARPHRD_TUNNEL6 => Some(LinkLayer::Ip6Tnl),
+            sys::ARPHRD_IPGRE | sys::ARPHRD_IP6GRE => Some(LinkLayer::Gre),
+            sys::ARPHRD_NONE => Some(LinkLayer::Headerless),
+            _ => None,
+        }
+    }
+
+    /// Whether every frame is a bare IP packet.
+    pub fn is_ip(&self) -> bool {
+        matches!(*self, LinkLayer::Ip | LinkLayer::Ipip | LinkLayer::Sit
+                        | LinkLayer::Ip6Tnl | LinkLayer::Gre | LinkLayer::Headerless)
+    }
 }
 
 impl fmt::Display for LinkLayer {
@@ -29,6 +95,15 @@ impl fmt::Display for LinkLayer {
             LinkLayer::Eth => write!(f, "Ethernet"),
             LinkLayer::Ip => write!(f, "Ip"),
             LinkLayer::IpWithPI(size) => write!(f, "IpWith{}PrefixBytes", size),
+            LinkLayer::LinuxSll => write!(f, "LinuxSll"),
+            LinkLayer::LinuxSll2 => write!(f, "LinuxSll2"),
+            LinkLayer::Ieee80211 => write!(f, "Ieee80211"),
+            LinkLayer::Ieee80211Radiotap => write!(f, "Ieee80211Radiotap"),
+            LinkLayer::Ipip => write!(f, "Ipip"),
+            LinkLayer::Sit => write!(f, "Sit"),
+            LinkLayer::Ip6Tnl => write!(f, "Ip6Tnl"),
+            LinkLayer::Gre => write!(f, "Gre"),
+            LinkLayer::Headerless => write!(f, "Headerless"),
         }
     }
 }
@@ -40,8 +115,8 @@ pub struct BufferReader<'a> {
 }
 
 impl<'a> BufferReader<'a> {
-    pub fn new(buffer: &'a [u8], len: usize) -> BufferReader {
-        BufferReader { buffer: buffer, len: len, offset: 0 }
+    pub fn new(buffer: &'a [u8], len: usize) -> BufferReader<'a> {
+        BufferReader { buffer, len, offset: 0 }
     }
     
     pub fn offset(&self) -> usize {
@@ -51,6 +126,10 @@ impl<'a> BufferReader<'a> {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 impl<'a> Iterator for BufferReader<'a> {
@@ -58,31 +137,35 @@ impl<'a> Iterator for BufferReader<'a> {
 
     #[cfg(any(target_os = "macos", target_os = "freebsd"))]
     fn next(&mut self) -> Option<Self::Item> {
-        let len = self.len();
+        let len = self.len.min(self.buffer.len());
         let offset = self.offset;
-        if offset >= len  {
+        if offset + sys::BPF_HDR_SIZE > len {
+            // Nothing or a cut header left, skip it so the caller can drop the rest.
+            self.offset = self.offset.max(len);
+            return None;
+        }
+
+        let bpf_buf = &self.buffer[offset..offset+sys::BPF_HDR_SIZE];
+        let bpf_packet = bpf_buf.as_ptr() as *const sys::bpf_hdr;
+        let bh_hdrlen = unsafe { (*bpf_packet).bh_hdrlen } as usize;
+        // bh_datalen is the length on the wire, only bh_caplen bytes were captured.
+        let bh_caplen = unsafe { (*bpf_packet).bh_caplen } as usize;
+
+        if bh_hdrlen < sys::BPF_HDR_SIZE || offset + bh_hdrlen + bh_caplen > len {
+            self.offset = len;
             None
         } else {
-            let bpf_buf = &self.buffer[offset..offset+sys::BPF_HDR_SIZE];
-            let bpf_packet = bpf_buf.as_ptr() as *const sys::bpf_hdr;
-            let bh_hdrlen = unsafe { (*bpf_packet).bh_hdrlen } as usize ;
-            let bh_datalen = unsafe { (*bpf_packet).bh_datalen } as usize;
-            
-            if bh_datalen + bh_hdrlen > len as usize {
-                None
-            } else {
-                self.offset = offset + sys::BPF_WORDALIGN((bh_datalen + bh_hdrlen) as isize) as usize;
-                let bpos = offset + bh_hdrlen;
-                let epos = offset + bh_hdrlen + bh_datalen;
-                let pos = (bpos, epos);
-                Some(pos)
-            }
+            self.offset = offset + sys::BPF_WORDALIGN((bh_hdrlen + bh_caplen) as isize) as usize;
+            let bpos = offset + bh_hdrlen;
+            let epos = offset + bh_hdrlen + bh_caplen;
+            let pos = (bpos, epos);
+            Some(pos)
         }
     }
 
-    #[cfg(all(target_os = "linux"))]
+    #[cfg(target_os = "linux")]
     fn next(&mut self) -> Option<Self::Item> {
-        let len = self.len;
+        let len = self.len.min(self.buffer.len());
         let offset = self.offset;
         if offset >= len  {
             None
@@ -96,61 +179,158 @@ impl<'a> Iterator for BufferReader<'a> {
 
 
 
+/// A frame buffer for `RawSocket::recv_batch`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PacketBuf {
+    buffer: Vec<u8>,
+    len: usize,
+}
+
+impl PacketBuf {
+    pub fn new(capacity: usize) -> PacketBuf {
+        PacketBuf { buffer: vec![0u8; capacity], len: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for PacketBuf {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+/// Capture counters of a `RawSocket`, cumulative since the socket was opened.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct Stats {
+    /// Packets seen by the kernel for this socket, including the dropped ones.
+    pub received: u64,
+    /// Packets dropped by the kernel because the socket buffer was full.
+    pub dropped: u64,
+    /// Packets dropped while the TPACKET_V3 ring was frozen (Linux only).
+    pub freeze_queue: Option<u64>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} packets received, {} packets dropped by kernel", self.received, self.dropped)?;
+        if let Some(freeze_queue) = self.freeze_queue {
+            write!(f, ", {} packets dropped by freeze queue", freeze_queue)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RawSocket {
     fd: sys::c_int,
     dt: LinkLayer,
     ifname: String,
+    // Names are per namespace, the index is what the socket is bound to.
+    #[cfg(target_os = "linux")]
+    ifindex: u32,
+    // Set by `with_ifname_in`, where `ifindex` is looked up.
+    #[cfg(target_os = "linux")]
+    netns: Option<Arc<NetNs>>,
     blen: usize,
+    // Linux resets the counters on every read, so they are accumulated here.
+    #[cfg(target_os = "linux")]
+    stats: Stats,
+    // The frames of the last BPF read `recv_batch` had no buffer left for.
+    #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+    pending: Vec<u8>,
 }
 
 #[cfg(all(target_os = "linux", target_env = "gnu"))]
 impl RawSocket {
-    pub fn enable_promiscuous_mode(&mut self) -> Result<(), io::Error> {
-        let fd = self.fd;
-        
-        unsafe {
-            let mut ifr: sys::ifreq = mem::zeroed();
-            
-            for (i, byte) in self.ifname.bytes().enumerate() {
-                ifr.ifr_name[i] = byte as sys::c_char;
-            }
+    // Membership is bound to the socket, the kernel reverts it when the socket is closed.
+    fn set_membership(&mut self, mr_type: sys::c_int, addr: Option<EthernetAddress>, add: bool) -> Result<(), io::Error> {
+        let mut mreq: sys::packet_mreq = unsafe { mem::zeroed() };
+        mreq.mr_ifindex = self.ifindex as sys::c_int;
+        mreq.mr_type = mr_type as sys::c_ushort;
+        if let Some(addr) = addr {
+            mreq.mr_alen = addr.as_bytes().len() as sys::c_ushort;
+            mreq.mr_address[..addr.as_bytes().len()].copy_from_slice(addr.as_bytes());
+        }
 
-            if sys::ioctl(fd, sys::SIOCGIFFLAGS, &ifr) < 0 {
-                return Err(io::Error::last_os_error());
-            }
-            
-            ifr.ifru.flags |= sys::IFF_PROMISC as i16;
+        let optname = if add { sys::PACKET_ADD_MEMBERSHIP } else { sys::PACKET_DROP_MEMBERSHIP };
+        let ret = unsafe {
+            sys::setsockopt(self.fd, sys::SOL_PACKET, optname,
+                            &mreq as *const sys::packet_mreq as *const sys::c_void,
+                            mem::size_of::<sys::packet_mreq>() as sys::socklen_t)
+        };
 
-            if sys::ioctl(fd, sys::SIOCSIFFLAGS, &ifr) < 0 {
-                return Err(io::Error::last_os_error());
-            }
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
         }
+    }
 
-        Ok(())
+    pub fn enable_promiscuous_mode(&mut self) -> Result<(), io::Error> {
+        self.set_membership(sys::PACKET_MR_PROMISC, None, true)
+    }
+
+    pub fn disable_promiscuous_mode(&mut self) -> Result<(), io::Error> {
+        self.set_membership(sys::PACKET_MR_PROMISC, None, false)
+    }
+
+    pub fn enable_allmulti_mode(&mut self) -> Result<(), io::Error> {
+        self.set_membership(sys::PACKET_MR_ALLMULTI, None, true)
+    }
+
+    pub fn disable_allmulti_mode(&mut self) -> Result<(), io::Error> {
+        self.set_membership(sys::PACKET_MR_ALLMULTI, None, false)
+    }
+
+    pub fn join_multicast(&mut self, addr: EthernetAddress) -> Result<(), io::Error> {
+        self.set_membership(sys::PACKET_MR_MULTICAST, Some(addr), true)
+    }
+
+    pub fn leave_multicast(&mut self, addr: EthernetAddress) -> Result<(), io::Error> {
+        self.set_membership(sys::PACKET_MR_MULTICAST, Some(addr), false)
+    }
+
+    /// Whether the interface is currently in promiscuous mode,
+    /// whoever (this socket, another socket or `ip link`) put it there.
+    pub fn is_promiscuous_mode(&self) -> Result<bool, io::Error> {
+        let ifindex = self.ifindex;
+        let info = netns::run_in(self.netns.as_deref(), || {
+            Interface::with_index(ifindex).and_then(|iface| iface.link_info())
+        })??;
+        Ok(info.promiscuity > 0)
+    }
+
+    /// Open the socket inside a network namespace, it keeps capturing there.
+    pub fn with_ifname_in(ifname: &str, netns: Option<&NetNs>) -> Result<RawSocket, io::Error> {
+        let mut socket = netns::run_in(netns, || RawSocket::with_ifname(ifname))??;
+        if let Some(netns) = netns {
+            socket.netns = Some(Arc::new(netns.try_clone()?));
+        }
+        Ok(socket)
     }
 
     pub fn with_ifname(ifname: &str) -> Result<RawSocket, io::Error> {
-        let flags = sys::if_name_to_flags(ifname).unwrap();
-        // IFF_PROMISC
-        let link_layer = 
-            if flags & sys::IFF_LOOPBACK != 0 {
-                LinkLayer::Eth
-            } else if flags & sys::IFF_BROADCAST != 0 {
-                LinkLayer::Eth
-            } else if flags & sys::IFF_POINTOPOINT != 0 {
-                if flags & sys::IFF_NO_PI as i32 != 0 {
-                    LinkLayer::Ip
-                } else {
-                    LinkLayer::IpWithPI(4)
-                }
-            } else {
-                return Err(io::Error::new(io::ErrorKind::Other, "Unknow LinkLayer"))
-            };
-        let protocol = match link_layer {
-            LinkLayer::Eth | LinkLayer::IpWithPI(_) => (sys::ETH_P_ALL as u16).to_be(),
-            LinkLayer::Ip => (sys::ETH_P_IP as u16).to_be()
+        let hwtype = sys::if_name_to_hwtype(ifname)?;
+        let link_layer = match LinkLayer::from_hwtype(hwtype) {
+            Some(link_layer) => link_layer,
+            None => return Err(io::Error::new(io::ErrorKind::Other, format!("Unknow LinkLayer (ARPHRD {})", hwtype))),
         };
+        let protocol = (sys::ETH_P_ALL as u16).to_be();
 
         let fd = unsafe {
             sys::socket(sys::AF_PACKET, sys::SOCK_RAW | sys::SOCK_NONBLOCK, protocol as i32)
@@ -159,31 +339,16 @@ impl RawSocket {
         if fd == -1 {
             return Err(io::Error::last_os_error())
         }
-        
-        // let mut ifr: sys::ifreq = unsafe { mem::zeroed() };
-        // for (i, byte) in ifname.bytes().enumerate() {
-        //     ifr.ifr_name[i] = byte as sys::c_char;
-        // }
-
-        // if unsafe { sys::ioctl(fd, sys::SIOCGIFFLAGS, &ifr) } < 0 {
-        //     return Err(io::Error::last_os_error());
-        // }
-        // unsafe {
-        //     ifr.ifru.flags |= sys::IFF_PROMISC as i16;
-        // }
-        // if unsafe { sys::ioctl(fd, sys::SIOCSIFFLAGS, &ifr) } < 0 {
-        //     return Err(io::Error::last_os_error());
-        // }
 
         let ifindex = sys::if_name_to_index(ifname);
 
         let sll = sys::sockaddr_ll {
             sll_family:   sys::AF_PACKET as u16,
-            sll_protocol: protocol as u16,
+            sll_protocol: protocol,
             sll_ifindex:  ifindex as i32,
-            sll_hatype:   1,
+            sll_hatype:   0,
             sll_pkttype:  0,
-            sll_halen:    6,
+            sll_halen:    0,
             sll_addr:     [0; 8]
         };
         
@@ -195,11 +360,73 @@ impl RawSocket {
             return Err(io::Error::last_os_error())
         }
         
-        let mtu = sys::if_name_to_mtu(ifname).unwrap();
+        let mtu = match sys::if_name_to_mtu(ifname) {
+            Ok(mtu) => mtu,
+            Err(e) => {
+                unsafe { sys::close(fd) };
+                return Err(e);
+            }
+        };
+        // Room for the link layer header in front of a full MTU packet.
+        let blen = mtu + MAX_LINK_HEADER_LEN;
 
-        Ok(RawSocket { fd: fd, dt: link_layer, blen: mtu, ifname: ifname.to_string() })
+        Ok(RawSocket {
+            fd,
+            dt: link_layer,
+            blen,
+            ifname: ifname.to_string(),
+            ifindex,
+            netns: None,
+            stats: Stats::default(),
+        })
     }
     
+    /// Capture on every interface at once. Frames come back behind a synthesised
+    /// Linux cooked (SLL2) header carrying the ifindex, protocol and packet type,
+    /// `send` expects the same header in front of the payload.
+    pub fn any() -> Result<RawSocket, io::Error> {
+        let protocol = (sys::ETH_P_ALL as u16).to_be();
+
+        let fd = unsafe {
+            sys::socket(sys::AF_PACKET, sys::SOCK_DGRAM | sys::SOCK_NONBLOCK, protocol as i32)
+        };
+
+        if fd == -1 {
+            return Err(io::Error::last_os_error())
+        }
+
+        let sll = sys::sockaddr_ll {
+            sll_family:   sys::AF_PACKET as u16,
+            sll_protocol: protocol,
+            sll_ifindex:  0,
+            sll_hatype:   0,
+            sll_pkttype:  0,
+            sll_halen:    0,
+            sll_addr:     [0; 8]
+        };
+
+        let sa = &sll as *const sys::sockaddr_ll as *const sys::sockaddr;
+        let ret = unsafe { sys::bind(fd, sa, mem::size_of::<sys::sockaddr_ll>() as u32) };
+
+        if ret == -1 {
+            unsafe { sys::close(fd) };
+            return Err(io::Error::last_os_error())
+        }
+
+        // The loopback MTU is 64K.
+        let blen = 65536 + SLL2_HDR_LEN;
+
+        Ok(RawSocket {
+            fd,
+            dt: LinkLayer::LinuxSll2,
+            blen,
+            ifname: "any".to_string(),
+            ifindex: 0,
+            netns: None,
+            stats: Stats::default(),
+        })
+    }
+
     pub fn link_layer(&self) -> LinkLayer {
         self.dt
     }
@@ -208,7 +435,39 @@ impl RawSocket {
         self.blen
     }
 
+    pub fn stats(&mut self) -> Result<Stats, io::Error> {
+        let mut tp_stats = sys::tpacket_stats_v3::default();
+        let mut len = mem::size_of::<sys::tpacket_stats_v3>() as sys::socklen_t;
+        let ret = unsafe {
+            sys::getsockopt(self.fd, sys::SOL_PACKET, sys::PACKET_STATISTICS,
+                            &mut tp_stats as *mut sys::tpacket_stats_v3 as *mut sys::c_void,
+                            &mut len)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // The kernel already adds `tp_drops` into `tp_packets`.
+        self.stats.received += tp_stats.tp_packets as u64;
+        self.stats.dropped += tp_stats.tp_drops as u64;
+        // Only a TPACKET_V3 socket fills the freeze queue counter.
+        if len as usize == mem::size_of::<sys::tpacket_stats_v3>() {
+            let freeze_queue = self.stats.freeze_queue.unwrap_or(0);
+            self.stats.freeze_queue = Some(freeze_queue + tp_stats.tp_freeze_q_cnt as u64);
+        }
+
+        Ok(self.stats)
+    }
+
+    fn is_cooked(&self) -> bool {
+        self.dt == LinkLayer::LinuxSll2
+    }
+
     pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if self.is_cooked() {
+            return self.recv_cooked(buf);
+        }
+
         let len = unsafe {
             sys::recv(self.fd, 
                       buf.as_mut_ptr() as *mut sys::c_void,
@@ -221,7 +480,33 @@ impl RawSocket {
         }
     }
 
+    fn recv_cooked(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if buf.len() < SLL2_HDR_LEN {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let mut sll: sys::sockaddr_ll = unsafe { mem::zeroed() };
+        let mut sll_len = mem::size_of::<sys::sockaddr_ll>() as sys::socklen_t;
+        let len = unsafe {
+            sys::recvfrom(self.fd,
+                          buf[SLL2_HDR_LEN..].as_mut_ptr() as *mut sys::c_void,
+                          buf.len() - SLL2_HDR_LEN, 0,
+                          &mut sll as *mut sys::sockaddr_ll as *mut sys::sockaddr,
+                          &mut sll_len)
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        write_sll2_header(&sll, &mut buf[..SLL2_HDR_LEN]);
+        Ok(len as usize + SLL2_HDR_LEN)
+    }
+
     pub fn send(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        if self.is_cooked() {
+            return self.send_cooked(buf);
+        }
+
         let len = unsafe {
             sys::send(self.fd,
                       buf.as_ptr() as *const sys::c_void,
@@ -235,6 +520,144 @@ impl RawSocket {
             Ok(len as usize)
         }
     }
+
+    // The SLL2 header in front of the payload tells where the packet goes.
+    fn send_cooked(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        if buf.len() < SLL2_HDR_LEN {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let sll = read_sll2_header(&buf[..SLL2_HDR_LEN]);
+        let len = unsafe {
+            sys::sendto(self.fd,
+                        buf[SLL2_HDR_LEN..].as_ptr() as *const sys::c_void,
+                        buf.len() - SLL2_HDR_LEN, 0,
+                        &sll as *const sys::sockaddr_ll as *const sys::sockaddr,
+                        mem::size_of::<sys::sockaddr_ll>() as sys::socklen_t)
+        };
+
+        if len < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize + SLL2_HDR_LEN)
+        }
+    }
+
+    /// Receive up to `bufs.len()` frames with a single `recvmmsg` call, one frame
+    /// per buffer, returns the number of frames received.
+    pub fn recv_batch(&mut self, bufs: &mut [PacketBuf]) -> Result<usize, io::Error> {
+        let offset = if self.is_cooked() { SLL2_HDR_LEN } else { 0 };
+        if bufs.iter().any(|buf| buf.capacity() < offset) {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let mut names = vec![unsafe { mem::zeroed::<sys::sockaddr_ll>() }; bufs.len()];
+        let mut iovecs = bufs.iter_mut()
+            .map(|buf| sys::iovec {
+                iov_base: buf.buffer[offset..].as_mut_ptr() as *mut sys::c_void,
+                iov_len: buf.buffer.len() - offset,
+            })
+            .collect::<Vec<sys::iovec>>();
+        let mut msgs = iovecs.iter_mut().zip(names.iter_mut())
+            .map(|(iov, name)| {
+                let mut msg: sys::mmsghdr = unsafe { mem::zeroed() };
+                msg.msg_hdr.msg_iov = iov as *mut sys::iovec;
+                msg.msg_hdr.msg_iovlen = 1;
+                if offset > 0 {
+                    msg.msg_hdr.msg_name = name as *mut sys::sockaddr_ll as *mut sys::c_void;
+                    msg.msg_hdr.msg_namelen = mem::size_of::<sys::sockaddr_ll>() as sys::socklen_t;
+                }
+                msg
+            })
+            .collect::<Vec<sys::mmsghdr>>();
+
+        let ret = unsafe {
+            sys::recvmmsg(self.fd, msgs.as_mut_ptr(), msgs.len() as sys::c_uint, 0, ptr::null_mut())
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let n = ret as usize;
+        for ((buf, msg), name) in bufs.iter_mut().zip(msgs.iter()).zip(names.iter()).take(n) {
+            if offset > 0 {
+                write_sll2_header(name, &mut buf.buffer[..SLL2_HDR_LEN]);
+            }
+            buf.len = msg.msg_len as usize + offset;
+        }
+
+        Ok(n)
+    }
+
+    /// Send the frames with a single `sendmmsg` call,
+    /// returns the number of frames sent.
+    pub fn send_batch(&mut self, frames: &[&[u8]]) -> Result<usize, io::Error> {
+        let offset = if self.is_cooked() { SLL2_HDR_LEN } else { 0 };
+        if frames.iter().any(|frame| frame.len() < offset) {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let mut names = frames.iter()
+            .map(|frame| if offset > 0 { read_sll2_header(&frame[..SLL2_HDR_LEN]) } else { unsafe { mem::zeroed() } })
+            .collect::<Vec<sys::sockaddr_ll>>();
+        let mut iovecs = frames.iter()
+            .map(|frame| sys::iovec {
+                iov_base: frame[offset..].as_ptr() as *mut sys::c_void,
+                iov_len: frame.len() - offset,
+            })
+            .collect::<Vec<sys::iovec>>();
+        let mut msgs = iovecs.iter_mut().zip(names.iter_mut())
+            .map(|(iov, name)| {
+                let mut msg: sys::mmsghdr = unsafe { mem::zeroed() };
+                msg.msg_hdr.msg_iov = iov as *mut sys::iovec;
+                msg.msg_hdr.msg_iovlen = 1;
+                if offset > 0 {
+                    msg.msg_hdr.msg_name = name as *mut sys::sockaddr_ll as *mut sys::c_void;
+                    msg.msg_hdr.msg_namelen = mem::size_of::<sys::sockaddr_ll>() as sys::socklen_t;
+                }
+                msg
+            })
+            .collect::<Vec<sys::mmsghdr>>();
+
+        let ret = unsafe { sys::sendmmsg(self.fd, msgs.as_mut_ptr(), msgs.len() as sys::c_uint, 0) };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
+
+// https://www.tcpdump.org/linktypes/LINKTYPE_LINUX_SLL2.html
+#[cfg(target_os = "linux")]
+pub const SLL2_HDR_LEN: usize = 20;
+
+#[cfg(target_os = "linux")]
+fn write_sll2_header(sll: &sys::sockaddr_ll, header: &mut [u8]) {
+    // `sll_protocol` is already in network byte order.
+    header[0..2].copy_from_slice(&sll.sll_protocol.to_ne_bytes());
+    NetworkEndian::write_u16(&mut header[2..4], 0);
+    NetworkEndian::write_i32(&mut header[4..8], sll.sll_ifindex);
+    NetworkEndian::write_u16(&mut header[8..10], sll.sll_hatype);
+    header[10] = sll.sll_pkttype;
+    header[11] = sll.sll_halen;
+    header[12..20].copy_from_slice(&sll.sll_addr);
+}
+
+#[cfg(target_os = "linux")]
+fn read_sll2_header(header: &[u8]) -> sys::sockaddr_ll {
+    let mut sll_addr = [0u8; 8];
+    sll_addr.copy_from_slice(&header[12..20]);
+
+    sys::sockaddr_ll {
+        sll_family:   sys::AF_PACKET as u16,
+        sll_protocol: u16::from_ne_bytes([header[0], header[1]]),
+        sll_ifindex:  NetworkEndian::read_i32(&header[4..8]),
+        sll_hatype:   NetworkEndian::read_u16(&header[8..10]),
+        sll_pkttype:  header[10],
+        sll_halen:    header[11],
+        sll_addr,
+    }
 }
 
 
@@ -330,28 +753,26 @@ impl RawSocket {
         }
     }
     
+    // BIOCPROMISC is bound to the BPF descriptor, the kernel reverts it when the descriptor is closed.
     pub fn enable_promiscuous_mode(&mut self) -> Result<(), io::Error> {
-        let bpf_fd = self.fd;
-
-        unsafe {
-            let mut ifr: sys::ifreq = mem::zeroed();
-            
-            for (i, byte) in self.ifname.bytes().enumerate() {
-                ifr.ifr_name[i] = byte as sys::c_char;
-            }
-
-            if sys::ioctl(bpf_fd, sys::SIOCGIFFLAGS, &ifr) < 0 {
-                return Err(io::Error::last_os_error());
-            }
-            
-            ifr.ifru.flags |= sys::IFF_PROMISC as i16;
-
-            if sys::ioctl(bpf_fd, sys::SIOCSIFFLAGS, &ifr) < 0 {
-                return Err(io::Error::last_os_error());
-            }
+        let ret = unsafe { sys::ioctl(self.fd, sys::BIOCPROMISC) };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
         }
+    }
 
-        Ok(())
+    pub fn disable_promiscuous_mode(&mut self) -> Result<(), io::Error> {
+        // BPF has no ioctl to leave promiscuous mode, only closing the descriptor does.
+        Err(io::Error::new(io::ErrorKind::Other, "BPF can not leave promiscuous mode, drop the RawSocket instead"))
+    }
+
+    /// Whether the interface is currently in promiscuous mode,
+    /// whoever (this descriptor, another one or `ifconfig`) put it there.
+    pub fn is_promiscuous_mode(&self) -> Result<bool, io::Error> {
+        let flags = sys::if_name_to_flags(&self.ifname)?;
+        Ok(flags & sys::IFF_PROMISC != 0)
     }
 
     pub fn with_ifname(ifname: &str) -> Result<RawSocket, io::Error> {
@@ -386,7 +807,7 @@ impl RawSocket {
                     _ => return Err(io::Error::new(io::ErrorKind::Other, "Unknow LinkLayer"))
                 };
                 let blen = RawSocket::get_blen(bpf_fd).unwrap();
-                Ok(RawSocket { fd: bpf_fd, dt: link_layer, blen: blen, ifname: ifname.to_string() })
+                Ok(RawSocket { fd: bpf_fd, dt: link_layer, blen: blen, ifname: ifname.to_string(), pending: Vec::new() })
             },
             Err(e) => Err(e)
         }
@@ -400,6 +821,16 @@ impl RawSocket {
         self.blen
     }
 
+    pub fn stats(&mut self) -> Result<Stats, io::Error> {
+        let mut bs = sys::bpf_stat::default();
+        let ret = unsafe { sys::ioctl(self.fd, sys::BIOCGSTATS, &mut bs) };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Stats { received: bs.bs_recv as u64, dropped: bs.bs_drop as u64, freeze_queue: None })
+        }
+    }
+
     pub fn recv(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         let len = unsafe { sys::read(self.fd, buf.as_mut_ptr() as *mut sys::c_void, self.blen) };
 
@@ -421,26 +852,91 @@ impl RawSocket {
             Ok(size)
         }
     }
+
+    /// Receive up to `bufs.len()` frames, one per buffer like on Linux.
+    ///
+    /// BPF has no batched syscalls but a single `read` returns every frame captured
+    /// so far, they are spread over the buffers and those left over are handed out
+    /// by the next call, before reading again. A frame longer than its buffer is cut.
+    pub fn recv_batch(&mut self, bufs: &mut [PacketBuf]) -> Result<usize, io::Error> {
+        if bufs.is_empty() {
+            return Ok(0);
+        }
+
+        if self.pending.is_empty() {
+            self.pending.resize(self.blen, 0);
+            let len = unsafe { sys::read(self.fd, self.pending.as_mut_ptr() as *mut sys::c_void, self.blen) };
+            if len < 0 {
+                self.pending.clear();
+                return Err(io::Error::last_os_error());
+            }
+            self.pending.truncate(len as usize);
+        }
+
+        let mut n = 0;
+        let mut reader = BufferReader::new(&self.pending, self.pending.len());
+        while n < bufs.len() {
+            let (start, end) = match reader.next() {
+                Some(frame) => frame,
+                None => break,
+            };
+            let buf = &mut bufs[n];
+            let len = std::cmp::min(end - start, buf.capacity());
+            buf.buffer[..len].copy_from_slice(&self.pending[start..start + len]);
+            buf.len = len;
+            n += 1;
+        }
+
+        // Frames stay word aligned, the consumed part is a multiple of the alignment.
+        let consumed = std::cmp::min(reader.offset(), self.pending.len());
+        self.pending.drain(..consumed);
+        // Without a whole frame left the next call has to read again.
+        if self.pending.len() < sys::BPF_HDR_SIZE {
+            self.pending.clear();
+        }
+
+        Ok(n)
+    }
+
+    pub fn send_batch(&mut self, frames: &[&[u8]]) -> Result<usize, io::Error> {
+        let mut n = 0;
+        for frame in frames.iter() {
+            match self.send(frame) {
+                Ok(_) => n += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock && n > 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+// select(2) on macOS rejects a tv_usec of a second or more.
+fn millis_to_timeval(millis: u64) -> sys::timeval {
+    sys::timeval {
+        tv_sec: (millis / 1_000) as sys::time_t,
+        tv_usec: ((millis % 1_000) * 1_000) as sys::suseconds_t,
+    }
 }
 
 impl RawSocket {
     pub fn wait(&self, millis: Option<u64>) -> io::Result<()> {
         let fd = self.fd;
         unsafe {
-            let mut readfds = mem::uninitialized::<sys::fd_set>();
+            let mut readfds: sys::fd_set = mem::zeroed();
             sys::FD_ZERO(&mut readfds);
             sys::FD_SET(fd, &mut readfds);
 
-            let mut writefds = mem::uninitialized::<sys::fd_set>();
+            let mut writefds: sys::fd_set = mem::zeroed();
             sys::FD_ZERO(&mut writefds);
 
-            let mut exceptfds = mem::uninitialized::<sys::fd_set>();
+            let mut exceptfds: sys::fd_set = mem::zeroed();
             sys::FD_ZERO(&mut exceptfds);
 
-            let mut timeout = sys::timeval { tv_sec: 0, tv_usec: 0 };
+            let mut timeout = millis_to_timeval(millis.unwrap_or(0));
             let timeout_ptr =
-                if let Some(millis) = millis {
-                    timeout.tv_usec = (millis * 1_000) as sys::suseconds_t;
+                if millis.is_some() {
                     &mut timeout as *mut _
                 } else {
                     ptr::null_mut()
@@ -459,8 +955,207 @@ impl AsRawFd for RawSocket {
     }
 }
 
+#[cfg(feature = "mio")]
+impl mio::event::Source for RawSocket {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        SourceFd(&self.fd).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        SourceFd(&self.fd).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.fd).deregister(registry)
+    }
+}
+
 impl Drop for RawSocket {
     fn drop(&mut self) {
         unsafe { sys::close(self.fd) };
     }
 }
+
+
+#[cfg(all(test, target_os = "linux", target_env = "gnu"))]
+mod tests {
+    use super::*;
+    use crate::interface::{self, Interface, Flags};
+    use crate::interface::link::LinkBuilder;
+    use crate::netns::scratch::Scratch;
+
+    // A broadcast with the local experimental EtherType, told apart from
+    // the router solicitations and reports a fresh link sends.
+    #[cfg(any(feature = "mio", feature = "tokio"))]
+    pub(super) fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x88, 0xb5];
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[cfg(any(feature = "mio", feature = "tokio"))]
+    pub(super) fn is_frame(frame: &[u8], payload: &[u8]) -> bool {
+        frame.len() >= 14 && frame[12..14] == [0x88, 0xb5] && &frame[14..] == payload
+    }
+
+    // A veth pair inside `netns`, with a socket on each end.
+    #[cfg(any(feature = "mio", feature = "tokio"))]
+    pub(super) fn pair(netns: &Scratch, name: &'static str, peer: &'static str) -> (RawSocket, RawSocket) {
+        netns.run(move || LinkBuilder::veth(name, peer).up().create().map(|_| ())).unwrap().unwrap();
+        netns.ip(&["link", "set", peer, "up"]);
+        (RawSocket::with_ifname_in(name, Some(netns)).unwrap(), RawSocket::with_ifname_in(peer, Some(netns)).unwrap())
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn socket_in_netns() {
+        let netns = Scratch::new();
+        // Only exists inside the namespace.
+        netns.run(|| LinkBuilder::veth("rawns0", "rawns1").up().create().map(|_| ())).unwrap().unwrap();
+        assert!(Interface::with_name("rawns0").is_err());
+
+        let mut socket = RawSocket::with_ifname_in("rawns0", Some(&netns)).unwrap();
+        assert_eq!(socket.link_layer(), LinkLayer::Eth);
+        assert!(!socket.is_promiscuous_mode().unwrap());
+
+        socket.enable_promiscuous_mode().unwrap();
+        assert!(socket.is_promiscuous_mode().unwrap());
+        // Socket memberships do not show up in IFF_PROMISC.
+        let iface = netns.run(|| Interface::with_name("rawns0")).unwrap().unwrap();
+        assert!(!iface.flags().contains(Flags::IFF_PROMISC));
+
+        socket.disable_promiscuous_mode().unwrap();
+        assert!(!socket.is_promiscuous_mode().unwrap());
+
+        socket.enable_allmulti_mode().unwrap();
+        socket.join_multicast(EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb])).unwrap();
+        socket.leave_multicast(EthernetAddress([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb])).unwrap();
+        socket.disable_allmulti_mode().unwrap();
+
+        let iface = interface::interfaces_in(Some(&netns)).unwrap()
+                                                          .into_iter()
+                                                          .find(|iface| iface.name() == "rawns0")
+                                                          .unwrap();
+        assert!(iface.netns() == Some(&*netns));
+        assert_eq!(iface.kind(), interface::InterfaceKind::Veth);
+        assert_eq!(iface.hwtype().unwrap(), sys::ARPHRD_ETHER);
+        // The peer, resolved in the namespace as well.
+        assert!(iface.link_info().unwrap().link.is_some());
+        assert_eq!(iface.tunnel_params().unwrap(), None);
+        assert!(!iface.is_tun() && !iface.is_tap());
+        assert_eq!(iface.stats().unwrap().rx_errors, 0);
+    }
+
+    #[test]
+    fn link_layer_from_hwtype() {
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_ETHER), Some(LinkLayer::Eth));
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_LOOPBACK), Some(LinkLayer::Eth));
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_NONE), Some(LinkLayer::Headerless));
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_IPGRE), Some(LinkLayer::Gre));
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_SIT), Some(LinkLayer::Sit));
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_TUNNEL6), Some(LinkLayer::Ip6Tnl));
+        assert_eq!(LinkLayer::from_hwtype(sys::ARPHRD_IEEE80211_RADIOTAP), Some(LinkLayer::Ieee80211Radiotap));
+        // ARPHRD_ATM.
+        assert_eq!(LinkLayer::from_hwtype(19), None);
+        assert!(LinkLayer::from_hwtype(sys::ARPHRD_NONE).unwrap().is_ip());
+        assert!(!LinkLayer::from_hwtype(sys::ARPHRD_ETHER).unwrap().is_ip());
+    }
+
+    #[test]
+    fn hwtype_name_too_long() {
+        let err = sys::if_name_to_hwtype("0123456789abcdef").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = RawSocket::with_ifname("0123456789abcdefghij").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn sll2_header_roundtrip() {
+        let sll = sys::sockaddr_ll {
+            sll_family:   sys::AF_PACKET as u16,
+            sll_protocol: 0x0800u16.to_be(),
+            sll_ifindex:  7,
+            sll_hatype:   sys::ARPHRD_ETHER,
+            sll_pkttype:  4, // PACKET_OUTGOING
+            sll_halen:    6,
+            sll_addr:     [0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00],
+        };
+        let mut header = [0xffu8; SLL2_HDR_LEN];
+        write_sll2_header(&sll, &mut header);
+        // The layout on the wire, everything in network byte order.
+        assert_eq!(&header[..], &[0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x01, 0x04, 0x06,
+                                  0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00][..]);
+
+        let read = read_sll2_header(&header);
+        assert_eq!(read.sll_family, sys::AF_PACKET as u16);
+        assert_eq!(u16::from_be(read.sll_protocol), 0x0800);
+        assert_eq!(read.sll_ifindex, 7);
+        assert_eq!(read.sll_hatype, sys::ARPHRD_ETHER);
+        assert_eq!(read.sll_pkttype, 4);
+        assert_eq!(read.sll_halen, 6);
+        assert_eq!(read.sll_addr, sll.sll_addr);
+    }
+
+    #[test]
+    fn timeval_split() {
+        let timeval = millis_to_timeval(1_500);
+        assert_eq!((timeval.tv_sec, timeval.tv_usec), (1, 500_000));
+        let timeval = millis_to_timeval(999);
+        assert_eq!((timeval.tv_sec, timeval.tv_usec), (0, 999_000));
+        let timeval = millis_to_timeval(60_000);
+        assert_eq!((timeval.tv_sec, timeval.tv_usec), (60, 0));
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn wait_over_a_second() {
+        let netns = Scratch::new();
+        // The peer stays down, nothing arrives.
+        netns.run(|| LinkBuilder::veth("rawwt0", "rawwt1").up().create().map(|_| ())).unwrap().unwrap();
+        let socket = RawSocket::with_ifname_in("rawwt0", Some(&netns)).unwrap();
+
+        let start = std::time::Instant::now();
+        socket.wait(Some(1_500)).unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(1_500));
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn mio_source() {
+        use mio::{Events, Interest, Poll, Token};
+        use std::time::Duration;
+
+        let netns = Scratch::new();
+        let (mut socket, mut peer) = pair(&netns, "rawmio0", "rawmio1");
+
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(8);
+        poll.registry().register(&mut socket, Token(7), Interest::READABLE).unwrap();
+        peer.send(&frame(b"mio")).unwrap();
+
+        let mut buffer = vec![0u8; socket.blen()];
+        let mut received = false;
+        while !received {
+            poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+            assert!(!events.is_empty(), "timed out");
+            for event in events.iter() {
+                assert_eq!(event.token(), Token(7));
+                assert!(event.is_readable());
+            }
+            // Edge triggered, read until it would block.
+            loop {
+                match socket.recv(&mut buffer) {
+                    Ok(len) => received |= is_frame(&buffer[..len], b"mio"),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+
+        poll.registry().deregister(&mut socket).unwrap();
+        peer.send(&frame(b"mio")).unwrap();
+        poll.poll(&mut events, Some(Duration::from_millis(200))).unwrap();
+        assert!(events.is_empty());
+    }
+}
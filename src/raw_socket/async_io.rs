@@ -0,0 +1,163 @@
+use super::{BufferReader, RawSocket};
+
+use futures_core::Stream;
+use tokio::io::unix::AsyncFd;
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::collections::VecDeque;
+
+
+/// A `RawSocket` driven by the tokio reactor.
+pub struct AsyncRawSocket {
+    inner: AsyncFd<RawSocket>,
+}
+
+impl AsyncRawSocket {
+    /// Must be called from within a tokio runtime.
+    pub fn new(raw_socket: RawSocket) -> Result<AsyncRawSocket, io::Error> {
+        Ok(AsyncRawSocket { inner: AsyncFd::new(raw_socket)? })
+    }
+
+    pub fn with_ifname(ifname: &str) -> Result<AsyncRawSocket, io::Error> {
+        AsyncRawSocket::new(RawSocket::with_ifname(ifname)?)
+    }
+
+    pub fn get_ref(&self) -> &RawSocket {
+        self.inner.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut RawSocket {
+        self.inner.get_mut()
+    }
+
+    pub fn into_inner(self) -> RawSocket {
+        self.inner.into_inner()
+    }
+
+    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        loop {
+            let mut guard = self.inner.readable_mut().await?;
+            match guard.try_io(|inner| inner.get_mut().recv(buf)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    pub async fn send(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        loop {
+            let mut guard = self.inner.writable_mut().await?;
+            match guard.try_io(|inner| inner.get_mut().send(buf)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// A stream of captured frames, one link layer frame per item.
+    pub fn frames(&mut self) -> Frames<'_> {
+        let blen = self.get_ref().blen();
+        Frames { socket: self, buffer: vec![0u8; blen], pending: VecDeque::new() }
+    }
+}
+
+pub struct Frames<'a> {
+    socket: &'a mut AsyncRawSocket,
+    buffer: Vec<u8>,
+    // A BPF read may carry several frames.
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl<'a> Stream for Frames<'a> {
+    type Item = Result<Vec<u8>, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(frame) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+
+            let mut guard = match this.socket.inner.poll_read_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let buffer = &mut this.buffer;
+            match guard.try_io(|inner| inner.get_mut().recv(buffer)) {
+                Ok(Ok(len)) => {
+                    for (start, end) in BufferReader::new(buffer, len) {
+                        this.pending.push_back(buffer[start..end].to_vec());
+                    }
+                },
+                Ok(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+
+#[cfg(all(test, target_os = "linux", target_env = "gnu"))]
+mod tests {
+    use super::*;
+    use super::super::tests::{frame, is_frame, pair};
+    use crate::netns::scratch::Scratch;
+
+    use std::future::Future;
+    use std::time::Duration;
+
+    // `StreamExt::next` without pulling in futures-util.
+    struct Next<'a, S>(&'a mut S);
+
+    impl<'a, S: Stream + Unpin> Future for Next<'a, S> {
+        type Output = Option<S::Item>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            Pin::new(&mut *self.0).poll_next(cx)
+        }
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn recv_send_frames() {
+        let netns = Scratch::new();
+        let (socket, peer) = pair(&netns, "rawtk0", "rawtk1");
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut socket = AsyncRawSocket::new(socket).unwrap();
+            let mut peer = AsyncRawSocket::new(peer).unwrap();
+
+            let sent = frame(b"recv");
+            assert_eq!(peer.send(&sent).await.unwrap(), sent.len());
+            let mut buffer = vec![0u8; socket.get_ref().blen()];
+            let recv = async {
+                loop {
+                    let len = socket.recv(&mut buffer).await.unwrap();
+                    if is_frame(&buffer[..len], b"recv") {
+                        break;
+                    }
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), recv).await.expect("timed out");
+
+            peer.send(&frame(b"stream 1")).await.unwrap();
+            peer.send(&frame(b"stream 2")).await.unwrap();
+            let mut frames = socket.frames();
+            let mut payloads: Vec<&[u8]> = vec![b"stream 1", b"stream 2"];
+            let stream = async {
+                while !payloads.is_empty() {
+                    let frame = Next(&mut frames).await.unwrap().unwrap();
+                    if is_frame(&frame, payloads[0]) {
+                        payloads.remove(0);
+                    }
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(5), stream).await.expect("timed out");
+        });
+    }
+}
@@ -0,0 +1,473 @@
+// Routes over rtnetlink, in the main table or any other one.
+use crate::sys;
+use crate::netlink::{self, Attrs, MessageBuilder, NetlinkSocket};
+use crate::netns::{self, NetNs};
+use crate::interface::AddrScope;
+
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv6Address};
+
+use std::io;
+use std::fmt;
+use std::mem;
+
+
+/// A routing table, `ip route ... table <table>`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Table {
+    Default,
+    Main,
+    Local,
+    Id(u32),
+}
+
+impl Table {
+    pub fn from_u32(id: u32) -> Table {
+        match id {
+            253 => Table::Default,
+            254 => Table::Main,
+            255 => Table::Local,
+            _ => Table::Id(id),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match *self {
+            Table::Default => sys::RT_TABLE_DEFAULT as u32,
+            Table::Main => sys::RT_TABLE_MAIN as u32,
+            Table::Local => sys::RT_TABLE_LOCAL as u32,
+            Table::Id(id) => id,
+        }
+    }
+
+    // The header only has room for ids below 256, larger ones go in an attribute.
+    pub(super) fn header_id(&self) -> u8 {
+        let id = self.id();
+        if id < 256 { id as u8 } else { sys::RT_TABLE_UNSPEC }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Table::Default => write!(f, "default"),
+            Table::Main => write!(f, "main"),
+            Table::Local => write!(f, "local"),
+            Table::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RouteType {
+    Unicast,
+    Local,
+    Broadcast,
+    Anycast,
+    Multicast,
+    Blackhole,
+    Unreachable,
+    Prohibit,
+    // Stop the lookup in this table and continue with the next rule.
+    Throw,
+    Other(u8),
+}
+
+impl RouteType {
+    pub fn from_u8(kind: u8) -> RouteType {
+        match kind {
+            sys::RTN_UNICAST => RouteType::Unicast,
+            sys::RTN_LOCAL => RouteType::Local,
+            sys::RTN_BROADCAST => RouteType::Broadcast,
+            sys::RTN_ANYCAST => RouteType::Anycast,
+            sys::RTN_MULTICAST => RouteType::Multicast,
+            sys::RTN_BLACKHOLE => RouteType::Blackhole,
+            sys::RTN_UNREACHABLE => RouteType::Unreachable,
+            sys::RTN_PROHIBIT => RouteType::Prohibit,
+            sys::RTN_THROW => RouteType::Throw,
+            _ => RouteType::Other(kind),
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            RouteType::Unicast => sys::RTN_UNICAST,
+            RouteType::Local => sys::RTN_LOCAL,
+            RouteType::Broadcast => sys::RTN_BROADCAST,
+            RouteType::Anycast => sys::RTN_ANYCAST,
+            RouteType::Multicast => sys::RTN_MULTICAST,
+            RouteType::Blackhole => sys::RTN_BLACKHOLE,
+            RouteType::Unreachable => sys::RTN_UNREACHABLE,
+            RouteType::Prohibit => sys::RTN_PROHIBIT,
+            RouteType::Throw => sys::RTN_THROW,
+            RouteType::Other(kind) => kind,
+        }
+    }
+}
+
+impl fmt::Display for RouteType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RouteType::Unicast => write!(f, "unicast"),
+            RouteType::Local => write!(f, "local"),
+            RouteType::Broadcast => write!(f, "broadcast"),
+            RouteType::Anycast => write!(f, "anycast"),
+            RouteType::Multicast => write!(f, "multicast"),
+            RouteType::Blackhole => write!(f, "blackhole"),
+            RouteType::Unreachable => write!(f, "unreachable"),
+            RouteType::Prohibit => write!(f, "prohibit"),
+            RouteType::Throw => write!(f, "throw"),
+            RouteType::Other(kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+
+/// A route, `ip route` style.
+///
+/// ```no_run
+/// use znet::route::{self, Route, Table};
+/// use smoltcp::wire::{IpAddress, IpCidr};
+///
+/// let default = IpCidr::new(IpAddress::v4(0, 0, 0, 0), 0);
+/// route::add(&Route::new(default).ifindex(5).table(Table::Id(51820))).unwrap();
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Route {
+    pub dest: IpCidr,
+    pub gateway: Option<IpAddress>,
+    // The output interface.
+    pub ifindex: Option<u32>,
+    // The preferred source address, `src` in `ip route`.
+    pub source: Option<IpAddress>,
+    pub table: Table,
+    pub kind: RouteType,
+    // `None` lets `add` pick like `ip route` does: link scope for routes
+    // without a gateway, global otherwise.
+    pub scope: Option<AddrScope>,
+    // `metric` in `ip route`.
+    pub metric: Option<u32>,
+}
+
+impl Route {
+    pub fn new(dest: IpCidr) -> Route {
+        Route {
+            dest,
+            gateway: None,
+            ifindex: None,
+            source: None,
+            table: Table::Main,
+            kind: RouteType::Unicast,
+            scope: None,
+            metric: None,
+        }
+    }
+
+    pub fn gateway(mut self, gateway: IpAddress) -> Route {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    pub fn ifindex(mut self, ifindex: u32) -> Route {
+        self.ifindex = Some(ifindex);
+        self
+    }
+
+    pub fn source(mut self, source: IpAddress) -> Route {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn table(mut self, table: Table) -> Route {
+        self.table = table;
+        self
+    }
+
+    pub fn kind(mut self, kind: RouteType) -> Route {
+        self.kind = kind;
+        self
+    }
+
+    pub fn scope(mut self, scope: AddrScope) -> Route {
+        self.scope = Some(scope);
+        self
+    }
+
+    pub fn metric(mut self, metric: u32) -> Route {
+        self.metric = Some(metric);
+        self
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.dest.prefix_len() == 0
+    }
+
+    fn message(&self, kind: u16, flags: u16) -> Vec<u8> {
+        let scope = match self.scope {
+            Some(scope) => scope.as_u8(),
+            // Whatever scope the route has.
+            None if kind == sys::netlink::RTM_DELROUTE => sys::RT_SCOPE_NOWHERE,
+            None => match self.kind {
+                RouteType::Local => sys::RT_SCOPE_HOST,
+                RouteType::Broadcast | RouteType::Multicast | RouteType::Anycast => sys::RT_SCOPE_LINK,
+                RouteType::Unicast if self.gateway.is_none() => sys::RT_SCOPE_LINK,
+                _ => sys::RT_SCOPE_UNIVERSE,
+            },
+        };
+        let rtmsg = sys::netlink::rtmsg {
+            rtm_family: family(&self.dest.address()),
+            rtm_dst_len: self.dest.prefix_len(),
+            rtm_table: self.table.header_id(),
+            rtm_protocol: if kind == sys::netlink::RTM_NEWROUTE { sys::RTPROT_BOOT } else { 0 },
+            rtm_scope: scope,
+            rtm_type: self.kind.as_u8(),
+            ..Default::default()
+        };
+
+        let mut msg = MessageBuilder::new(kind, flags).header(&rtmsg);
+        if self.dest.prefix_len() > 0 {
+            msg = msg.attr(sys::RTA_DST, self.dest.address().as_bytes());
+        }
+        if let Some(gateway) = self.gateway {
+            msg = msg.attr(sys::RTA_GATEWAY, gateway.as_bytes());
+        }
+        if let Some(ifindex) = self.ifindex {
+            msg = msg.attr_u32(sys::RTA_OIF, ifindex);
+        }
+        if let Some(source) = self.source {
+            msg = msg.attr(sys::RTA_PREFSRC, source.as_bytes());
+        }
+        if let Some(metric) = self.metric {
+            msg = msg.attr_u32(sys::RTA_PRIORITY, metric);
+        }
+
+        msg.attr_u32(sys::RTA_TABLE, self.table.id()).build()
+    }
+}
+
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.kind != RouteType::Unicast {
+            write!(f, "{} ", self.kind)?;
+        }
+        if self.is_default() {
+            write!(f, "default")?;
+        } else {
+            write!(f, "{}", self.dest)?;
+        }
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {}", gateway)?;
+        }
+        if let Some(ifindex) = self.ifindex {
+            write!(f, " dev #{}", ifindex)?;
+        }
+        write!(f, " table {}", self.table)?;
+        if let Some(scope) = self.scope {
+            if scope != AddrScope::Global {
+                write!(f, " scope {}", scope)?;
+            }
+        }
+        if let Some(source) = self.source {
+            write!(f, " src {}", source)?;
+        }
+        if let Some(metric) = self.metric {
+            write!(f, " metric {}", metric)?;
+        }
+        Ok(())
+    }
+}
+
+
+pub(super) fn family(addr: &IpAddress) -> u8 {
+    match *addr {
+        IpAddress::Ipv6(_) => sys::AF_INET6 as u8,
+        _ => sys::AF_INET as u8,
+    }
+}
+
+pub(super) fn ip_address(family: u8, value: &[u8]) -> Option<IpAddress> {
+    match family as sys::c_int {
+        sys::AF_INET if value.len() == 4 => Some(IpAddress::Ipv4(Ipv4Address::from_bytes(value))),
+        sys::AF_INET6 if value.len() == 16 => Some(IpAddress::Ipv6(Ipv6Address::from_bytes(value))),
+        _ => None,
+    }
+}
+
+pub(super) fn unspecified(family: u8) -> Option<IpAddress> {
+    match family as sys::c_int {
+        sys::AF_INET => Some(IpAddress::Ipv4(Ipv4Address::UNSPECIFIED)),
+        sys::AF_INET6 => Some(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED)),
+        _ => None,
+    }
+}
+
+fn parse(payload: &[u8]) -> Option<Route> {
+    let rtmsg: sys::netlink::rtmsg = netlink::read(payload)?;
+    let offset = netlink::align(mem::size_of::<sys::netlink::rtmsg>());
+    if payload.len() < offset {
+        return None;
+    }
+
+    let mut route = Route::new(IpCidr::new(unspecified(rtmsg.rtm_family)?, 0));
+    route.table = Table::from_u32(rtmsg.rtm_table as u32);
+    route.kind = RouteType::from_u8(rtmsg.rtm_type);
+    route.scope = Some(AddrScope::from_u8(rtmsg.rtm_scope));
+    for (kind, value) in Attrs::new(&payload[offset..]) {
+        match kind {
+            sys::RTA_DST => {
+                let dest = ip_address(rtmsg.rtm_family, value)?;
+                route.dest = IpCidr::new(dest, rtmsg.rtm_dst_len);
+            },
+            sys::RTA_GATEWAY => route.gateway = ip_address(rtmsg.rtm_family, value),
+            sys::RTA_OIF => route.ifindex = netlink::attr_u32(value),
+            sys::RTA_PREFSRC => route.source = ip_address(rtmsg.rtm_family, value),
+            sys::RTA_PRIORITY => route.metric = netlink::attr_u32(value),
+            sys::RTA_TABLE => {
+                if let Some(id) = netlink::attr_u32(value) {
+                    route.table = Table::from_u32(id);
+                }
+            },
+            _ => { },
+        }
+    }
+
+    Some(route)
+}
+
+fn request(msg: Vec<u8>) -> Result<Vec<netlink::Message>, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+    socket.request(msg)
+}
+
+pub fn add(route: &Route) -> Result<(), io::Error> {
+    let flags = (sys::NLM_F_CREATE | sys::NLM_F_EXCL) as u16;
+    request(route.message(sys::netlink::RTM_NEWROUTE, flags)).map(|_| ())
+}
+
+/// Add the route, or replace the one with the same destination, table and metric.
+pub fn replace(route: &Route) -> Result<(), io::Error> {
+    let flags = (sys::NLM_F_CREATE | sys::NLM_F_REPLACE) as u16;
+    request(route.message(sys::netlink::RTM_NEWROUTE, flags)).map(|_| ())
+}
+
+pub fn remove(route: &Route) -> Result<(), io::Error> {
+    request(route.message(sys::netlink::RTM_DELROUTE, 0)).map(|_| ())
+}
+
+/// The IPv4 and IPv6 routes of a table, or of all tables.
+pub fn list(table: Option<Table>) -> Result<Vec<Route>, io::Error> {
+    let rtmsg = sys::netlink::rtmsg { rtm_family: sys::AF_UNSPEC as u8, ..Default::default() };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETROUTE, sys::NLM_F_DUMP as u16)
+        .header(&rtmsg)
+        .build();
+
+    Ok(request(msg)?
+        .iter()
+        .filter(|reply| reply.kind == sys::netlink::RTM_NEWROUTE)
+        .filter_map(|reply| parse(&reply.payload))
+        .filter(|route| table.map(|table| table == route.table).unwrap_or(true))
+        .collect())
+}
+
+pub fn add_in(netns: Option<&NetNs>, route: &Route) -> Result<(), io::Error> {
+    netns::run_in(netns, || add(route))?
+}
+
+pub fn remove_in(netns: Option<&NetNs>, route: &Route) -> Result<(), io::Error> {
+    netns::run_in(netns, || remove(route))?
+}
+
+pub fn list_in(netns: Option<&NetNs>, table: Option<Table>) -> Result<Vec<Route>, io::Error> {
+    netns::run_in(netns, || list(table))?
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netns::scratch::Scratch;
+
+    #[test]
+    fn table_ids() {
+        assert_eq!(Table::from_u32(254), Table::Main);
+        assert_eq!(Table::from_u32(100), Table::Id(100));
+        assert_eq!(Table::Local.id(), 255);
+        assert_eq!(Table::Id(100).header_id(), 100);
+        assert_eq!(Table::Id(51820).header_id(), sys::RT_TABLE_UNSPEC);
+        assert_eq!(Table::Id(51820).to_string(), "51820");
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let route = Route::new(IpCidr::new(IpAddress::v4(10, 1, 0, 0), 16))
+            .gateway(IpAddress::v4(10, 0, 0, 1))
+            .ifindex(3)
+            .source(IpAddress::v4(10, 0, 0, 2))
+            .metric(100)
+            .scope(AddrScope::Global);
+        // Skip the nlmsghdr.
+        let msg = route.message(sys::netlink::RTM_NEWROUTE, 0);
+        assert_eq!(msg[16 + 4], sys::RT_TABLE_MAIN);
+        assert_eq!(parse(&msg[16..]), Some(route));
+
+        // Only RTA_TABLE holds an id above 255.
+        let route = Route::new(IpCidr::new(IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 0), 0))
+            .ifindex(5)
+            .table(Table::Id(51820))
+            .kind(RouteType::Blackhole)
+            .scope(AddrScope::Global);
+        let msg = route.message(sys::netlink::RTM_NEWROUTE, 0);
+        assert_eq!(msg[16 + 4], sys::RT_TABLE_UNSPEC);
+        assert_eq!(parse(&msg[16..]), Some(route));
+    }
+
+    #[test]
+    fn message_scope() {
+        let link = Route::new(IpCidr::new(IpAddress::v4(10, 1, 0, 0), 16)).ifindex(3);
+        let via = link.clone().gateway(IpAddress::v4(10, 0, 0, 1));
+        let scope = |route: &Route, kind| route.message(kind, 0)[16 + 6];
+        assert_eq!(scope(&link, sys::netlink::RTM_NEWROUTE), sys::RT_SCOPE_LINK);
+        assert_eq!(scope(&via, sys::netlink::RTM_NEWROUTE), sys::RT_SCOPE_UNIVERSE);
+        assert_eq!(scope(&link, sys::netlink::RTM_DELROUTE), sys::RT_SCOPE_NOWHERE);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        let msg = Route::new(IpCidr::new(IpAddress::v4(10, 1, 0, 0), 16)).message(sys::netlink::RTM_NEWROUTE, 0);
+        assert_eq!(parse(&msg[16..20]), None);
+        // AF_UNSPEC
+        let mut payload = msg[16..].to_vec();
+        payload[0] = 0;
+        assert_eq!(parse(&payload), None);
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn add_list_remove() {
+        let netns = Scratch::new();
+        let routes = [
+            Route::new(IpCidr::new(IpAddress::v4(10, 86, 0, 0), 24)).ifindex(1).table(Table::Id(100)),
+            Route::new(IpCidr::new(IpAddress::v6(0xfd00, 0x86, 0, 0, 0, 0, 0, 0), 64)).ifindex(1).table(Table::Id(51820)),
+        ];
+
+        for route in &routes {
+            add_in(Some(&netns), route).unwrap();
+            // Already there.
+            assert!(add_in(Some(&netns), route).is_err());
+        }
+
+        for route in &routes {
+            let listed = list_in(Some(&netns), Some(route.table)).unwrap();
+            assert_eq!(listed.len(), 1, "{:?}", listed);
+            assert_eq!(listed[0].dest, route.dest);
+            assert_eq!(listed[0].ifindex, Some(1));
+            assert_eq!(listed[0].table, route.table);
+            assert!(list_in(Some(&netns), Some(Table::Main)).unwrap().iter().all(|main| main.dest != route.dest));
+        }
+
+        for route in &routes {
+            remove_in(Some(&netns), route).unwrap();
+            assert!(list_in(Some(&netns), Some(route.table)).unwrap().is_empty());
+        }
+    }
+}
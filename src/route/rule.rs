@@ -0,0 +1,502 @@
+//! Policy routing rules, the `ip rule` list that picks the table a packet is routed with.
+//!
+//! What wg-quick sets up for a full tunnel:
+//!
+//! ```no_run
+//! use znet::route::Table;
+//! use znet::route::rule::{self, Rule};
+//! use smoltcp::wire::IpVersion;
+//!
+//! // Everything not marked by the tunnel socket goes to the tunnel table ...
+//! rule::add(&Rule::lookup(IpVersion::Ipv4, Table::Id(51820)).fwmark(51820).invert()).unwrap();
+//! // ... unless the main table has a more specific route than the default one.
+//! rule::add(&Rule::lookup(IpVersion::Ipv4, Table::Main).suppress_prefixlength(0)).unwrap();
+//! ```
+use super::Table;
+use super::linux::{family, ip_address};
+
+use crate::sys;
+use crate::netlink::{self, Attrs, MessageBuilder, NetlinkSocket};
+use crate::netns::{self, NetNs};
+
+use smoltcp::wire::{IpCidr, IpVersion};
+
+use std::io;
+use std::fmt;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RuleAction {
+    Lookup(Table),
+    // Continue with the rule of this priority.
+    Goto(u32),
+    Nop,
+    Blackhole,
+    Unreachable,
+    Prohibit,
+}
+
+impl fmt::Display for RuleAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuleAction::Lookup(table) => write!(f, "lookup {}", table),
+            RuleAction::Goto(priority) => write!(f, "goto {}", priority),
+            RuleAction::Nop => write!(f, "nop"),
+            RuleAction::Blackhole => write!(f, "blackhole"),
+            RuleAction::Unreachable => write!(f, "unreachable"),
+            RuleAction::Prohibit => write!(f, "prohibit"),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Rule {
+    pub version: IpVersion,
+    // Rules are tried from the lowest priority up, the kernel picks one when it is `None`.
+    pub priority: Option<u32>,
+    pub from: Option<IpCidr>,
+    pub to: Option<IpCidr>,
+    pub fwmark: Option<u32>,
+    pub fwmask: Option<u32>,
+    // Incoming and outgoing interface names.
+    pub iif: Option<String>,
+    pub oif: Option<String>,
+    pub uid_range: Option<(u32, u32)>,
+    // Ignore routes found in the table whose prefix is this long or shorter,
+    // 0 skips the default route.
+    pub suppress_prefixlength: Option<u32>,
+    // `not`, the rule applies when the selectors do not match.
+    pub invert: bool,
+    pub action: RuleAction,
+}
+
+impl Rule {
+    pub fn new(version: IpVersion, action: RuleAction) -> Rule {
+        Rule {
+            version,
+            priority: None,
+            from: None,
+            to: None,
+            fwmark: None,
+            fwmask: None,
+            iif: None,
+            oif: None,
+            uid_range: None,
+            suppress_prefixlength: None,
+            invert: false,
+            action,
+        }
+    }
+
+    pub fn lookup(version: IpVersion, table: Table) -> Rule {
+        Rule::new(version, RuleAction::Lookup(table))
+    }
+
+    pub fn priority(mut self, priority: u32) -> Rule {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn from(mut self, from: IpCidr) -> Rule {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn to(mut self, to: IpCidr) -> Rule {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn fwmark(mut self, fwmark: u32) -> Rule {
+        self.fwmark = Some(fwmark);
+        self
+    }
+
+    pub fn fwmask(mut self, fwmask: u32) -> Rule {
+        self.fwmask = Some(fwmask);
+        self
+    }
+
+    pub fn iif(mut self, ifname: &str) -> Rule {
+        self.iif = Some(ifname.to_string());
+        self
+    }
+
+    pub fn oif(mut self, ifname: &str) -> Rule {
+        self.oif = Some(ifname.to_string());
+        self
+    }
+
+    /// Sockets owned by users `start..=end`.
+    pub fn uid_range(mut self, start: u32, end: u32) -> Rule {
+        self.uid_range = Some((start, end));
+        self
+    }
+
+    pub fn suppress_prefixlength(mut self, prefix_len: u32) -> Rule {
+        self.suppress_prefixlength = Some(prefix_len);
+        self
+    }
+
+    pub fn invert(mut self) -> Rule {
+        self.invert = true;
+        self
+    }
+
+    fn message(&self, kind: u16, flags: u16) -> Vec<u8> {
+        let family = match self.version {
+            IpVersion::Ipv6 => sys::AF_INET6 as u8,
+            IpVersion::Ipv4 => sys::AF_INET as u8,
+            _ => self.from.or(self.to).map(|cidr| family(&cidr.address())).unwrap_or(sys::AF_UNSPEC as u8),
+        };
+        let (action, table) = match self.action {
+            RuleAction::Lookup(table) => (sys::netlink::FR_ACT_TO_TBL, Some(table)),
+            RuleAction::Goto(_) => (sys::netlink::FR_ACT_GOTO, None),
+            RuleAction::Nop => (sys::netlink::FR_ACT_NOP, None),
+            RuleAction::Blackhole => (sys::netlink::FR_ACT_BLACKHOLE, None),
+            RuleAction::Unreachable => (sys::netlink::FR_ACT_UNREACHABLE, None),
+            RuleAction::Prohibit => (sys::netlink::FR_ACT_PROHIBIT, None),
+        };
+        let hdr = sys::netlink::fib_rule_hdr {
+            family,
+            dst_len: self.to.map(|cidr| cidr.prefix_len()).unwrap_or(0),
+            src_len: self.from.map(|cidr| cidr.prefix_len()).unwrap_or(0),
+            table: table.map(|table| table.header_id()).unwrap_or(sys::RT_TABLE_UNSPEC),
+            action,
+            flags: if self.invert { sys::netlink::FIB_RULE_INVERT } else { 0 },
+            ..Default::default()
+        };
+
+        let mut msg = MessageBuilder::new(kind, flags).header(&hdr);
+        if let Some(priority) = self.priority {
+            msg = msg.attr_u32(sys::netlink::FRA_PRIORITY, priority);
+        }
+        if let Some(from) = self.from {
+            msg = msg.attr(sys::netlink::FRA_SRC, from.address().as_bytes());
+        }
+        if let Some(to) = self.to {
+            msg = msg.attr(sys::netlink::FRA_DST, to.address().as_bytes());
+        }
+        if let Some(fwmark) = self.fwmark {
+            msg = msg.attr_u32(sys::netlink::FRA_FWMARK, fwmark);
+        }
+        if let Some(fwmask) = self.fwmask {
+            msg = msg.attr_u32(sys::netlink::FRA_FWMASK, fwmask);
+        }
+        if let Some(ref iif) = self.iif {
+            msg = msg.attr_str(sys::netlink::FRA_IIFNAME, iif);
+        }
+        if let Some(ref oif) = self.oif {
+            msg = msg.attr_str(sys::netlink::FRA_OIFNAME, oif);
+        }
+        if let Some((start, end)) = self.uid_range {
+            let range = sys::netlink::fib_rule_uid_range { start, end };
+            msg = msg.attr(sys::netlink::FRA_UID_RANGE, netlink::as_bytes(&range));
+        }
+        if let Some(prefix_len) = self.suppress_prefixlength {
+            msg = msg.attr_u32(sys::netlink::FRA_SUPPRESS_PREFIXLEN, prefix_len);
+        }
+        if let RuleAction::Goto(target) = self.action {
+            msg = msg.attr_u32(sys::netlink::FRA_GOTO, target);
+        }
+        if let Some(table) = table {
+            msg = msg.attr_u32(sys::netlink::FRA_TABLE, table.id());
+        }
+
+        msg.build()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(priority) = self.priority {
+            write!(f, "{}:\t", priority)?;
+        }
+        if self.invert {
+            write!(f, "not ")?;
+        }
+        match self.from {
+            Some(from) => write!(f, "from {}", from)?,
+            None => write!(f, "from all")?,
+        }
+        if let Some(to) = self.to {
+            write!(f, " to {}", to)?;
+        }
+        if let Some(fwmark) = self.fwmark {
+            write!(f, " fwmark {:#x}", fwmark)?;
+            match self.fwmask {
+                Some(fwmask) if fwmask != u32::MAX => write!(f, "/{:#x}", fwmask)?,
+                _ => { },
+            }
+        }
+        if let Some(ref iif) = self.iif {
+            write!(f, " iif {}", iif)?;
+        }
+        if let Some(ref oif) = self.oif {
+            write!(f, " oif {}", oif)?;
+        }
+        if let Some((start, end)) = self.uid_range {
+            write!(f, " uidrange {}-{}", start, end)?;
+        }
+        write!(f, " {}", self.action)?;
+        if let Some(prefix_len) = self.suppress_prefixlength {
+            write!(f, " suppress_prefixlength {}", prefix_len)?;
+        }
+        Ok(())
+    }
+}
+
+
+fn parse(payload: &[u8]) -> Option<Rule> {
+    let hdr: sys::netlink::fib_rule_hdr = netlink::read(payload)?;
+    let offset = netlink::align(mem::size_of::<sys::netlink::fib_rule_hdr>());
+    if payload.len() < offset {
+        return None;
+    }
+
+    let version = match hdr.family as sys::c_int {
+        sys::AF_INET => IpVersion::Ipv4,
+        sys::AF_INET6 => IpVersion::Ipv6,
+        _ => return None,
+    };
+    let mut table = Table::from_u32(hdr.table as u32);
+    let mut goto = 0;
+    let mut rule = Rule::new(version, RuleAction::Nop);
+    // FRA_PRIORITY is left out for priority 0.
+    rule.priority = Some(0);
+    rule.invert = hdr.flags & sys::netlink::FIB_RULE_INVERT != 0;
+    for (kind, value) in Attrs::new(&payload[offset..]) {
+        match kind {
+            sys::netlink::FRA_PRIORITY => rule.priority = netlink::attr_u32(value),
+            sys::netlink::FRA_SRC => {
+                rule.from = ip_address(hdr.family, value).map(|addr| IpCidr::new(addr, hdr.src_len));
+            },
+            sys::netlink::FRA_DST => {
+                rule.to = ip_address(hdr.family, value).map(|addr| IpCidr::new(addr, hdr.dst_len));
+            },
+            sys::netlink::FRA_FWMARK => rule.fwmark = netlink::attr_u32(value),
+            sys::netlink::FRA_FWMASK => rule.fwmask = netlink::attr_u32(value),
+            sys::netlink::FRA_IIFNAME => rule.iif = netlink::attr_str(value),
+            sys::netlink::FRA_OIFNAME => rule.oif = netlink::attr_str(value),
+            sys::netlink::FRA_UID_RANGE => {
+                rule.uid_range = netlink::read::<sys::netlink::fib_rule_uid_range>(value)
+                    .map(|range| (range.start, range.end));
+            },
+            sys::netlink::FRA_SUPPRESS_PREFIXLEN => {
+                // Reported as -1 when unset.
+                rule.suppress_prefixlength = netlink::attr_u32(value).filter(|len| *len != u32::MAX);
+            },
+            sys::netlink::FRA_TABLE => {
+                if let Some(id) = netlink::attr_u32(value) {
+                    table = Table::from_u32(id);
+                }
+            },
+            sys::netlink::FRA_GOTO => goto = netlink::attr_u32(value).unwrap_or(0),
+            _ => { },
+        }
+    }
+
+    rule.action = match hdr.action {
+        sys::netlink::FR_ACT_TO_TBL => RuleAction::Lookup(table),
+        sys::netlink::FR_ACT_GOTO => RuleAction::Goto(goto),
+        sys::netlink::FR_ACT_BLACKHOLE => RuleAction::Blackhole,
+        sys::netlink::FR_ACT_UNREACHABLE => RuleAction::Unreachable,
+        sys::netlink::FR_ACT_PROHIBIT => RuleAction::Prohibit,
+        _ => RuleAction::Nop,
+    };
+
+    Some(rule)
+}
+
+fn request(msg: Vec<u8>) -> Result<Vec<netlink::Message>, io::Error> {
+    let mut socket = NetlinkSocket::new(sys::NETLINK_ROUTE)?;
+    socket.request(msg)
+}
+
+pub fn add(rule: &Rule) -> Result<(), io::Error> {
+    let flags = (sys::NLM_F_CREATE | sys::NLM_F_EXCL) as u16;
+    request(rule.message(sys::netlink::RTM_NEWRULE, flags)).map(|_| ())
+}
+
+/// Remove the first rule matching every selector that is set.
+pub fn remove(rule: &Rule) -> Result<(), io::Error> {
+    request(rule.message(sys::netlink::RTM_DELRULE, 0)).map(|_| ())
+}
+
+/// The IPv4 and IPv6 rules, by priority.
+pub fn list() -> Result<Vec<Rule>, io::Error> {
+    let hdr = sys::netlink::fib_rule_hdr { family: sys::AF_UNSPEC as u8, ..Default::default() };
+    let msg = MessageBuilder::new(sys::netlink::RTM_GETRULE, sys::NLM_F_DUMP as u16)
+        .header(&hdr)
+        .build();
+
+    let mut rules = request(msg)?
+        .iter()
+        .filter(|reply| reply.kind == sys::netlink::RTM_NEWRULE)
+        .filter_map(|reply| parse(&reply.payload))
+        .collect::<Vec<Rule>>();
+    rules.sort_by_key(|rule| rule.priority.unwrap_or(0));
+
+    Ok(rules)
+}
+
+pub fn add_in(netns: Option<&NetNs>, rule: &Rule) -> Result<(), io::Error> {
+    netns::run_in(netns, || add(rule))?
+}
+
+pub fn remove_in(netns: Option<&NetNs>, rule: &Rule) -> Result<(), io::Error> {
+    netns::run_in(netns, || remove(rule))?
+}
+
+pub fn list_in(netns: Option<&NetNs>) -> Result<Vec<Rule>, io::Error> {
+    netns::run_in(netns, list)?
+}
+
+
+/// Set SO_MARK on a socket, `fwmark` rules then see its packets. Needs CAP_NET_ADMIN.
+pub fn set_mark<S: AsRawFd>(socket: &S, mark: u32) -> Result<(), io::Error> {
+    let ret = unsafe {
+        sys::setsockopt(socket.as_raw_fd(), sys::SOL_SOCKET, sys::SO_MARK,
+                        &mark as *const u32 as *const sys::c_void,
+                        mem::size_of::<u32>() as sys::socklen_t)
+    };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn mark<S: AsRawFd>(socket: &S) -> Result<u32, io::Error> {
+    let mut mark: u32 = 0;
+    let mut len = mem::size_of::<u32>() as sys::socklen_t;
+    let ret = unsafe {
+        sys::getsockopt(socket.as_raw_fd(), sys::SOL_SOCKET, sys::SO_MARK,
+                        &mut mark as *mut u32 as *mut sys::c_void, &mut len)
+    };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(mark)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use smoltcp::wire::IpAddress;
+
+    // RTM_NEWRULE payloads from `ip rule show`.
+    // 0:	from all lookup local
+    const RULE_LOCAL: &[u8] = &[
+        0x02, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00,
+        0xff, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xff, 0xff, 0xff, 0xff, 0x05, 0x00, 0x15, 0x00,
+        0x02, 0x00, 0x00, 0x00,
+    ];
+
+    // 1000:	from 10.1.0.0/16 to 10.2.0.0/16 fwmark 0x10/0xff iif v0 lookup 100
+    const RULE_SELECTORS: &[u8] = &[
+        0x02, 0x10, 0x10, 0x00, 0x64, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00,
+        0x64, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xff, 0xff, 0xff, 0xff, 0x05, 0x00, 0x15, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x07, 0x00, 0x03, 0x00, 0x76, 0x30, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00,
+        0xe8, 0x03, 0x00, 0x00, 0x08, 0x00, 0x0a, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x10, 0x00,
+        0xff, 0x00, 0x00, 0x00, 0x08, 0x00, 0x01, 0x00, 0x0a, 0x02, 0x00, 0x00, 0x08, 0x00, 0x02, 0x00,
+        0x0a, 0x01, 0x00, 0x00,
+    ];
+
+    // 1500:	from all goto 3000
+    const RULE_GOTO: &[u8] = &[
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xff, 0xff, 0xff, 0xff, 0x05, 0x00, 0x15, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00, 0xdc, 0x05, 0x00, 0x00, 0x08, 0x00, 0x04, 0x00,
+        0xb8, 0x0b, 0x00, 0x00,
+    ];
+
+    // 3000:	from all prohibit
+    const RULE_PROHIBIT: &[u8] = &[
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0xff, 0xff, 0xff, 0xff, 0x05, 0x00, 0x15, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00, 0xb8, 0x0b, 0x00, 0x00,
+    ];
+
+    // 2000:	not from all to fd00::/8 uidrange 1000-2000 lookup main suppress_prefixlength 0
+    const RULE_V6_INVERT: &[u8] = &[
+        0x0a, 0x08, 0x00, 0x00, 0xfe, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00,
+        0xfe, 0x00, 0x00, 0x00, 0x08, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x15, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x06, 0x00, 0xd0, 0x07, 0x00, 0x00, 0x0c, 0x00, 0x14, 0x00,
+        0xe8, 0x03, 0x00, 0x00, 0xd0, 0x07, 0x00, 0x00, 0x14, 0x00, 0x01, 0x00, 0xfd, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn parse_default_rule() {
+        let rule = parse(RULE_LOCAL).unwrap();
+        // No FRA_PRIORITY and a suppress_prefixlength of -1.
+        assert_eq!(rule, Rule::lookup(IpVersion::Ipv4, Table::Local).priority(0));
+    }
+
+    #[test]
+    fn parse_selectors() {
+        let rule = parse(RULE_SELECTORS).unwrap();
+        let expected = Rule::lookup(IpVersion::Ipv4, Table::Id(100))
+            .priority(1000)
+            .from(IpCidr::new(IpAddress::v4(10, 1, 0, 0), 16))
+            .to(IpCidr::new(IpAddress::v4(10, 2, 0, 0), 16))
+            .fwmark(0x10)
+            .fwmask(0xff)
+            .iif("v0");
+        assert_eq!(rule, expected);
+        assert_eq!(rule.to_string(), "1000:\tfrom 10.1.0.0/16 to 10.2.0.0/16 fwmark 0x10/0xff iif v0 lookup 100");
+    }
+
+    #[test]
+    fn parse_actions() {
+        let rule = parse(RULE_GOTO).unwrap();
+        assert_eq!(rule, Rule::new(IpVersion::Ipv4, RuleAction::Goto(3000)).priority(1500));
+
+        let rule = parse(RULE_PROHIBIT).unwrap();
+        assert_eq!(rule, Rule::new(IpVersion::Ipv4, RuleAction::Prohibit).priority(3000));
+    }
+
+    #[test]
+    fn parse_ipv6_invert() {
+        let rule = parse(RULE_V6_INVERT).unwrap();
+        let expected = Rule::lookup(IpVersion::Ipv6, Table::Main)
+            .priority(2000)
+            .to(IpCidr::new(IpAddress::v6(0xfd00, 0, 0, 0, 0, 0, 0, 0), 8))
+            .uid_range(1000, 2000)
+            .suppress_prefixlength(0)
+            .invert();
+        assert_eq!(rule, expected);
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert_eq!(parse(&RULE_LOCAL[..8]), None);
+        // AF_UNSPEC
+        let mut payload = RULE_LOCAL.to_vec();
+        payload[0] = 0;
+        assert_eq!(parse(&payload), None);
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let rule = Rule::lookup(IpVersion::Ipv4, Table::Id(51820))
+            .priority(32764)
+            .fwmark(51820)
+            .oif("wg0")
+            .invert();
+        // Skip the nlmsghdr.
+        let msg = rule.message(sys::netlink::RTM_NEWRULE, 0);
+        assert_eq!(parse(&msg[16..]), Some(rule));
+
+        let rule = Rule::new(IpVersion::Ipv6, RuleAction::Goto(100)).priority(10);
+        let msg = rule.message(sys::netlink::RTM_NEWRULE, 0);
+        assert_eq!(parse(&msg[16..]), Some(rule));
+    }
+}
@@ -22,6 +22,16 @@ pub const BIOCSRTIMEOUT: libc::c_ulong = 0x8010426d;
 pub const BIOCGSEESENT: libc::c_ulong = 0x40044276;
 pub const BIOCSSEESENT: libc::c_ulong = 0x80044277;
 
+pub const BIOCPROMISC: libc::c_ulong = 0x20004269;
+pub const BIOCGSTATS: libc::c_ulong = 0x4008426f;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct bpf_stat {
+    pub bs_recv: libc::c_uint,      // number of packets received
+    pub bs_drop: libc::c_uint,      // number of packets dropped
+}
+
 
 cfg_if! {
     if #[cfg(all(target_os = "macos", target_pointer_width = "32"))] {
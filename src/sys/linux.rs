@@ -1,4 +1,3 @@
-use libc;
 
 use crate::sys;
 
@@ -14,15 +13,6 @@ type FLAG_TYPE = libc::c_ulong;
 type FLAG_TYPE = libc::c_int;
 
 
-pub const CTL_NET: libc::c_int  = 3;        // Networking
-    
-pub const NET_IPV4: libc::c_int = 5;
-pub const NET_IPV6: libc::c_int = 12;
-    
-pub const NET_IPV4_FORWARD: libc::c_int    = 8;
-pub const NET_IPV6_FORWARDING: libc::c_int = 1;
-
-
 // https://github.com/torvalds/linux/blob/master/include/uapi/linux/sockios.h
 pub const SIOCGIFADDR: FLAG_TYPE = 0x8915;
 
@@ -36,8 +26,28 @@ pub const SIOCSIFMETRIC: FLAG_TYPE = 0x0000891e;
 pub const SIOCGIFINDEX: FLAG_TYPE = 0x8933;
 
 pub const TUNSETIFF:    FLAG_TYPE = 0x400454CA;
-pub const PACKET_ADD_MEMBERSHIP: FLAG_TYPE = 1;
-pub const PACKET_MR_PROMISC: FLAG_TYPE = 1;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_arp.h
+pub const ARPHRD_RAWIP: u16  = 519;
+pub const ARPHRD_IP6GRE: u16 = 823;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_packet.h
+pub const PACKET_STATISTICS: libc::c_int = 6;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct tpacket_stats {
+    pub tp_packets: libc::c_uint,
+    pub tp_drops:   libc::c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct tpacket_stats_v3 {
+    pub tp_packets: libc::c_uint,
+    pub tp_drops:   libc::c_uint,
+    pub tp_freeze_q_cnt: libc::c_uint,
+}
 
 // route flags
 // https://github.com/torvalds/linux/blob/master/include/uapi/linux/route.h
@@ -155,17 +165,20 @@ pub fn if_name_to_flags(ifname: &str) -> Result<i32, io::Error> {
     }
     
     let mut req: ifreq = unsafe { mem::zeroed() };
-    unsafe {
+    let ret = unsafe {
         ptr::copy_nonoverlapping(ifname.as_ptr() as *const sys::c_char,
                                  req.ifr_name.as_mut_ptr(),
                                  ifname.len());
-        let ret = sys::ioctl(fd, sys::SIOCGIFFLAGS as _, &req);
-        if ret == -1 {
-            return Err(io::Error::last_os_error());
-        }
-    }
+        sys::ioctl(fd, sys::SIOCGIFFLAGS as _, &req)
+    };
+
+    unsafe { libc::close(fd) };
 
-    Ok(req.ifr_flags as i32)
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(req.ifr_flags as i32)
+    }
 }
 
 pub fn if_index_to_name(ifindex: u32) -> String {
@@ -187,4 +200,32 @@ pub fn if_index_to_name(ifindex: u32) -> String {
         }
         str::from_utf8(&ifname_buf[..pos]).unwrap().to_string()
     }
-}
\ No newline at end of file
+}
+
+// ARPHRD_* of the device in the namespace of the calling thread.
+pub fn if_name_to_hwtype(ifname: &str) -> Result<u16, io::Error> {
+    // Leave room for the terminating NUL of `ifr_name`.
+    if ifname.len() >= sys::IFNAMSIZ {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("interface name too long: {}", ifname)));
+    }
+
+    let fd = unsafe { sys::socket(sys::AF_INET, sys::SOCK_DGRAM, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut req: ifreq = unsafe { mem::zeroed() };
+    for (i, byte) in ifname.bytes().enumerate() {
+        req.ifr_name[i] = byte as libc::c_char;
+    }
+
+    let ret = unsafe { sys::ioctl(fd, SIOCGIFHWADDR as _, &mut req) };
+    let err = io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+
+    if ret == -1 {
+        return Err(err);
+    }
+
+    Ok(unsafe { req.ifru.hwaddr.sa_family })
+}
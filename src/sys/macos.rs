@@ -207,6 +207,37 @@ pub fn if_name_to_mtu(name: &str) -> Result<usize, io::Error> {
     }
 }
 
+pub fn if_name_to_flags(ifname: &str) -> Result<i32, io::Error> {
+    #[repr(C)]
+    struct ifreq {
+        ifr_name: [sys::c_char; sys::IF_NAMESIZE],
+        ifr_flags: sys::c_short,
+    }
+
+    let fd = unsafe { sys::socket(sys::AF_INET, sys::SOCK_DGRAM, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut ifreq = ifreq {
+        ifr_name: [0; sys::IF_NAMESIZE],
+        ifr_flags: 0
+    };
+    for (i, byte) in ifname.as_bytes().iter().enumerate() {
+        ifreq.ifr_name[i] = *byte as sys::c_char
+    }
+
+    let ret = unsafe { sys::ioctl(fd, sys::SIOCGIFFLAGS, &mut ifreq as *mut ifreq) };
+
+    unsafe { libc::close(fd) };
+
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ifreq.ifr_flags as i32)
+    }
+}
+
 pub fn if_index_to_name(ifindex: u32) -> String {
     let ifname_buf: [u8; libc::IF_NAMESIZE] = [0u8; libc::IF_NAMESIZE];
     unsafe {
@@ -228,3 +259,38 @@ pub fn if_index_to_name(ifindex: u32) -> String {
     }
 }
 
+// Counters handed out by getifaddrs(3) in `ifa_data` of the AF_LINK entries (net/if_var.h).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct if_data {
+    pub ifi_type:       libc::c_uchar, // ethernet, tokenring, etc
+    pub ifi_typelen:    libc::c_uchar, // Length of frame type id
+    pub ifi_physical:   libc::c_uchar, // e.g., AUI, Thinnet, 10base-T, etc
+    pub ifi_addrlen:    libc::c_uchar, // media address length
+    pub ifi_hdrlen:     libc::c_uchar, // media header length
+    pub ifi_recvquota:  libc::c_uchar, // polling quota for receive intrs
+    pub ifi_xmitquota:  libc::c_uchar, // polling quota for xmit intrs
+    pub ifi_unused1:    libc::c_uchar, // for future use
+    pub ifi_mtu:        u32,           // maximum transmission unit
+    pub ifi_metric:     u32,           // routing metric (external only)
+    pub ifi_baudrate:   u32,           // linespeed
+    pub ifi_ipackets:   u32,           // packets received on interface
+    pub ifi_ierrors:    u32,           // input errors on interface
+    pub ifi_opackets:   u32,           // packets sent on interface
+    pub ifi_oerrors:    u32,           // output errors on interface
+    pub ifi_collisions: u32,           // collisions on csma interfaces
+    pub ifi_ibytes:     u32,           // total number of octets received
+    pub ifi_obytes:     u32,           // total number of octets sent
+    pub ifi_imcasts:    u32,           // packets received via multicast
+    pub ifi_omcasts:    u32,           // packets sent via multicast
+    pub ifi_iqdrops:    u32,           // dropped on input, this interface
+    pub ifi_noproto:    u32,           // destined for unsupported protocol
+    pub ifi_recvtiming: u32,           // usec spent receiving when timing
+    pub ifi_xmittiming: u32,           // usec spent xmitting when timing
+    pub ifi_lastchange: [i32; 2],      // time of last administrative change
+    pub ifi_unused2:    u32,           // used to be the default_proto
+    pub ifi_hwassist:   u32,           // HW offload capabilities
+    pub ifi_reserved1:  u32,           // for future use
+    pub ifi_reserved2:  u32,           // for future use
+}
+
@@ -7,6 +7,10 @@ pub use libc::*;
 #[path = "./linux.rs"]
 mod platform;
 
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[path = "./netlink.rs"]
+pub mod netlink;
+
 
 #[cfg(any(target_os = "macos", target_os = "freebsd"))]
 #[path = "./bpf.rs"]
@@ -0,0 +1,313 @@
+// rtnetlink(7) declarations libc does not carry (or only carries in some versions).
+// They stay in their own namespace (`sys::netlink::*`) so they never clash with the `libc::*` glob.
+use libc;
+
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/rtnetlink.h
+pub const RTM_NEWLINK: u16  = 16;
+pub const RTM_DELLINK: u16  = 17;
+pub const RTM_GETLINK: u16  = 18;
+pub const RTM_SETLINK: u16  = 19;
+pub const RTM_NEWADDR: u16  = 20;
+pub const RTM_DELADDR: u16  = 21;
+pub const RTM_GETADDR: u16  = 22;
+pub const RTM_NEWROUTE: u16 = 24;
+pub const RTM_DELROUTE: u16 = 25;
+pub const RTM_GETROUTE: u16 = 26;
+pub const RTM_NEWRULE: u16  = 32;
+pub const RTM_DELRULE: u16  = 33;
+pub const RTM_GETRULE: u16  = 34;
+
+// Multicast groups, for NETLINK_ADD_MEMBERSHIP.
+pub const RTNLGRP_LINK: u32        = 1;
+pub const RTNLGRP_IPV4_IFADDR: u32 = 5;
+pub const RTNLGRP_IPV6_IFADDR: u32 = 9;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_addr.h
+pub const IFA_ADDRESS: u16   = 1;
+pub const IFA_LOCAL: u16     = 2;
+pub const IFA_LABEL: u16     = 3;
+pub const IFA_BROADCAST: u16 = 4;
+pub const IFA_CACHEINFO: u16 = 6;
+pub const IFA_FLAGS: u16     = 8;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if.h
+pub const IFF_LOWER_UP: u32 = 0x10000;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_link.h
+pub const IFLA_ADDRESS: u16      = 1;
+pub const IFLA_BROADCAST: u16    = 2;
+pub const IFLA_IFNAME: u16       = 3;
+pub const IFLA_MTU: u16          = 4;
+pub const IFLA_LINK: u16         = 5;
+pub const IFLA_QDISC: u16        = 6;
+pub const IFLA_STATS: u16        = 7;
+pub const IFLA_MASTER: u16       = 10;
+pub const IFLA_WIRELESS: u16     = 11;
+pub const IFLA_TXQLEN: u16       = 13;
+pub const IFLA_OPERSTATE: u16    = 16;
+pub const IFLA_LINKMODE: u16     = 17;
+pub const IFLA_LINKINFO: u16     = 18;
+pub const IFLA_NET_NS_PID: u16   = 19;
+pub const IFLA_IFALIAS: u16      = 20;
+pub const IFLA_STATS64: u16      = 23;
+pub const IFLA_NET_NS_FD: u16    = 28;
+pub const IFLA_PROMISCUITY: u16  = 30;
+pub const IFLA_CARRIER: u16      = 33;
+pub const IFLA_LINK_NETNSID: u16 = 37;
+pub const IFLA_PERM_ADDRESS: u16 = 54;
+
+pub const IFLA_INFO_KIND: u16       = 1;
+pub const IFLA_INFO_DATA: u16       = 2;
+pub const IFLA_INFO_SLAVE_KIND: u16 = 4;
+
+pub const IFLA_VLAN_ID: u16 = 1;
+
+pub const IFLA_TUN_TYPE: u16 = 3;
+
+pub const VETH_INFO_PEER: u16 = 1;
+
+pub const IFLA_MACVLAN_MODE: u16     = 1;
+pub const MACVLAN_MODE_PRIVATE: u32  = 1;
+pub const MACVLAN_MODE_VEPA: u32     = 2;
+pub const MACVLAN_MODE_BRIDGE: u32   = 4;
+pub const MACVLAN_MODE_PASSTHRU: u32 = 8;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/if_tunnel.h
+pub const IFLA_GRE_LINK: u16   = 1;
+pub const IFLA_GRE_IFLAGS: u16 = 2;
+pub const IFLA_GRE_OFLAGS: u16 = 3;
+pub const IFLA_GRE_IKEY: u16   = 4;
+pub const IFLA_GRE_OKEY: u16   = 5;
+pub const IFLA_GRE_LOCAL: u16  = 6;
+pub const IFLA_GRE_REMOTE: u16 = 7;
+pub const IFLA_GRE_TTL: u16    = 8;
+pub const GRE_KEY: u16         = 0x2000;
+
+pub const IFLA_IPTUN_LINK: u16   = 1;
+pub const IFLA_IPTUN_LOCAL: u16  = 2;
+pub const IFLA_IPTUN_REMOTE: u16 = 3;
+pub const IFLA_IPTUN_TTL: u16    = 4;
+pub const IFLA_IPTUN_PROTO: u16  = 9;
+
+pub const IFLA_VXLAN_ID: u16     = 1;
+pub const IFLA_VXLAN_GROUP: u16  = 2;
+pub const IFLA_VXLAN_LINK: u16   = 3;
+pub const IFLA_VXLAN_LOCAL: u16  = 4;
+pub const IFLA_VXLAN_TTL: u16    = 5;
+pub const IFLA_VXLAN_PORT: u16   = 15;
+pub const IFLA_VXLAN_GROUP6: u16 = 16;
+pub const IFLA_VXLAN_LOCAL6: u16 = 17;
+
+pub const IFLA_IPVLAN_MODE: u16 = 1;
+pub const IPVLAN_MODE_L2: u16   = 0;
+pub const IPVLAN_MODE_L3: u16   = 1;
+pub const IPVLAN_MODE_L3S: u16  = 2;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/fib_rules.h
+pub const FRA_DST: u16                = 1;
+pub const FRA_SRC: u16                = 2;
+pub const FRA_IIFNAME: u16            = 3;
+pub const FRA_GOTO: u16               = 4;
+pub const FRA_PRIORITY: u16           = 6;
+pub const FRA_FWMARK: u16             = 10;
+pub const FRA_SUPPRESS_PREFIXLEN: u16 = 14;
+pub const FRA_TABLE: u16              = 15;
+pub const FRA_FWMASK: u16             = 16;
+pub const FRA_OIFNAME: u16            = 17;
+pub const FRA_UID_RANGE: u16          = 20;
+
+pub const FR_ACT_TO_TBL: u8      = 1;
+pub const FR_ACT_GOTO: u8        = 2;
+pub const FR_ACT_NOP: u8         = 3;
+pub const FR_ACT_BLACKHOLE: u8   = 6;
+pub const FR_ACT_UNREACHABLE: u8 = 7;
+pub const FR_ACT_PROHIBIT: u8    = 8;
+
+pub const FIB_RULE_INVERT: u32 = 0x02;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/netfilter/nf_tables.h
+pub const NFTA_TABLE_NAME: u16 = 1;
+
+pub const NFTA_CHAIN_TABLE: u16  = 1;
+pub const NFTA_CHAIN_NAME: u16   = 3;
+pub const NFTA_CHAIN_HOOK: u16   = 4;
+pub const NFTA_CHAIN_POLICY: u16 = 5;
+pub const NFTA_CHAIN_TYPE: u16   = 7;
+
+pub const NFTA_HOOK_HOOKNUM: u16  = 1;
+pub const NFTA_HOOK_PRIORITY: u16 = 2;
+
+pub const NFTA_RULE_TABLE: u16       = 1;
+pub const NFTA_RULE_CHAIN: u16       = 2;
+pub const NFTA_RULE_EXPRESSIONS: u16 = 4;
+
+pub const NFTA_LIST_ELEM: u16 = 1;
+
+pub const NFTA_EXPR_NAME: u16 = 1;
+pub const NFTA_EXPR_DATA: u16 = 2;
+
+pub const NFTA_DATA_VALUE: u16   = 1;
+pub const NFTA_DATA_VERDICT: u16 = 2;
+
+pub const NFTA_VERDICT_CODE: u16 = 1;
+
+pub const NFTA_IMMEDIATE_DREG: u16 = 1;
+pub const NFTA_IMMEDIATE_DATA: u16 = 2;
+
+pub const NFTA_META_DREG: u16 = 1;
+pub const NFTA_META_KEY: u16  = 2;
+
+pub const NFTA_CMP_SREG: u16 = 1;
+pub const NFTA_CMP_OP: u16   = 2;
+pub const NFTA_CMP_DATA: u16 = 3;
+
+pub const NFTA_PAYLOAD_DREG: u16   = 1;
+pub const NFTA_PAYLOAD_BASE: u16   = 2;
+pub const NFTA_PAYLOAD_OFFSET: u16 = 3;
+pub const NFTA_PAYLOAD_LEN: u16    = 4;
+
+pub const NFTA_BITWISE_SREG: u16 = 1;
+pub const NFTA_BITWISE_DREG: u16 = 2;
+pub const NFTA_BITWISE_LEN: u16  = 3;
+pub const NFTA_BITWISE_MASK: u16 = 4;
+pub const NFTA_BITWISE_XOR: u16  = 5;
+
+pub const NFTA_CT_DREG: u16 = 1;
+pub const NFTA_CT_KEY: u16  = 2;
+
+pub const NFTA_NAT_TYPE: u16           = 1;
+pub const NFTA_NAT_FAMILY: u16         = 2;
+pub const NFTA_NAT_REG_ADDR_MIN: u16   = 3;
+pub const NFTA_NAT_REG_PROTO_MIN: u16  = 5;
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/netfilter/nf_conntrack_common.h
+pub const NF_CT_STATE_INVALID: u32     = 0x01;
+pub const NF_CT_STATE_ESTABLISHED: u32 = 0x02;
+pub const NF_CT_STATE_RELATED: u32     = 0x04;
+pub const NF_CT_STATE_NEW: u32         = 0x08;
+
+// RFC 2863 operational status, IFLA_OPERSTATE.
+pub const IF_OPER_UNKNOWN: u8        = 0;
+pub const IF_OPER_NOTPRESENT: u8     = 1;
+pub const IF_OPER_DOWN: u8           = 2;
+pub const IF_OPER_LOWERLAYERDOWN: u8 = 3;
+pub const IF_OPER_TESTING: u8        = 4;
+pub const IF_OPER_DORMANT: u8        = 5;
+pub const IF_OPER_UP: u8             = 6;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ifinfomsg {
+    pub ifi_family: libc::c_uchar,
+    pub __ifi_pad:  libc::c_uchar,
+    pub ifi_type:   libc::c_ushort,   // ARPHRD_*
+    pub ifi_index:  libc::c_int,      // Link index
+    pub ifi_flags:  libc::c_uint,     // IFF_* flags
+    pub ifi_change: libc::c_uint,     // IFF_* change mask
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ifaddrmsg {
+    pub ifa_family:    u8,
+    pub ifa_prefixlen: u8,            // The prefix length
+    pub ifa_flags:     u8,            // Flags
+    pub ifa_scope:     u8,            // Address scope
+    pub ifa_index:     u32,           // Link index
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ifa_cacheinfo {
+    pub ifa_prefered: u32,            // seconds, all ones for forever
+    pub ifa_valid:    u32,
+    pub cstamp:       u32,            // created timestamp, hundredths of seconds
+    pub tstamp:       u32,            // updated timestamp, hundredths of seconds
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct rtmsg {
+    pub rtm_family:   u8,
+    pub rtm_dst_len:  u8,
+    pub rtm_src_len:  u8,
+    pub rtm_tos:      u8,
+    pub rtm_table:    u8,             // Routing table id, RTA_TABLE for ids above 255
+    pub rtm_protocol: u8,             // Routing protocol, RTPROT_*
+    pub rtm_scope:    u8,             // RT_SCOPE_*
+    pub rtm_type:     u8,             // RTN_*
+    pub rtm_flags:    u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct fib_rule_hdr {
+    pub family:  u8,
+    pub dst_len: u8,
+    pub src_len: u8,
+    pub tos:     u8,
+    pub table:   u8,
+    pub res1:    u8,
+    pub res2:    u8,
+    pub action:  u8,                  // FR_ACT_*
+    pub flags:   u32,                 // FIB_RULE_*
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct fib_rule_uid_range {
+    pub start: u32,
+    pub end:   u32,
+}
+
+// https://github.com/torvalds/linux/blob/master/include/uapi/linux/netfilter/nfnetlink.h
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct nfgenmsg {
+    pub nfgen_family: u8,             // NFPROTO_*
+    pub version:      u8,             // NFNETLINK_V0
+    pub res_id:       u16,            // Resource id, big endian
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct rtattr {
+    pub rta_len:  libc::c_ushort,
+    pub rta_type: libc::c_ushort,
+}
+
+// The main device statistics structure.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct rtnl_link_stats64 {
+    pub rx_packets: u64,              // total packets received
+    pub tx_packets: u64,              // total packets transmitted
+    pub rx_bytes:   u64,              // total bytes received
+    pub tx_bytes:   u64,              // total bytes transmitted
+    pub rx_errors:  u64,              // bad packets received
+    pub tx_errors:  u64,              // packet transmit problems
+    pub rx_dropped: u64,              // no space in linux buffers
+    pub tx_dropped: u64,              // no space available in linux
+    pub multicast:  u64,              // multicast packets received
+    pub collisions: u64,
+
+    // detailed rx_errors
+    pub rx_length_errors: u64,
+    pub rx_over_errors:   u64,        // receiver ring buff overflow
+    pub rx_crc_errors:    u64,        // recved pkt with crc error
+    pub rx_frame_errors:  u64,        // recv'd frame alignment error
+    pub rx_fifo_errors:   u64,        // recv'r fifo overrun
+    pub rx_missed_errors: u64,        // receiver missed packet
+
+    // detailed tx_errors
+    pub tx_aborted_errors:   u64,
+    pub tx_carrier_errors:   u64,
+    pub tx_fifo_errors:      u64,
+    pub tx_heartbeat_errors: u64,
+    pub tx_window_errors:    u64,
+
+    // for cslip etc
+    pub rx_compressed: u64,
+    pub tx_compressed: u64,
+}
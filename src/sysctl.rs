@@ -0,0 +1,356 @@
+//! Typed access to the net sysctls a VPN or gateway touches.
+//!
+//! Linux reads and writes `/proc/sys/net`, macOS goes through sysctlbyname(3)
+//! and only knows the global forwarding and router advertisement knobs.
+//!
+//! ```no_run
+//! use znet::sysctl;
+//!
+//! let guard = sysctl::enable_forwarding().unwrap();
+//! // ... route traffic ...
+//! drop(guard); // forwarding is back to what it was
+//! ```
+use std::io;
+use std::fmt;
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use std::fs;
+        use std::path::PathBuf;
+    } else if #[cfg(target_os = "macos")] {
+        use crate::sys;
+
+        use std::ptr;
+        use std::mem;
+        use std::ffi::CString;
+    }
+}
+
+
+/// `all`, `default` (inherited by new interfaces) or a single interface.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Conf {
+    All,
+    Default,
+    Interface(String),
+}
+
+impl fmt::Display for Conf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Conf::All => write!(f, "all"),
+            Conf::Default => write!(f, "default"),
+            Conf::Interface(ref ifname) => write!(f, "{}", ifname),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RpFilter {
+    Off,
+    Strict,
+    Loose,
+}
+
+impl RpFilter {
+    pub fn from_u32(value: u32) -> Option<RpFilter> {
+        match value {
+            0 => Some(RpFilter::Off),
+            1 => Some(RpFilter::Strict),
+            2 => Some(RpFilter::Loose),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        match *self {
+            RpFilter::Off => 0,
+            RpFilter::Strict => 1,
+            RpFilter::Loose => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AcceptRa {
+    Never,
+    // Only while forwarding is disabled.
+    UnlessForwarding,
+    Always,
+}
+
+impl AcceptRa {
+    pub fn from_u32(value: u32) -> Option<AcceptRa> {
+        match value {
+            0 => Some(AcceptRa::Never),
+            1 => Some(AcceptRa::UnlessForwarding),
+            2 => Some(AcceptRa::Always),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        match *self {
+            AcceptRa::Never => 0,
+            AcceptRa::UnlessForwarding => 1,
+            AcceptRa::Always => 2,
+        }
+    }
+}
+
+
+/// A net sysctl, the value is an integer (a boolean for most of them).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Sysctl {
+    // net.ipv4.ip_forward
+    IpForward,
+    // net.ipv6.conf.<conf>.forwarding
+    Ipv6Forwarding(Conf),
+    // net.ipv4.conf.<conf>.rp_filter, see `RpFilter`.
+    RpFilter(Conf),
+    // net.ipv6.conf.<conf>.accept_ra, see `AcceptRa`.
+    AcceptRa(Conf),
+    // net.ipv6.conf.<conf>.disable_ipv6
+    DisableIpv6(Conf),
+    // net.ipv4.conf.<conf>.proxy_arp
+    ProxyArp(Conf),
+    // net.ipv4.conf.<conf>.src_valid_mark, take the fwmark into account for reverse path filtering.
+    SrcValidMark(Conf),
+}
+
+impl Sysctl {
+    // The name below `net.`, split in its components.
+    fn components(&self) -> Vec<String> {
+        let conf = |family: &str, conf: &Conf, name: &str| {
+            vec![family.to_string(), "conf".to_string(), conf.to_string(), name.to_string()]
+        };
+        match *self {
+            Sysctl::IpForward => vec!["ipv4".to_string(), "ip_forward".to_string()],
+            Sysctl::Ipv6Forwarding(ref c) => conf("ipv6", c, "forwarding"),
+            Sysctl::RpFilter(ref c) => conf("ipv4", c, "rp_filter"),
+            Sysctl::AcceptRa(ref c) => conf("ipv6", c, "accept_ra"),
+            Sysctl::DisableIpv6(ref c) => conf("ipv6", c, "disable_ipv6"),
+            Sysctl::ProxyArp(ref c) => conf("ipv4", c, "proxy_arp"),
+            Sysctl::SrcValidMark(ref c) => conf("ipv4", c, "src_valid_mark"),
+        }
+    }
+
+    pub fn get(&self) -> Result<u32, io::Error> {
+        read(self)
+    }
+
+    /// Write `value` and return the previous one.
+    pub fn set(&self, value: u32) -> Result<u32, io::Error> {
+        let previous = read(self)?;
+        if previous != value {
+            write(self, value)?;
+        }
+        Ok(previous)
+    }
+
+    pub fn is_enabled(&self) -> Result<bool, io::Error> {
+        self.get().map(|value| value != 0)
+    }
+}
+
+impl fmt::Display for Sysctl {
+    // Dots in interface names (VLANs) become slashes, like sysctl(8) prints them.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "net")?;
+        for component in self.components() {
+            write!(f, ".{}", component.replace('.', "/"))?;
+        }
+        Ok(())
+    }
+}
+
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        fn path(sysctl: &Sysctl) -> PathBuf {
+            let mut path = PathBuf::from("/proc/sys/net");
+            for component in sysctl.components() {
+                path.push(component);
+            }
+            path
+        }
+
+        fn read(sysctl: &Sysctl) -> Result<u32, io::Error> {
+            let value = fs::read_to_string(path(sysctl))?;
+            value.trim()
+                 .parse::<u32>()
+                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", sysctl, e)))
+        }
+
+        fn write(sysctl: &Sysctl, value: u32) -> Result<(), io::Error> {
+            fs::write(path(sysctl), value.to_string())
+        }
+    } else if #[cfg(target_os = "macos")] {
+        // The BSD stack only has global switches.
+        fn name(sysctl: &Sysctl) -> Result<CString, io::Error> {
+            let name = match *sysctl {
+                Sysctl::IpForward => "net.inet.ip.forwarding",
+                Sysctl::Ipv6Forwarding(Conf::All) => "net.inet6.ip6.forwarding",
+                Sysctl::AcceptRa(Conf::All) => "net.inet6.ip6.accept_rtadv",
+                _ => return Err(io::Error::new(io::ErrorKind::Other,
+                                               format!("{} is not supported on this platform", sysctl))),
+            };
+            Ok(CString::new(name).unwrap())
+        }
+
+        fn read(sysctl: &Sysctl) -> Result<u32, io::Error> {
+            let name = name(sysctl)?;
+            let mut value: sys::c_int = 0;
+            let mut len = mem::size_of::<sys::c_int>();
+            let ret = unsafe {
+                sys::sysctlbyname(name.as_ptr(), &mut value as *mut sys::c_int as *mut sys::c_void,
+                                  &mut len, ptr::null_mut(), 0)
+            };
+            if ret < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(value as u32)
+        }
+
+        fn write(sysctl: &Sysctl, value: u32) -> Result<(), io::Error> {
+            let name = name(sysctl)?;
+            let mut value = value as sys::c_int;
+            let ret = unsafe {
+                sys::sysctlbyname(name.as_ptr(), ptr::null_mut(), ptr::null_mut(),
+                                  &mut value as *mut sys::c_int as *mut sys::c_void,
+                                  mem::size_of::<sys::c_int>())
+            };
+            if ret < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+}
+
+
+/// Remembers the first value of every sysctl set through it and restores
+/// them, last first, when dropped.
+#[derive(Debug, Default)]
+pub struct SysctlGuard {
+    saved: Vec<(Sysctl, u32)>,
+}
+
+impl SysctlGuard {
+    pub fn new() -> SysctlGuard {
+        SysctlGuard { saved: Vec::new() }
+    }
+
+    pub fn set(&mut self, sysctl: Sysctl, value: u32) -> Result<(), io::Error> {
+        let previous = sysctl.set(value)?;
+        if !self.saved.iter().any(|(saved, _)| *saved == sysctl) {
+            self.saved.push((sysctl, previous));
+        }
+        Ok(())
+    }
+
+    /// Keep the current values, nothing is restored on drop.
+    pub fn forget(mut self) {
+        self.saved.clear();
+    }
+}
+
+impl Drop for SysctlGuard {
+    fn drop(&mut self) {
+        while let Some((sysctl, value)) = self.saved.pop() {
+            if let Err(e) = sysctl.set(value) {
+                error!("failed to restore {} to {}: {}", sysctl, value, e);
+            }
+        }
+    }
+}
+
+
+/// Turn on IPv4 and IPv6 forwarding until the guard is dropped.
+pub fn enable_forwarding() -> Result<SysctlGuard, io::Error> {
+    let mut guard = SysctlGuard::new();
+    guard.set(Sysctl::IpForward, 1)?;
+    guard.set(Sysctl::Ipv6Forwarding(Conf::All), 1)?;
+    Ok(guard)
+}
+
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::interface::link::LinkBuilder;
+    use crate::netns::scratch::Scratch;
+
+    #[test]
+    fn display() {
+        assert_eq!(Sysctl::IpForward.to_string(), "net.ipv4.ip_forward");
+        assert_eq!(Sysctl::Ipv6Forwarding(Conf::All).to_string(), "net.ipv6.conf.all.forwarding");
+        assert_eq!(Sysctl::AcceptRa(Conf::Default).to_string(), "net.ipv6.conf.default.accept_ra");
+        // The VLAN keeps its dot in the path.
+        let vlan = Sysctl::RpFilter(Conf::Interface("eth0.10".to_string()));
+        assert_eq!(vlan.to_string(), "net.ipv4.conf.eth0/10.rp_filter");
+        assert_eq!(path(&vlan), PathBuf::from("/proc/sys/net/ipv4/conf/eth0.10/rp_filter"));
+    }
+
+    #[test]
+    fn values() {
+        for value in 0..3 {
+            assert_eq!(RpFilter::from_u32(value).unwrap().as_u32(), value);
+            assert_eq!(AcceptRa::from_u32(value).unwrap().as_u32(), value);
+        }
+        assert_eq!(RpFilter::from_u32(3), None);
+        assert_eq!(AcceptRa::from_u32(3), None);
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn set_get() {
+        let netns = Scratch::new();
+        netns.run(|| {
+            LinkBuilder::veth("sys0.10", "sys1").create().unwrap();
+            let rp_filter = Sysctl::RpFilter(Conf::Interface("sys0.10".to_string()));
+
+            let previous = rp_filter.get().unwrap();
+            assert_eq!(rp_filter.set(RpFilter::Loose.as_u32()).unwrap(), previous);
+            assert_eq!(rp_filter.get().unwrap(), RpFilter::Loose.as_u32());
+            assert!(rp_filter.is_enabled().unwrap());
+
+            assert!(Sysctl::ProxyArp(Conf::Interface("nonexistent0".to_string())).get().is_err());
+        }).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs root for network namespaces"]
+    fn guard_restores() {
+        let netns = Scratch::new();
+        netns.run(|| {
+            LinkBuilder::veth("sys0.10", "sys1").create().unwrap();
+            let conf = Conf::Interface("sys0.10".to_string());
+            let all = Sysctl::Ipv6Forwarding(Conf::All);
+            let link = Sysctl::Ipv6Forwarding(conf.clone());
+            let rp_filter = Sysctl::RpFilter(conf);
+            assert_eq!(Sysctl::IpForward.get().unwrap(), 0);
+            assert_eq!(all.get().unwrap(), 0);
+            let rp = rp_filter.get().unwrap();
+
+            let mut guard = enable_forwarding().unwrap();
+            guard.set(rp_filter.clone(), RpFilter::Strict.as_u32()).unwrap();
+            guard.set(rp_filter.clone(), RpFilter::Loose.as_u32()).unwrap();
+            // Turning on `all` turned on the link already, this saves 1.
+            guard.set(link.clone(), 0).unwrap();
+            assert_eq!(Sysctl::IpForward.get().unwrap(), 1);
+            assert_eq!(rp_filter.get().unwrap(), RpFilter::Loose.as_u32());
+
+            // Restoring `all` last resets the link too, first would leave it on.
+            drop(guard);
+            assert_eq!(Sysctl::IpForward.get().unwrap(), 0);
+            assert_eq!(all.get().unwrap(), 0);
+            assert_eq!(link.get().unwrap(), 0);
+            assert_eq!(rp_filter.get().unwrap(), rp);
+
+            let guard = enable_forwarding().unwrap();
+            guard.forget();
+            assert_eq!(Sysctl::IpForward.get().unwrap(), 1);
+        }).unwrap();
+    }
+}